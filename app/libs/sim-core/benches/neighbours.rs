@@ -0,0 +1,150 @@
+//! Three neighbour passes, shaped like the density, pressure and viscosity
+//! passes, either walking the grid every pass or reading a neighbour list
+//! built by one walk, as `Sim2D` does each substep.
+
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rayon::prelude::*;
+use sim_core::{
+    layout::InitialLayout,
+    spatial::{SpatialIndex, SpatialIndexKind},
+    vector_2d::Vector2D,
+};
+
+const SMOOTHING_RADIUS: f32 = 0.35;
+
+// Same particle spacing as the default 5000 particle scene
+fn scene(particle_count: usize) -> (Vec<Vector2D>, Box<dyn SpatialIndex>) {
+    let scale = (particle_count as f32 / 5000.0).sqrt();
+    let world_dimensions = Vector2D::new(16.0 * scale, 9.0 * scale);
+    let positions = InitialLayout::Jitter {
+        amplitude: 0.5,
+        seed: 1,
+    }
+    .generate(particle_count, world_dimensions, 0.025)
+    .unwrap();
+
+    let mut index = SpatialIndexKind::FlatGrid.build();
+    index.rebuild(&positions, SMOOTHING_RADIUS, world_dimensions, false);
+    (positions, index)
+}
+
+fn for_each_in_radius(
+    index: &dyn SpatialIndex,
+    position: Vector2D,
+    mut f: impl FnMut(usize, Vector2D, f32),
+) {
+    let cell = (
+        (position.x / SMOOTHING_RADIUS).floor() as isize,
+        (position.y / SMOOTHING_RADIUS).floor() as isize,
+    );
+    for x in -1..=1 {
+        for y in -1..=1 {
+            let (indices, positions) = index.cell((cell.0 + x, cell.1 + y));
+            for (&neighbour_index, &neighbour_position) in indices.iter().zip(positions) {
+                let offset = neighbour_position - position;
+                let squared_distance = offset.dot(offset);
+                if squared_distance <= SMOOTHING_RADIUS * SMOOTHING_RADIUS {
+                    f(neighbour_index, offset, squared_distance.sqrt());
+                }
+            }
+        }
+    }
+}
+
+fn weight(distance: f32) -> f32 {
+    let q = SMOOTHING_RADIUS - distance;
+    q * q
+}
+
+// Walks the grid once per pass
+fn grid_walk(index: &dyn SpatialIndex, positions: &[Vector2D]) -> (Vec<f32>, Vec<Vector2D>) {
+    let densities: Vec<f32> = positions
+        .par_iter()
+        .map(|position| {
+            let mut density = 0.0;
+            for_each_in_radius(index, *position, |_, _, distance| {
+                density += weight(distance)
+            });
+            density
+        })
+        .collect();
+    let forces: Vec<Vector2D> = positions
+        .par_iter()
+        .map(|position| {
+            let mut force = Vector2D::new(0.0, 0.0);
+            for_each_in_radius(index, *position, |i, offset, distance| {
+                force += offset * weight(distance) / densities[i];
+            });
+            for_each_in_radius(index, *position, |i, _, distance| {
+                force += positions[i] * weight(distance);
+            });
+            force
+        })
+        .collect();
+    (densities, forces)
+}
+
+// Walks the grid once into a list the passes share
+fn neighbour_list(
+    index: &dyn SpatialIndex,
+    positions: &[Vector2D],
+    lists: &mut Vec<Vec<(usize, Vector2D, f32)>>,
+) -> (Vec<f32>, Vec<Vector2D>) {
+    lists.resize_with(positions.len(), Vec::new);
+    lists
+        .par_iter_mut()
+        .zip(positions.par_iter())
+        .for_each(|(list, position)| {
+            list.clear();
+            for_each_in_radius(index, *position, |i, offset, distance| {
+                list.push((i, offset, distance))
+            });
+        });
+
+    let densities: Vec<f32> = lists
+        .par_iter()
+        .map(|list| list.iter().map(|&(_, _, distance)| weight(distance)).sum())
+        .collect();
+    let forces: Vec<Vector2D> = lists
+        .par_iter()
+        .map(|list| {
+            let mut force = Vector2D::new(0.0, 0.0);
+            for &(i, offset, distance) in list {
+                force += offset * weight(distance) / densities[i];
+            }
+            for &(i, _, distance) in list {
+                force += positions[i] * weight(distance);
+            }
+            force
+        })
+        .collect();
+    (densities, forces)
+}
+
+fn neighbour_passes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("neighbour_passes");
+    for particle_count in [5_000, 20_000] {
+        let (positions, index) = scene(particle_count);
+
+        group.bench_with_input(
+            BenchmarkId::new("grid_walk", particle_count),
+            &positions,
+            |b, positions| b.iter(|| black_box(grid_walk(index.as_ref(), positions))),
+        );
+
+        let mut lists = Vec::new();
+        group.bench_with_input(
+            BenchmarkId::new("neighbour_list", particle_count),
+            &positions,
+            |b, positions| {
+                b.iter(|| black_box(neighbour_list(index.as_ref(), positions, &mut lists)))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, neighbour_passes);
+criterion_main!(benches);
@@ -0,0 +1,199 @@
+//! Times `Sim2D::step` and each pass it runs, at several particle counts and
+//! rayon thread counts.
+//!
+//! ```text
+//! cargo bench --features bench --bench step
+//! ```
+//!
+//! Besides Criterion's own output, the medians are written as a
+//! `sim_core::bench::BenchReport` to `$SIM_BENCH_REPORT`, or
+//! `target/bench-report.json`, for the `bench-compare` binary.
+
+use std::{
+    hint::black_box,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use criterion::{BenchmarkId, Criterion};
+use serde::Deserialize;
+use sim_core::{
+    Sim2D,
+    bench::{BenchReport, BenchResult, Pass},
+    config::SimConfig,
+    obstacles::Shape,
+    rigid_bodies::RigidBody,
+    vector_2d::Vector2D,
+};
+
+const PARTICLE_COUNTS: [usize; 4] = [1_000, 5_000, 20_000, 100_000];
+const DT: f32 = 1.0 / 60.0;
+
+// Powers of two up to the core count, and the core count itself
+fn thread_counts() -> Vec<usize> {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut counts: Vec<usize> = (0..)
+        .map(|power| 1 << power)
+        .take_while(|&count| count < cores)
+        .collect();
+    counts.push(cores);
+    counts
+}
+
+// Same particle spacing as the default 5000 particle scene, with surface
+// tension, vorticity confinement, heat and dye diffusion and a floating body
+// so every pass has work to do
+fn scene(particle_count: usize) -> Sim2D {
+    let scale = (particle_count as f32 / 5000.0).sqrt();
+    let config = SimConfig {
+        particle_count,
+        world_width: 16.0 * scale,
+        world_height: 9.0 * scale,
+        surface_tension: 0.2,
+        vorticity_confinement: 0.5,
+        thermal_diffusivity: 0.05,
+        dye_diffusivity: 0.05,
+        rigid_bodies: vec![RigidBody {
+            id: 0,
+            shape: Shape::Circle {
+                centre: Vector2D::new(0.0, 0.0),
+                radius: 0.5,
+            },
+            mass: 10.0,
+            inertia: None,
+            position: Vector2D::new(0.0, 0.0),
+            rotation: 0.0,
+            velocity: Vector2D::new(0.0, 0.0),
+            angular_velocity: 0.0,
+            restitution: 0.2,
+            friction: 0.1,
+        }],
+        ..SimConfig::default()
+    };
+    config.validate().expect("benchmark scene should be valid");
+
+    // Settle the initial grid so the passes see a realistic neighbourhood
+    let mut sim = Sim2D::new(config);
+    for _ in 0..10 {
+        sim.step(DT);
+    }
+    sim
+}
+
+fn bench_name(pass: Option<Pass>) -> &'static str {
+    pass.map_or("step", Pass::name)
+}
+
+fn function_name(threads: usize) -> String {
+    format!("{threads}_threads")
+}
+
+fn benchmarks(c: &mut Criterion, thread_counts: &[usize]) {
+    for particle_count in PARTICLE_COUNTS {
+        let mut sim = scene(particle_count);
+
+        for &threads in thread_counts {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("thread pool should build");
+
+            let passes = std::iter::once(None).chain(Pass::ALL.map(Some));
+            for pass in passes {
+                let mut group = c.benchmark_group(bench_name(pass));
+                if particle_count >= 20_000 {
+                    group.sample_size(20);
+                }
+                group.bench_function(
+                    BenchmarkId::new(function_name(threads), particle_count),
+                    |b| {
+                        pool.install(|| match pass {
+                            None => b.iter(|| sim.step(black_box(DT))),
+                            // Substep length, so repeated passes stay stable
+                            Some(pass) => b.iter(|| sim.run_pass(pass, black_box(DT / 2.0))),
+                        })
+                    },
+                );
+                group.finish();
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Estimate {
+    point_estimate: f64,
+}
+
+#[derive(Deserialize)]
+struct Estimates {
+    mean: Estimate,
+    median: Estimate,
+    std_dev: Estimate,
+}
+
+// Reads back the estimates Criterion saved for each benchmark. Those
+// filtered out on the command line, with none saved since `started`, are
+// skipped.
+fn report(criterion_dir: &Path, thread_counts: &[usize], started: SystemTime) -> BenchReport {
+    let mut results = Vec::new();
+    for particle_count in PARTICLE_COUNTS {
+        for &threads in thread_counts {
+            let passes = std::iter::once(None).chain(Pass::ALL.map(Some));
+            for pass in passes {
+                let path = criterion_dir
+                    .join(bench_name(pass))
+                    .join(function_name(threads))
+                    .join(particle_count.to_string())
+                    .join("new")
+                    .join("estimates.json");
+                let saved = std::fs::metadata(&path).and_then(|metadata| metadata.modified());
+                if !saved.is_ok_and(|saved| saved >= started) {
+                    continue;
+                }
+                let json = std::fs::read_to_string(path).expect("Criterion estimates should read");
+                let estimates: Estimates =
+                    serde_json::from_str(&json).expect("Criterion estimates should parse");
+
+                results.push(BenchResult {
+                    name: bench_name(pass).to_string(),
+                    particle_count,
+                    threads,
+                    mean_ns: estimates.mean.point_estimate,
+                    median_ns: estimates.median.point_estimate,
+                    std_dev_ns: estimates.std_dev.point_estimate,
+                });
+            }
+        }
+    }
+    BenchReport { results }
+}
+
+fn main() {
+    let target_dir =
+        std::env::var_os("CARGO_TARGET_DIR").map_or_else(|| PathBuf::from("target"), PathBuf::from);
+    let criterion_dir = target_dir.join("criterion");
+    let report_path = std::env::var_os("SIM_BENCH_REPORT")
+        .map_or_else(|| target_dir.join("bench-report.json"), PathBuf::from);
+
+    let thread_counts = thread_counts();
+    let started = SystemTime::now();
+    let mut criterion = Criterion::default()
+        .output_directory(&criterion_dir)
+        .configure_from_args();
+    benchmarks(&mut criterion, &thread_counts);
+    criterion.final_summary();
+
+    let report = report(&criterion_dir, &thread_counts, started);
+    if report.results.is_empty() {
+        return;
+    }
+    report
+        .write(&report_path)
+        .expect("benchmark report should be writable");
+    println!(
+        "Wrote {} results to {}",
+        report.results.len(),
+        report_path.display()
+    );
+}
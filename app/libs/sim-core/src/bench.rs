@@ -0,0 +1,256 @@
+//! Hooks and result files for the benchmark suite in `benches/step.rs`,
+//! behind the `bench` feature.
+//!
+//! The suite writes a [`BenchReport`] after each run, and the
+//! `bench-compare` binary compares two of them.
+
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Sim2D;
+
+/// One of the passes [`Sim2D::step`] runs each substep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    ExternalForces,
+    SpatialIndex,
+    Neighbours,
+    Densities,
+    PressureForces,
+    SurfaceNormals,
+    SurfaceTensionForces,
+    ViscousForces,
+    Vorticities,
+    VorticityConfinementForces,
+    Temperatures,
+    Dyes,
+    Positions,
+    RigidBodies,
+}
+
+impl Pass {
+    /// Every pass, in the order the explicit solver runs them.
+    pub const ALL: [Pass; 14] = [
+        Pass::ExternalForces,
+        Pass::SpatialIndex,
+        Pass::Neighbours,
+        Pass::Densities,
+        Pass::PressureForces,
+        Pass::SurfaceNormals,
+        Pass::SurfaceTensionForces,
+        Pass::ViscousForces,
+        Pass::Vorticities,
+        Pass::VorticityConfinementForces,
+        Pass::Temperatures,
+        Pass::Dyes,
+        Pass::Positions,
+        Pass::RigidBodies,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::ExternalForces => "external_forces",
+            Pass::SpatialIndex => "spatial_index",
+            Pass::Neighbours => "neighbours",
+            Pass::Densities => "densities",
+            Pass::PressureForces => "pressure_forces",
+            Pass::SurfaceNormals => "surface_normals",
+            Pass::SurfaceTensionForces => "surface_tension_forces",
+            Pass::ViscousForces => "viscous_forces",
+            Pass::Vorticities => "vorticities",
+            Pass::VorticityConfinementForces => "vorticity_confinement_forces",
+            Pass::Temperatures => "temperatures",
+            Pass::Dyes => "dyes",
+            Pass::Positions => "positions",
+            Pass::RigidBodies => "rigid_bodies",
+        }
+    }
+}
+
+impl Sim2D {
+    /// Runs a single pass on its own. Each pass reads what the passes before
+    /// it wrote, so step the simulation at least once first.
+    pub fn run_pass(&mut self, pass: Pass, dt: f32) {
+        match pass {
+            Pass::ExternalForces => self.update_external_forces(dt),
+            Pass::SpatialIndex => self.update_spatial_index(),
+            Pass::Neighbours => self.update_neighbours(),
+            Pass::Densities => self.update_densities(),
+            Pass::PressureForces => self.update_pressure_forces(dt),
+            Pass::SurfaceNormals => self.update_surface_normals(),
+            Pass::SurfaceTensionForces => self.update_surface_tension_forces(dt),
+            Pass::ViscousForces => self.update_viscous_forces(dt),
+            Pass::Vorticities => self.update_vorticities(),
+            Pass::VorticityConfinementForces => self.update_vorticity_confinement_forces(dt),
+            Pass::Temperatures => self.update_temperatures(dt),
+            Pass::Dyes => self.update_dyes(dt),
+            Pass::Positions => self.update_positions(dt),
+            Pass::RigidBodies => self.update_rigid_bodies(dt),
+        }
+    }
+}
+
+/// Timing of one benchmark, in nanoseconds per iteration.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchResult {
+    /// `step` or a [`Pass::name`].
+    pub name: String,
+    pub particle_count: usize,
+    pub threads: usize,
+    pub mean_ns: f64,
+    pub median_ns: f64,
+    pub std_dev_ns: f64,
+}
+
+impl BenchResult {
+    fn key(&self) -> (&str, usize, usize) {
+        (&self.name, self.particle_count, self.threads)
+    }
+}
+
+/// Every result from one run of the suite, saved as JSON.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchReport {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(io::Error::other)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    /// Pairs each result with the matching one in `baseline`, skipping
+    /// benchmarks only one of the reports has.
+    pub fn compare(&self, baseline: &BenchReport) -> Vec<Comparison> {
+        self.results
+            .iter()
+            .filter_map(|current| {
+                let baseline = baseline
+                    .results
+                    .iter()
+                    .find(|result| result.key() == current.key())?;
+                Some(Comparison {
+                    name: current.name.clone(),
+                    particle_count: current.particle_count,
+                    threads: current.threads,
+                    baseline_ns: baseline.median_ns,
+                    current_ns: current.median_ns,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Median time of one benchmark in two reports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub name: String,
+    pub particle_count: usize,
+    pub threads: usize,
+    pub baseline_ns: f64,
+    pub current_ns: f64,
+}
+
+impl Comparison {
+    /// Relative change from the baseline, so 0.1 is 10% slower.
+    pub fn change(&self) -> f64 {
+        self.current_ns / self.baseline_ns - 1.0
+    }
+
+    /// Whether the benchmark slowed down by more than `threshold`.
+    pub fn is_regression(&self, threshold: f64) -> bool {
+        self.change() > threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::SimConfig, obstacles::Shape, rigid_bodies::RigidBody, vector_2d::Vector2D,
+    };
+
+    fn result(name: &str, threads: usize, median_ns: f64) -> BenchResult {
+        BenchResult {
+            name: name.to_string(),
+            particle_count: 1000,
+            threads,
+            mean_ns: median_ns,
+            median_ns,
+            std_dev_ns: 0.0,
+        }
+    }
+
+    #[test]
+    fn reports_compare_matching_benchmarks() {
+        let baseline = BenchReport {
+            results: vec![result("step", 1, 100.0), result("densities", 1, 50.0)],
+        };
+        let current = BenchReport {
+            results: vec![
+                result("step", 1, 120.0),
+                result("densities", 1, 40.0),
+                result("densities", 4, 15.0),
+            ],
+        };
+
+        let comparisons = current.compare(&baseline);
+        assert_eq!(comparisons.len(), 2);
+        assert!((comparisons[0].change() - 0.2).abs() < 1e-9);
+        assert!(comparisons[0].is_regression(0.05));
+        assert!(!comparisons[1].is_regression(0.05));
+
+        let json = serde_json::to_string(&current).unwrap();
+        assert!(json.contains("\"particleCount\":1000"));
+        assert_eq!(serde_json::from_str::<BenchReport>(&json).unwrap(), current);
+    }
+
+    #[test]
+    fn passes_run_on_their_own() {
+        let config = SimConfig {
+            particle_count: 300,
+            surface_tension: 0.2,
+            vorticity_confinement: 0.5,
+            thermal_diffusivity: 0.05,
+            dye_diffusivity: 0.05,
+            rigid_bodies: vec![RigidBody {
+                id: 0,
+                shape: Shape::Circle {
+                    centre: Vector2D::new(0.0, 0.0),
+                    radius: 0.5,
+                },
+                mass: 10.0,
+                inertia: None,
+                position: Vector2D::new(0.0, 2.0),
+                rotation: 0.0,
+                velocity: Vector2D::new(0.0, 0.0),
+                angular_velocity: 0.0,
+                restitution: 0.2,
+                friction: 0.1,
+            }],
+            ..SimConfig::default()
+        };
+        let mut sim = Sim2D::new(config);
+        sim.step(1.0 / 60.0);
+        let body_height = sim.rigid_body_states[0].position.y;
+
+        for pass in Pass::ALL {
+            sim.run_pass(pass, 1.0 / 120.0);
+        }
+        assert!(
+            sim.positions[..sim.particle_count]
+                .iter()
+                .all(|position| position.x.is_finite() && position.y.is_finite())
+        );
+        assert!(sim.rigid_body_states[0].position.y < body_height);
+    }
+}
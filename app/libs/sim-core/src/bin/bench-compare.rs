@@ -0,0 +1,81 @@
+//! Compares two reports written by the `step` benchmark suite.
+//!
+//! ```text
+//! cargo run --release --features bench --bin bench-compare -- \
+//!     baseline.json current.json [threshold]
+//! ```
+//!
+//! Prints the change in median time of every benchmark both reports share,
+//! and exits with status 1 if any slowed down by more than `threshold`
+//! (default 0.05, or 5%).
+
+use std::{path::Path, process::ExitCode};
+
+use sim_core::bench::BenchReport;
+
+const DEFAULT_THRESHOLD: f64 = 0.05;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (baseline_path, current_path, threshold) = match args.as_slice() {
+        [baseline, current] => (baseline, current, DEFAULT_THRESHOLD),
+        [baseline, current, threshold] => match threshold.parse() {
+            Ok(threshold) => (baseline, current, threshold),
+            Err(_) => {
+                eprintln!("Invalid threshold: {threshold}");
+                return ExitCode::from(2);
+            }
+        },
+        _ => {
+            eprintln!("Usage: bench-compare <baseline.json> <current.json> [threshold]");
+            return ExitCode::from(2);
+        }
+    };
+
+    let read = |path: &String| {
+        BenchReport::read(Path::new(path))
+            .map_err(|error| eprintln!("Could not read {path}: {error}"))
+    };
+    let (Ok(baseline), Ok(current)) = (read(baseline_path), read(current_path)) else {
+        return ExitCode::from(2);
+    };
+
+    let comparisons = current.compare(&baseline);
+    if comparisons.is_empty() {
+        eprintln!("The reports share no benchmarks");
+        return ExitCode::from(2);
+    }
+
+    println!(
+        "{:<24} {:>9} {:>7} {:>14} {:>14} {:>8}",
+        "benchmark", "particles", "threads", "baseline (us)", "current (us)", "change"
+    );
+    let mut regressions = 0;
+    for comparison in &comparisons {
+        let regressed = comparison.is_regression(threshold);
+        if regressed {
+            regressions += 1;
+        }
+        println!(
+            "{:<24} {:>9} {:>7} {:>14.1} {:>14.1} {:>+7.1}%{}",
+            comparison.name,
+            comparison.particle_count,
+            comparison.threads,
+            comparison.baseline_ns / 1000.0,
+            comparison.current_ns / 1000.0,
+            comparison.change() * 100.0,
+            if regressed { "  regressed" } else { "" }
+        );
+    }
+
+    if regressions > 0 {
+        println!(
+            "{regressions} of {} benchmarks regressed by more than {:.1}%",
+            comparisons.len(),
+            threshold * 100.0
+        );
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
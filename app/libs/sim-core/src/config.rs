@@ -0,0 +1,720 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dye::DYE_CHANNELS,
+    flow::{Emitter, Sink},
+    kernels::KernelKind,
+    kinematics::KinematicBody,
+    layout::InitialLayout,
+    obstacles::Obstacle,
+    phases::{MAX_PHASES, Phase},
+    rigid_bodies::RigidBody,
+    sdf::Boundary,
+    solver::{SolverKind, Substepping},
+    spatial::SpatialIndexKind,
+    thermal::HeatSource,
+    vector_2d::Vector2D,
+    viscosity::ViscosityModel,
+};
+
+/// Complete set of parameters needed to construct a [`crate::Sim2D`].
+///
+/// Serialised with camelCase keys so the same document can be shared
+/// between the web client, the WebSocket server and saved scene files.
+/// Missing keys fall back to [`SimConfig::default`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SimConfig {
+    /// Particles placed by `layout` at the start.
+    pub particle_count: usize,
+    /// Room reserved for emitters to add particles. Defaults to
+    /// `particle_count`, so a closed scene never grows.
+    pub max_particle_count: Option<usize>,
+    pub particle_radius: f32,
+    pub world_width: f32,
+    pub world_height: f32,
+    pub gravity: f32,
+    pub target_density: f32,
+    pub pressure_multiplier: f32,
+    /// Strength of the [`ViscosityModel::Diffusion`] model, which the other
+    /// models ignore.
+    pub viscosity_strength: f32,
+    pub viscosity_model: ViscosityModel,
+    pub smoothing_radius: f32,
+    pub interaction_strength: f32,
+    pub interaction_radius: f32,
+    pub collision_damping: f32,
+    /// Strength of the repulsion between particles of different phases,
+    /// which keeps immiscible fluids apart.
+    pub interface_tension: f32,
+    /// Cohesion within each fluid, which beads droplets. 0 turns it off.
+    pub surface_tension: f32,
+    /// Strength of the vorticity confinement force, which puts back the
+    /// small swirls that numerical damping smooths out. 0 turns it off.
+    pub vorticity_confinement: f32,
+    /// Temperature every particle starts and is emitted at, and the one
+    /// buoyancy is measured from.
+    pub ambient_temperature: f32,
+    /// Rate heat spreads between neighbouring particles. 0 insulates them.
+    pub thermal_diffusivity: f32,
+    /// Fractional change in weight per degree above `ambient_temperature`,
+    /// in the Boussinesq approximation. 0 turns buoyancy off.
+    pub thermal_expansion: f32,
+    /// Rate dye spreads between neighbouring particles. 0 keeps it sharp,
+    /// carried only by the flow.
+    pub dye_diffusivity: f32,
+    pub solver: SolverKind,
+    pub substepping: Substepping,
+    /// Makes every step bit-reproducible across thread counts and between
+    /// native and wasm builds, at a small cost in sorting. See
+    /// [`crate::Sim2D::checksum`].
+    pub deterministic: bool,
+    pub spatial_index: SpatialIndexKind,
+    /// Kernel behind densities and the pressure, interface tension and
+    /// surface normal passes. The near density always uses a sharper spiky
+    /// kernel. With the `simd` feature, the density and pressure sums run
+    /// four neighbours at a time for every kernel; the other passes, and the
+    /// particle buffers, are not vectorised.
+    pub density_kernel: KernelKind,
+    pub viscosity_kernel: KernelKind,
+    pub layout: InitialLayout,
+    pub obstacles: Vec<Obstacle>,
+    pub boundary: Option<Boundary>,
+    pub kinematic_bodies: Vec<KinematicBody>,
+    pub rigid_bodies: Vec<RigidBody>,
+    pub emitters: Vec<Emitter>,
+    pub sinks: Vec<Sink>,
+    pub heat_sources: Vec<HeatSource>,
+    pub phases: Vec<Phase>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            particle_count: 5000,
+            max_particle_count: None,
+            particle_radius: 0.025,
+            world_width: 16.0,
+            world_height: 9.0,
+            gravity: -12.0,
+            target_density: 75.0,
+            pressure_multiplier: 500.0,
+            viscosity_strength: 0.03,
+            viscosity_model: ViscosityModel::Diffusion,
+            smoothing_radius: 0.35,
+            interaction_strength: 90.0,
+            interaction_radius: 2.0,
+            collision_damping: 0.95,
+            interface_tension: 0.0,
+            surface_tension: 0.0,
+            vorticity_confinement: 0.0,
+            ambient_temperature: 0.0,
+            thermal_diffusivity: 0.0,
+            thermal_expansion: 0.0,
+            dye_diffusivity: 0.0,
+            solver: SolverKind::Explicit,
+            substepping: Substepping::Fixed { substeps: 2 },
+            deterministic: false,
+            spatial_index: SpatialIndexKind::FlatGrid,
+            density_kernel: KernelKind::Spiky,
+            viscosity_kernel: KernelKind::Poly6,
+            layout: InitialLayout::Grid,
+            obstacles: Vec::new(),
+            boundary: None,
+            kinematic_bodies: Vec::new(),
+            rigid_bodies: Vec::new(),
+            emitters: Vec::new(),
+            sinks: Vec::new(),
+            heat_sources: Vec::new(),
+            phases: Vec::new(),
+        }
+    }
+}
+
+impl SimConfig {
+    pub fn builder() -> SimConfigBuilder {
+        SimConfigBuilder::default()
+    }
+
+    /// Size of the particle buffers, which never reallocate.
+    pub fn particle_capacity(&self) -> usize {
+        self.max_particle_count.unwrap_or(self.particle_count)
+    }
+
+    /// Checks that every parameter is finite and within the range the solver
+    /// can handle. Configs built with [`SimConfigBuilder`] are always valid,
+    /// deserialised configs should be checked before use.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.particle_capacity() == 0 {
+            return Err(ConfigError::NoParticles);
+        }
+        if self.particle_count > self.particle_capacity() {
+            return Err(ConfigError::TooManyParticles);
+        }
+
+        let fields = [
+            ("particleRadius", self.particle_radius),
+            ("worldWidth", self.world_width),
+            ("worldHeight", self.world_height),
+            ("gravity", self.gravity),
+            ("targetDensity", self.target_density),
+            ("pressureMultiplier", self.pressure_multiplier),
+            ("viscosityStrength", self.viscosity_strength),
+            ("smoothingRadius", self.smoothing_radius),
+            ("interactionStrength", self.interaction_strength),
+            ("interactionRadius", self.interaction_radius),
+            ("collisionDamping", self.collision_damping),
+            ("interfaceTension", self.interface_tension),
+            ("surfaceTension", self.surface_tension),
+            ("vorticityConfinement", self.vorticity_confinement),
+            ("ambientTemperature", self.ambient_temperature),
+            ("thermalDiffusivity", self.thermal_diffusivity),
+            ("thermalExpansion", self.thermal_expansion),
+            ("dyeDiffusivity", self.dye_diffusivity),
+        ];
+        if let Some((field, _)) = fields.iter().find(|(_, value)| !value.is_finite()) {
+            return Err(ConfigError::NotFinite(field));
+        }
+
+        let positive = [
+            ("particleRadius", self.particle_radius),
+            ("targetDensity", self.target_density),
+            ("smoothingRadius", self.smoothing_radius),
+        ];
+        if let Some((field, _)) = positive.iter().find(|(_, value)| *value <= 0.0) {
+            return Err(ConfigError::NotPositive(field));
+        }
+
+        let non_negative = [
+            ("pressureMultiplier", self.pressure_multiplier),
+            ("viscosityStrength", self.viscosity_strength),
+            ("interactionRadius", self.interaction_radius),
+            ("interfaceTension", self.interface_tension),
+            ("surfaceTension", self.surface_tension),
+            ("vorticityConfinement", self.vorticity_confinement),
+            ("thermalDiffusivity", self.thermal_diffusivity),
+            ("thermalExpansion", self.thermal_expansion),
+            ("dyeDiffusivity", self.dye_diffusivity),
+        ];
+        if let Some((field, _)) = non_negative.iter().find(|(_, value)| *value < 0.0) {
+            return Err(ConfigError::Negative(field));
+        }
+
+        if !(0.0..=1.0).contains(&self.collision_damping) {
+            return Err(ConfigError::OutOfRange("collisionDamping"));
+        }
+
+        if self.world_width <= 2.0 * self.particle_radius
+            || self.world_height <= 2.0 * self.particle_radius
+        {
+            return Err(ConfigError::WorldTooSmall);
+        }
+
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            obstacle.validate()?;
+
+            if self.obstacles[..i]
+                .iter()
+                .any(|other| other.id == obstacle.id)
+            {
+                return Err(ConfigError::DuplicateObstacleId(obstacle.id));
+            }
+        }
+
+        for (i, body) in self.kinematic_bodies.iter().enumerate() {
+            body.validate()?;
+
+            if self.kinematic_bodies[..i]
+                .iter()
+                .any(|other| other.id == body.id)
+            {
+                return Err(ConfigError::DuplicateKinematicBodyId(body.id));
+            }
+        }
+
+        for (i, body) in self.rigid_bodies.iter().enumerate() {
+            body.validate()?;
+
+            if self.rigid_bodies[..i]
+                .iter()
+                .any(|other| other.id == body.id)
+            {
+                return Err(ConfigError::DuplicateRigidBodyId(body.id));
+            }
+        }
+
+        self.viscosity_model.validate()?;
+        self.solver.validate()?;
+        self.substepping.validate()?;
+
+        self.spatial_index.validate(
+            self.smoothing_radius,
+            Vector2D::new(self.world_width, self.world_height),
+        )?;
+
+        if self.phases.len() > MAX_PHASES {
+            return Err(ConfigError::InvalidPhase("too many phases"));
+        }
+        for phase in &self.phases {
+            phase.validate()?;
+        }
+
+        for (i, emitter) in self.emitters.iter().enumerate() {
+            emitter.validate()?;
+
+            if emitter.phase as usize > self.phases.len() {
+                return Err(ConfigError::InvalidEmitter("phase does not exist"));
+            }
+
+            if self.emitters[..i]
+                .iter()
+                .any(|other| other.id == emitter.id)
+            {
+                return Err(ConfigError::DuplicateEmitterId(emitter.id));
+            }
+        }
+
+        for (i, sink) in self.sinks.iter().enumerate() {
+            sink.validate()?;
+
+            if self.sinks[..i].iter().any(|other| other.id == sink.id) {
+                return Err(ConfigError::DuplicateSinkId(sink.id));
+            }
+        }
+
+        for (i, source) in self.heat_sources.iter().enumerate() {
+            source.validate()?;
+
+            if self.heat_sources[..i]
+                .iter()
+                .any(|other| other.id == source.id)
+            {
+                return Err(ConfigError::DuplicateHeatSourceId(source.id));
+            }
+        }
+
+        if let Some(boundary) = &self.boundary {
+            boundary.validate_in(Vector2D::new(self.world_width, self.world_height))?;
+        }
+
+        self.layout.validate()?;
+        self.layout.validate_fit(
+            self.particle_count,
+            Vector2D::new(self.world_width, self.world_height),
+            self.particle_radius,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    NoParticles,
+    TooManyParticles,
+    NotFinite(&'static str),
+    NotPositive(&'static str),
+    Negative(&'static str),
+    OutOfRange(&'static str),
+    WorldTooSmall,
+    InvalidLayout(&'static str),
+    InvalidObstacle(&'static str),
+    DuplicateObstacleId(u32),
+    InvalidBoundary(&'static str),
+    InvalidKinematicBody(&'static str),
+    DuplicateKinematicBodyId(u32),
+    InvalidRigidBody(&'static str),
+    DuplicateRigidBodyId(u32),
+    InvalidEmitter(&'static str),
+    DuplicateEmitterId(u32),
+    DuplicateSinkId(u32),
+    InvalidHeatSource(&'static str),
+    DuplicateHeatSourceId(u32),
+    InvalidPhase(&'static str),
+    InvalidViscosityModel(&'static str),
+    InvalidSolver(&'static str),
+    InvalidSubstepping(&'static str),
+    InvalidSpatialIndex(&'static str),
+    InvalidDyeChannel(usize),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoParticles => write!(f, "particleCount must be at least 1"),
+            ConfigError::TooManyParticles => {
+                write!(f, "particleCount must not exceed maxParticleCount")
+            }
+            ConfigError::NotFinite(field) => write!(f, "{field} must be finite"),
+            ConfigError::NotPositive(field) => write!(f, "{field} must be greater than 0"),
+            ConfigError::Negative(field) => write!(f, "{field} must not be negative"),
+            ConfigError::OutOfRange(field) => write!(f, "{field} must be between 0 and 1"),
+            ConfigError::WorldTooSmall => {
+                write!(f, "world must be wider and taller than one particle")
+            }
+            ConfigError::InvalidLayout(reason) => write!(f, "invalid layout: {reason}"),
+            ConfigError::InvalidObstacle(reason) => write!(f, "invalid obstacle: {reason}"),
+            ConfigError::DuplicateObstacleId(id) => write!(f, "obstacle id {id} is used twice"),
+            ConfigError::InvalidBoundary(reason) => write!(f, "invalid boundary: {reason}"),
+            ConfigError::InvalidKinematicBody(reason) => {
+                write!(f, "invalid kinematic body: {reason}")
+            }
+            ConfigError::DuplicateKinematicBodyId(id) => {
+                write!(f, "kinematic body id {id} is used twice")
+            }
+            ConfigError::InvalidRigidBody(reason) => write!(f, "invalid rigid body: {reason}"),
+            ConfigError::DuplicateRigidBodyId(id) => {
+                write!(f, "rigid body id {id} is used twice")
+            }
+            ConfigError::InvalidEmitter(reason) => write!(f, "invalid emitter: {reason}"),
+            ConfigError::DuplicateEmitterId(id) => write!(f, "emitter id {id} is used twice"),
+            ConfigError::DuplicateSinkId(id) => write!(f, "sink id {id} is used twice"),
+            ConfigError::InvalidHeatSource(reason) => write!(f, "invalid heat source: {reason}"),
+            ConfigError::DuplicateHeatSourceId(id) => {
+                write!(f, "heat source id {id} is used twice")
+            }
+            ConfigError::InvalidPhase(reason) => write!(f, "invalid phase: {reason}"),
+            ConfigError::InvalidViscosityModel(reason) => {
+                write!(f, "invalid viscosity model: {reason}")
+            }
+            ConfigError::InvalidSolver(reason) => write!(f, "invalid solver: {reason}"),
+            ConfigError::InvalidSubstepping(reason) => {
+                write!(f, "invalid substepping: {reason}")
+            }
+            ConfigError::InvalidSpatialIndex(reason) => {
+                write!(f, "invalid spatial index: {reason}")
+            }
+            ConfigError::InvalidDyeChannel(channel) => {
+                write!(f, "dye channel {channel} must be below {DYE_CHANNELS}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Builder for [`SimConfig`], starting from the defaults.
+#[derive(Clone, Debug, Default)]
+pub struct SimConfigBuilder {
+    config: SimConfig,
+}
+
+impl SimConfigBuilder {
+    pub fn particle_count(mut self, particle_count: usize) -> Self {
+        self.config.particle_count = particle_count;
+        self
+    }
+
+    pub fn max_particle_count(mut self, max_particle_count: usize) -> Self {
+        self.config.max_particle_count = Some(max_particle_count);
+        self
+    }
+
+    pub fn particle_radius(mut self, particle_radius: f32) -> Self {
+        self.config.particle_radius = particle_radius;
+        self
+    }
+
+    pub fn world_dimensions(mut self, world_width: f32, world_height: f32) -> Self {
+        self.config.world_width = world_width;
+        self.config.world_height = world_height;
+        self
+    }
+
+    pub fn gravity(mut self, gravity: f32) -> Self {
+        self.config.gravity = gravity;
+        self
+    }
+
+    pub fn target_density(mut self, target_density: f32) -> Self {
+        self.config.target_density = target_density;
+        self
+    }
+
+    pub fn pressure_multiplier(mut self, pressure_multiplier: f32) -> Self {
+        self.config.pressure_multiplier = pressure_multiplier;
+        self
+    }
+
+    pub fn viscosity_strength(mut self, viscosity_strength: f32) -> Self {
+        self.config.viscosity_strength = viscosity_strength;
+        self
+    }
+
+    pub fn viscosity_model(mut self, viscosity_model: ViscosityModel) -> Self {
+        self.config.viscosity_model = viscosity_model;
+        self
+    }
+
+    pub fn smoothing_radius(mut self, smoothing_radius: f32) -> Self {
+        self.config.smoothing_radius = smoothing_radius;
+        self
+    }
+
+    pub fn interaction_strength(mut self, interaction_strength: f32) -> Self {
+        self.config.interaction_strength = interaction_strength;
+        self
+    }
+
+    pub fn interaction_radius(mut self, interaction_radius: f32) -> Self {
+        self.config.interaction_radius = interaction_radius;
+        self
+    }
+
+    pub fn collision_damping(mut self, collision_damping: f32) -> Self {
+        self.config.collision_damping = collision_damping;
+        self
+    }
+
+    pub fn interface_tension(mut self, interface_tension: f32) -> Self {
+        self.config.interface_tension = interface_tension;
+        self
+    }
+
+    pub fn surface_tension(mut self, surface_tension: f32) -> Self {
+        self.config.surface_tension = surface_tension;
+        self
+    }
+
+    pub fn vorticity_confinement(mut self, vorticity_confinement: f32) -> Self {
+        self.config.vorticity_confinement = vorticity_confinement;
+        self
+    }
+
+    pub fn ambient_temperature(mut self, ambient_temperature: f32) -> Self {
+        self.config.ambient_temperature = ambient_temperature;
+        self
+    }
+
+    pub fn thermal_diffusivity(mut self, thermal_diffusivity: f32) -> Self {
+        self.config.thermal_diffusivity = thermal_diffusivity;
+        self
+    }
+
+    pub fn thermal_expansion(mut self, thermal_expansion: f32) -> Self {
+        self.config.thermal_expansion = thermal_expansion;
+        self
+    }
+
+    pub fn dye_diffusivity(mut self, dye_diffusivity: f32) -> Self {
+        self.config.dye_diffusivity = dye_diffusivity;
+        self
+    }
+
+    pub fn solver(mut self, solver: SolverKind) -> Self {
+        self.config.solver = solver;
+        self
+    }
+
+    pub fn substepping(mut self, substepping: Substepping) -> Self {
+        self.config.substepping = substepping;
+        self
+    }
+
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.config.deterministic = deterministic;
+        self
+    }
+
+    pub fn spatial_index(mut self, spatial_index: SpatialIndexKind) -> Self {
+        self.config.spatial_index = spatial_index;
+        self
+    }
+
+    pub fn density_kernel(mut self, density_kernel: KernelKind) -> Self {
+        self.config.density_kernel = density_kernel;
+        self
+    }
+
+    pub fn viscosity_kernel(mut self, viscosity_kernel: KernelKind) -> Self {
+        self.config.viscosity_kernel = viscosity_kernel;
+        self
+    }
+
+    pub fn layout(mut self, layout: InitialLayout) -> Self {
+        self.config.layout = layout;
+        self
+    }
+
+    /// Adds an obstacle, giving it the next free id.
+    pub fn obstacle(mut self, mut obstacle: Obstacle) -> Self {
+        obstacle.id = self
+            .config
+            .obstacles
+            .iter()
+            .map(|o| o.id + 1)
+            .max()
+            .unwrap_or(0);
+        self.config.obstacles.push(obstacle);
+        self
+    }
+
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.config.boundary = Some(boundary);
+        self
+    }
+
+    /// Adds a kinematic body, giving it the next free id.
+    pub fn kinematic_body(mut self, mut body: KinematicBody) -> Self {
+        body.id = self
+            .config
+            .kinematic_bodies
+            .iter()
+            .map(|b| b.id + 1)
+            .max()
+            .unwrap_or(0);
+        self.config.kinematic_bodies.push(body);
+        self
+    }
+
+    /// Adds a rigid body, giving it the next free id.
+    pub fn rigid_body(mut self, mut body: RigidBody) -> Self {
+        body.id = self
+            .config
+            .rigid_bodies
+            .iter()
+            .map(|b| b.id + 1)
+            .max()
+            .unwrap_or(0);
+        self.config.rigid_bodies.push(body);
+        self
+    }
+
+    /// Adds an emitter, giving it the next free id.
+    pub fn emitter(mut self, mut emitter: Emitter) -> Self {
+        emitter.id = self
+            .config
+            .emitters
+            .iter()
+            .map(|e| e.id + 1)
+            .max()
+            .unwrap_or(0);
+        self.config.emitters.push(emitter);
+        self
+    }
+
+    /// Adds a sink, giving it the next free id.
+    pub fn sink(mut self, mut sink: Sink) -> Self {
+        sink.id = self
+            .config
+            .sinks
+            .iter()
+            .map(|s| s.id + 1)
+            .max()
+            .unwrap_or(0);
+        self.config.sinks.push(sink);
+        self
+    }
+
+    /// Adds a heat source, giving it the next free id.
+    pub fn heat_source(mut self, mut source: HeatSource) -> Self {
+        source.id = self
+            .config
+            .heat_sources
+            .iter()
+            .map(|s| s.id + 1)
+            .max()
+            .unwrap_or(0);
+        self.config.heat_sources.push(source);
+        self
+    }
+
+    /// Adds a phase, which particles refer to by its index plus one.
+    pub fn phase(mut self, phase: Phase) -> Self {
+        self.config.phases.push(phase);
+        self
+    }
+
+    pub fn build(self) -> Result<SimConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_default_is_valid() {
+        assert_eq!(SimConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn config_builder_sets_fields() {
+        let config = SimConfig::builder()
+            .particle_count(100)
+            .smoothing_radius(0.5)
+            .viscosity_strength(0.1)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.particle_count, 100);
+        assert_eq!(config.smoothing_radius, 0.5);
+        assert_eq!(config.viscosity_strength, 0.1);
+    }
+
+    #[test]
+    fn config_builder_rejects_invalid() {
+        assert_eq!(
+            SimConfig::builder().particle_count(0).build(),
+            Err(ConfigError::NoParticles)
+        );
+        assert_eq!(
+            SimConfig::builder()
+                .particle_count(100)
+                .max_particle_count(50)
+                .build(),
+            Err(ConfigError::TooManyParticles)
+        );
+        assert_eq!(
+            SimConfig::builder().smoothing_radius(0.0).build(),
+            Err(ConfigError::NotPositive("smoothingRadius"))
+        );
+        assert_eq!(
+            SimConfig::builder().gravity(f32::NAN).build(),
+            Err(ConfigError::NotFinite("gravity"))
+        );
+        assert_eq!(
+            SimConfig::builder().thermal_diffusivity(-1.0).build(),
+            Err(ConfigError::Negative("thermalDiffusivity"))
+        );
+        assert_eq!(
+            SimConfig::builder().collision_damping(1.5).build(),
+            Err(ConfigError::OutOfRange("collisionDamping"))
+        );
+        assert_eq!(
+            SimConfig::builder()
+                .layout(InitialLayout::DamBreak {
+                    width: 0.01,
+                    height: 0.01,
+                })
+                .build(),
+            Err(ConfigError::InvalidLayout(
+                "region is too small for the particle count"
+            ))
+        );
+        assert!(matches!(
+            SimConfig::builder()
+                .solver(SolverKind::PositionBased { iterations: 0 })
+                .build(),
+            Err(ConfigError::InvalidSolver(_))
+        ));
+        assert!(matches!(
+            SimConfig::builder()
+                .substepping(Substepping::Adaptive {
+                    velocity_cfl: 0.0,
+                    force_cfl: 0.25,
+                    max_substeps: 8,
+                })
+                .build(),
+            Err(ConfigError::InvalidSubstepping(_))
+        ));
+        assert!(matches!(
+            SimConfig::builder()
+                .viscosity_model(ViscosityModel::Xsph { epsilon: -0.1 })
+                .build(),
+            Err(ConfigError::InvalidViscosityModel(_))
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::vector_2d::Vector2D;
+
+/// Dye channels each particle carries, so up to this many colours can be
+/// mixed at once.
+pub const DYE_CHANNELS: usize = 3;
+
+/// Concentration of each dye channel, from 0 (clear) to 1.
+pub type Dye = [f32; DYE_CHANNELS];
+
+// Pointer held down to paint dye, like the pull and push interactions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct DyeBrush {
+    pub position: Vector2D,
+    pub channel: usize,
+}
+
+impl DyeBrush {
+    // Full strength at the centre of the brush, fading out to its edge.
+    // Painting never thins dye that is already there.
+    pub fn paint(&self, position: Vector2D, radius: f32, dye: &mut Dye) {
+        let distance = (position - self.position).magnitude();
+        if distance >= radius {
+            return;
+        }
+
+        let concentration = &mut dye[self.channel];
+        *concentration = concentration.max(1.0 - distance / radius);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Sim2D,
+        config::{ConfigError, SimConfig},
+    };
+
+    fn still_box(dye_diffusivity: f32) -> Sim2D {
+        Sim2D::new(
+            SimConfig::builder()
+                .particle_count(600)
+                .world_dimensions(4.0, 2.0)
+                .gravity(0.0)
+                .interaction_radius(0.5)
+                .dye_diffusivity(dye_diffusivity)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn brush_paints_only_its_channel_within_reach() {
+        let mut sim = still_box(0.0);
+        sim.enable_dye_interaction(-1.0, 0.0, 1).unwrap();
+        sim.step(1.0 / 60.0);
+        sim.disable_dye_interaction();
+
+        for (position, dye) in sim.positions().iter().zip(sim.dyes()) {
+            assert_eq!(dye[0], 0.0);
+            assert_eq!(dye[2], 0.0);
+            if (*position - Vector2D::new(-1.0, 0.0)).magnitude() > 0.6 {
+                assert_eq!(dye[1], 0.0, "{position:?}");
+            }
+        }
+        assert!(sim.dyes().iter().any(|dye| dye[1] > 0.8));
+
+        // Carried with the particles once the brush lifts
+        let painted = sim.dyes().iter().map(|dye| dye[1]).sum::<f32>();
+        sim.step(1.0 / 60.0);
+        let carried = sim.dyes().iter().map(|dye| dye[1]).sum::<f32>();
+        assert_eq!(painted, carried);
+
+        sim.clear_dyes();
+        assert!(sim.dyes().iter().all(|dye| *dye == [0.0; DYE_CHANNELS]));
+
+        assert_eq!(
+            sim.enable_dye_interaction(0.0, 0.0, DYE_CHANNELS),
+            Err(ConfigError::InvalidDyeChannel(DYE_CHANNELS))
+        );
+    }
+
+    #[test]
+    fn dye_spreads_when_diffusive() {
+        // Particles tinted a second after a dab of dye
+        let spread = |dye_diffusivity: f32| {
+            let mut sim = still_box(dye_diffusivity);
+            sim.enable_dye_interaction(0.0, 0.0, 0).unwrap();
+            sim.step(1.0 / 60.0);
+            sim.disable_dye_interaction();
+            let painted = sim.dyes().iter().map(|dye| dye[0]).sum::<f32>();
+
+            for _ in 0..60 {
+                sim.step(1.0 / 60.0);
+            }
+
+            let tinted = sim.dyes().iter().filter(|dye| dye[0] > 0.01).count();
+            let total = sim.dyes().iter().map(|dye| dye[0]).sum::<f32>();
+            assert!(
+                (total - painted).abs() < 0.01 * painted,
+                "{painted} -> {total}"
+            );
+            tinted
+        };
+
+        let (diffused, sharp) = (spread(0.05), spread(0.0));
+        assert!(diffused > 2 * sharp, "{diffused} vs {sharp}");
+    }
+}
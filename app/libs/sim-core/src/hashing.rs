@@ -40,3 +40,12 @@ pub fn hash_cell(cell: (isize, isize)) -> usize {
 pub fn key_from_hash(hash: usize, table_size: usize) -> usize {
     hash % table_size
 }
+
+pub const CHECKSUM_SEED: u64 = 0xCBF2_9CE4_8422_2325;
+
+// FNV-1a, continuing from `hash`
+pub fn checksum_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
@@ -1,8 +1,68 @@
 use std::f32::consts::PI;
 
+use serde::{Deserialize, Serialize};
+
+/// Radially symmetric smoothing kernel with compact support, normalised so
+/// it integrates to 1 over the plane.
+pub trait Kernel {
+    /// Weight of a neighbour `distance` away. 0 from `radius` on.
+    fn value(&self, distance: f32, radius: f32) -> f32;
+
+    /// Rate of change of [`Kernel::value`] with distance.
+    fn derivative(&self, distance: f32, radius: f32) -> f32;
+}
+
+/// Which [`Kernel`] a pass smooths with, as stored in a
+/// [`crate::config::SimConfig`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum KernelKind {
+    /// `(h - r)^2`. Sharp peak, so particles don't clump under pressure.
+    Spiky,
+    /// `(h^2 - r^2)^3` (Müller et al. 2003). Smooth, but flat in the middle.
+    Poly6,
+    /// Monaghan's cubic B-spline.
+    CubicSpline,
+    /// Wendland's C2 function, which resists pairing at large neighbour
+    /// counts.
+    WendlandC2,
+    /// Wendland's C4 function. Smoother than C2, for larger radii.
+    WendlandC4,
+}
+
+impl Kernel for KernelKind {
+    fn value(&self, distance: f32, radius: f32) -> f32 {
+        match self {
+            KernelKind::Spiky => density_kernel(distance, radius),
+            KernelKind::Poly6 => viscosity_kernel(distance, radius),
+            KernelKind::CubicSpline => cubic_spline_kernel(distance, radius),
+            KernelKind::WendlandC2 => wendland_c2_kernel(distance, radius),
+            KernelKind::WendlandC4 => wendland_c4_kernel(distance, radius),
+        }
+    }
+
+    fn derivative(&self, distance: f32, radius: f32) -> f32 {
+        match self {
+            KernelKind::Spiky => density_kernel_derivative(distance, radius),
+            KernelKind::Poly6 => viscosity_kernel_derivative(distance, radius),
+            KernelKind::CubicSpline => cubic_spline_kernel_derivative(distance, radius),
+            KernelKind::WendlandC2 => wendland_c2_kernel_derivative(distance, radius),
+            KernelKind::WendlandC4 => wendland_c4_kernel_derivative(distance, radius),
+        }
+    }
+}
+
 // Spiky
+pub fn density_kernel_volume(radius: f32) -> f32 {
+    PI * radius.powi(4) / 6.0
+}
+
+pub fn near_density_kernel_volume(radius: f32) -> f32 {
+    PI * radius.powi(5) / 10.0
+}
+
 pub fn density_kernel(distance: f32, radius: f32) -> f32 {
-    let volume = PI * radius.powi(4) / 6.0;
+    let volume = density_kernel_volume(radius);
     if distance < radius {
         (radius - distance).powi(2) / volume
     } else {
@@ -11,7 +71,7 @@ pub fn density_kernel(distance: f32, radius: f32) -> f32 {
 }
 
 pub fn near_density_kernel(distance: f32, radius: f32) -> f32 {
-    let volume = PI * radius.powi(5) / 10.0;
+    let volume = near_density_kernel_volume(radius);
     if distance < radius {
         (radius - distance).powi(3) / volume
     } else {
@@ -20,7 +80,7 @@ pub fn near_density_kernel(distance: f32, radius: f32) -> f32 {
 }
 
 pub fn density_kernel_derivative(distance: f32, radius: f32) -> f32 {
-    let volume = PI * radius.powi(4) / 6.0;
+    let volume = density_kernel_volume(radius);
     if distance <= radius {
         -2.0 * (radius - distance) / volume
     } else {
@@ -29,7 +89,7 @@ pub fn density_kernel_derivative(distance: f32, radius: f32) -> f32 {
 }
 
 pub fn near_density_kernel_derivative(distance: f32, radius: f32) -> f32 {
-    let volume = PI * radius.powi(5) / 10.0;
+    let volume = near_density_kernel_volume(radius);
     if distance <= radius {
         -3.0 * (radius - distance).powi(2) / volume
     } else {
@@ -38,11 +98,162 @@ pub fn near_density_kernel_derivative(distance: f32, radius: f32) -> f32 {
 }
 
 // Poly6
+pub fn viscosity_kernel_volume(radius: f32) -> f32 {
+    PI * radius.powi(8) / 4.0
+}
+
 pub fn viscosity_kernel(distance: f32, radius: f32) -> f32 {
-    let volume = PI * radius.powi(8) / 4.0;
+    let volume = viscosity_kernel_volume(radius);
     if distance < radius {
         (radius * radius - distance * distance).powi(3) / volume
     } else {
         0.0
     }
 }
+
+pub fn viscosity_kernel_derivative(distance: f32, radius: f32) -> f32 {
+    let volume = viscosity_kernel_volume(radius);
+    if distance < radius {
+        -6.0 * distance * (radius * radius - distance * distance).powi(2) / volume
+    } else {
+        0.0
+    }
+}
+
+// Cubic spline, with the usual support of twice the smoothing length
+// stretched over the whole radius
+pub fn cubic_spline_kernel(distance: f32, radius: f32) -> f32 {
+    let scale = 40.0 / (7.0 * PI * radius * radius);
+    let q = distance / radius;
+    if q <= 0.5 {
+        scale * (6.0 * (q * q * q - q * q) + 1.0)
+    } else if q < 1.0 {
+        scale * 2.0 * (1.0 - q).powi(3)
+    } else {
+        0.0
+    }
+}
+
+pub fn cubic_spline_kernel_derivative(distance: f32, radius: f32) -> f32 {
+    let scale = 40.0 / (7.0 * PI * radius.powi(3));
+    let q = distance / radius;
+    if q <= 0.5 {
+        scale * 6.0 * (3.0 * q * q - 2.0 * q)
+    } else if q < 1.0 {
+        scale * -6.0 * (1.0 - q).powi(2)
+    } else {
+        0.0
+    }
+}
+
+// Wendland C2
+pub fn wendland_c2_kernel(distance: f32, radius: f32) -> f32 {
+    let scale = 7.0 / (PI * radius * radius);
+    let q = distance / radius;
+    if q < 1.0 {
+        scale * (1.0 - q).powi(4) * (1.0 + 4.0 * q)
+    } else {
+        0.0
+    }
+}
+
+pub fn wendland_c2_kernel_derivative(distance: f32, radius: f32) -> f32 {
+    let scale = 7.0 / (PI * radius.powi(3));
+    let q = distance / radius;
+    if q < 1.0 {
+        scale * -20.0 * q * (1.0 - q).powi(3)
+    } else {
+        0.0
+    }
+}
+
+// Wendland C4
+pub fn wendland_c4_kernel(distance: f32, radius: f32) -> f32 {
+    let scale = 9.0 / (PI * radius * radius);
+    let q = distance / radius;
+    if q < 1.0 {
+        scale * (1.0 - q).powi(6) * (1.0 + 6.0 * q + 35.0 / 3.0 * q * q)
+    } else {
+        0.0
+    }
+}
+
+pub fn wendland_c4_kernel_derivative(distance: f32, radius: f32) -> f32 {
+    let scale = 9.0 / (PI * radius.powi(3));
+    let q = distance / radius;
+    if q < 1.0 {
+        scale * -56.0 / 3.0 * q * (1.0 + 5.0 * q) * (1.0 - q).powi(5)
+    } else {
+        0.0
+    }
+}
+
+// Akinci cohesion spline, attracting beyond half the radius and repelling
+// below it
+pub fn cohesion_kernel(distance: f32, radius: f32) -> f32 {
+    let volume = PI * radius.powi(8) * 209.0 / 35840.0;
+    let spline = (radius - distance).powi(3) * distance.powi(3);
+    if 2.0 * distance > radius && distance <= radius {
+        spline / volume
+    } else if distance > 0.0 && 2.0 * distance <= radius {
+        (2.0 * spline - radius.powi(6) / 64.0) / volume
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [KernelKind; 5] = [
+        KernelKind::Spiky,
+        KernelKind::Poly6,
+        KernelKind::CubicSpline,
+        KernelKind::WendlandC2,
+        KernelKind::WendlandC4,
+    ];
+    const RADIUS: f32 = 0.35;
+
+    // Midpoint rule over rings of the support
+    fn integrate(f: impl Fn(f32) -> f32, radius: f32) -> f32 {
+        let rings = 4000;
+        let width = radius / rings as f32;
+        (0..rings)
+            .map(|ring| {
+                let r = (ring as f32 + 0.5) * width;
+                2.0 * PI * r * f(r) * width
+            })
+            .sum()
+    }
+
+    #[test]
+    fn kernels_integrate_to_one() {
+        for kind in KINDS {
+            let integral = integrate(|r| kind.value(r, RADIUS), RADIUS);
+            assert!((integral - 1.0).abs() < 1e-3, "{kind:?}: {integral}");
+            assert_eq!(kind.value(RADIUS, RADIUS), 0.0, "{kind:?}");
+        }
+
+        let near_density = integrate(|r| near_density_kernel(r, RADIUS), RADIUS);
+        assert!((near_density - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn derivatives_match_finite_differences() {
+        let step = 1e-3 * RADIUS;
+        for kind in KINDS {
+            for i in 1..20 {
+                let r = RADIUS * i as f32 / 20.0;
+                let expected =
+                    (kind.value(r + step, RADIUS) - kind.value(r - step, RADIUS)) / (2.0 * step);
+                let derivative = kind.derivative(r, RADIUS);
+                let tolerance = 1e-2 * expected.abs().max(1.0);
+                assert!(
+                    (derivative - expected).abs() < tolerance,
+                    "{kind:?} at {r}: {derivative} != {expected}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::f32::consts::TAU;
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ConfigError,
+    obstacles::{Shape, resolve_contact},
+    vector_2d::Vector2D,
+};
+
+/// Pose of a [`KinematicBody`] at a point in its schedule.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Keyframe {
+    /// Simulation time in seconds.
+    pub time: f32,
+    pub position: Vector2D,
+    pub rotation: f32,
+}
+
+/// How a [`KinematicBody`] moves over simulation time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Motion {
+    /// Linear interpolation between keyframes. The body holds the first pose
+    /// before the first keyframe and the last pose after the last, unless
+    /// `looped`, in which case it jumps back to the first keyframe.
+    Keyframes {
+        keyframes: Vec<Keyframe>,
+        looped: bool,
+    },
+    /// Sinusoidal sway about a rest pose, plus a constant `spin` in radians
+    /// per second for mixer blades.
+    Oscillator {
+        centre: Vector2D,
+        rotation: f32,
+        amplitude: Vector2D,
+        angular_amplitude: f32,
+        frequency: f32,
+        phase: f32,
+        spin: f32,
+    },
+}
+
+/// Pose and velocity of a moving body at one instant. Laid out as six `f32`s
+/// so rigid body states can be read straight out of wasm memory.
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[repr(C)]
+pub struct BodyState {
+    pub position: Vector2D,
+    pub rotation: f32,
+    pub velocity: Vector2D,
+    pub angular_velocity: f32,
+}
+
+impl BodyState {
+    /// Velocity of the body's surface at `point`.
+    pub fn point_velocity(&self, point: Vector2D) -> Vector2D {
+        self.velocity + (point - self.position).perpendicular() * self.angular_velocity
+    }
+}
+
+/// Wall that follows a scripted path regardless of the fluid, such as a
+/// paddle, a mixer blade or a piston.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KinematicBody {
+    /// Assigned by [`crate::Sim2D::add_kinematic_body`]; must be unique in a
+    /// config.
+    #[serde(default)]
+    pub id: u32,
+    /// Shape in body coordinates, about the origin.
+    pub shape: Shape,
+    pub motion: Motion,
+    /// Fraction of normal velocity kept after a bounce.
+    pub restitution: f32,
+    /// Fraction of tangential velocity removed on contact.
+    pub friction: f32,
+}
+
+impl KinematicBody {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(0.0..=1.0).contains(&self.restitution) {
+            return Err(ConfigError::OutOfRange("restitution"));
+        }
+        if !(0.0..=1.0).contains(&self.friction) {
+            return Err(ConfigError::OutOfRange("friction"));
+        }
+
+        match &self.motion {
+            Motion::Keyframes { keyframes, looped } => {
+                if keyframes.is_empty() || (*looped && keyframes.len() < 2) {
+                    return Err(ConfigError::InvalidKinematicBody(
+                        "looped schedules need at least 2 keyframes, others at least 1",
+                    ));
+                }
+                if keyframes.iter().any(|k| {
+                    !k.time.is_finite()
+                        || !k.rotation.is_finite()
+                        || !k.position.x.is_finite()
+                        || !k.position.y.is_finite()
+                }) {
+                    return Err(ConfigError::InvalidKinematicBody(
+                        "keyframes must be finite",
+                    ));
+                }
+                if keyframes
+                    .windows(2)
+                    .any(|pair| pair[1].time <= pair[0].time)
+                {
+                    return Err(ConfigError::InvalidKinematicBody(
+                        "keyframe times must be strictly increasing",
+                    ));
+                }
+            }
+            Motion::Oscillator {
+                centre,
+                rotation,
+                amplitude,
+                angular_amplitude,
+                frequency,
+                phase,
+                spin,
+            } => {
+                let values = [
+                    centre.x,
+                    centre.y,
+                    *rotation,
+                    amplitude.x,
+                    amplitude.y,
+                    *angular_amplitude,
+                    *frequency,
+                    *phase,
+                    *spin,
+                ];
+                if values.iter().any(|value| !value.is_finite()) {
+                    return Err(ConfigError::InvalidKinematicBody(
+                        "oscillator parameters must be finite",
+                    ));
+                }
+                if *frequency < 0.0 {
+                    return Err(ConfigError::InvalidKinematicBody(
+                        "oscillator frequency must not be negative",
+                    ));
+                }
+            }
+        }
+
+        self.shape.validate()
+    }
+
+    /// Pose and velocity at simulation time `time`.
+    pub fn state(&self, time: f32) -> BodyState {
+        match &self.motion {
+            Motion::Keyframes { keyframes, looped } => keyframe_state(keyframes, *looped, time),
+            Motion::Oscillator {
+                centre,
+                rotation,
+                amplitude,
+                angular_amplitude,
+                frequency,
+                phase,
+                spin,
+            } => {
+                let angle = TAU * frequency * time + phase;
+                let (sin, cos) = libm::sincosf(angle);
+                let rate = TAU * frequency * cos;
+
+                BodyState {
+                    position: *centre + *amplitude * sin,
+                    rotation: rotation + angular_amplitude * sin + spin * time,
+                    velocity: *amplitude * rate,
+                    angular_velocity: angular_amplitude * rate + spin,
+                }
+            }
+        }
+    }
+
+    /// The body's shape in world coordinates at simulation time `time`.
+    pub fn shape_at(&self, time: f32) -> Shape {
+        let state = self.state(time);
+        self.shape.transformed(state.position, state.rotation)
+    }
+
+    pub(crate) fn collide(
+        &self,
+        state: &BodyState,
+        position: &mut Vector2D,
+        velocity: &mut Vector2D,
+        particle_radius: f32,
+    ) {
+        collide_moving(
+            &self.shape,
+            state,
+            position,
+            velocity,
+            particle_radius,
+            self.restitution,
+            self.friction,
+        );
+    }
+}
+
+/// Like [`crate::obstacles::Obstacle::collide`], but against `shape` posed at
+/// `state` and relative to its moving surface, so the body drags and pushes
+/// the particles it touches.
+pub(crate) fn collide_moving(
+    shape: &Shape,
+    state: &BodyState,
+    position: &mut Vector2D,
+    velocity: &mut Vector2D,
+    particle_radius: f32,
+    restitution: f32,
+    friction: f32,
+) {
+    let local = (*position - state.position).rotate(-state.rotation);
+    let (distance, normal) = shape.signed_distance(local);
+    if distance >= particle_radius {
+        return;
+    }
+
+    let surface_velocity = state.point_velocity(*position);
+    let mut relative_velocity = *velocity - surface_velocity;
+    resolve_contact(
+        position,
+        &mut relative_velocity,
+        particle_radius - distance,
+        normal.rotate(state.rotation),
+        restitution,
+        friction,
+    );
+    *velocity = relative_velocity + surface_velocity;
+}
+
+fn keyframe_state(keyframes: &[Keyframe], looped: bool, time: f32) -> BodyState {
+    let first = keyframes[0];
+    let last = keyframes[keyframes.len() - 1];
+
+    let time = if looped {
+        first.time + (time - first.time).rem_euclid(last.time - first.time)
+    } else {
+        time
+    };
+
+    let held = |keyframe: Keyframe| BodyState {
+        position: keyframe.position,
+        rotation: keyframe.rotation,
+        velocity: Vector2D::new(0.0, 0.0),
+        angular_velocity: 0.0,
+    };
+
+    if time <= first.time {
+        return held(first);
+    }
+    if time >= last.time {
+        return held(last);
+    }
+
+    // Keyframes are sorted, so the segment is the first pair ending after `time`
+    let pair = keyframes
+        .windows(2)
+        .find(|pair| time < pair[1].time)
+        .unwrap();
+    let (start, end) = (pair[0], pair[1]);
+    let duration = end.time - start.time;
+    let t = (time - start.time) / duration;
+
+    BodyState {
+        position: start.position + (end.position - start.position) * t,
+        rotation: start.rotation + (end.rotation - start.rotation) * t,
+        velocity: (end.position - start.position) / duration,
+        angular_velocity: (end.rotation - start.rotation) / duration,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{a} != {b}");
+    }
+
+    fn piston(looped: bool) -> KinematicBody {
+        KinematicBody {
+            id: 0,
+            shape: Shape::OrientedBox {
+                centre: Vector2D::new(0.0, 0.0),
+                half_extents: Vector2D::new(0.1, 1.0),
+                rotation: 0.0,
+            },
+            motion: Motion::Keyframes {
+                keyframes: vec![
+                    Keyframe {
+                        time: 0.0,
+                        position: Vector2D::new(-1.0, 0.0),
+                        rotation: 0.0,
+                    },
+                    Keyframe {
+                        time: 2.0,
+                        position: Vector2D::new(1.0, 0.0),
+                        rotation: 0.0,
+                    },
+                ],
+                looped,
+            },
+            restitution: 0.0,
+            friction: 0.0,
+        }
+    }
+
+    #[test]
+    fn keyframes_interpolate_and_hold() {
+        let state = piston(false).state(0.5);
+        assert_close(state.position.x, -0.5);
+        assert_close(state.velocity.x, 1.0);
+
+        let state = piston(false).state(3.0);
+        assert_close(state.position.x, 1.0);
+        assert_eq!(state.velocity, Vector2D::new(0.0, 0.0));
+
+        let state = piston(true).state(3.0);
+        assert_close(state.position.x, 0.0);
+        assert_close(state.velocity.x, 1.0);
+    }
+
+    #[test]
+    fn oscillator_velocity_matches_pose() {
+        let body = KinematicBody {
+            motion: Motion::Oscillator {
+                centre: Vector2D::new(0.5, 0.0),
+                rotation: 0.0,
+                amplitude: Vector2D::new(1.0, 0.0),
+                angular_amplitude: 0.2,
+                frequency: 0.5,
+                phase: 0.3,
+                spin: 1.0,
+            },
+            ..piston(false)
+        };
+
+        let h = 1e-3;
+        let before = body.state(0.7 - h);
+        let after = body.state(0.7 + h);
+        let state = body.state(0.7);
+
+        assert!(
+            ((after.position.x - before.position.x) / (2.0 * h) - state.velocity.x).abs() < 1e-2
+        );
+        assert!(
+            ((after.rotation - before.rotation) / (2.0 * h) - state.angular_velocity).abs() < 1e-2
+        );
+    }
+
+    #[test]
+    fn moving_wall_pushes_particle() {
+        let body = piston(false);
+        let state = body.state(1.0);
+
+        // Particle at rest just inside the right face of the piston at x = 0.1
+        let mut position = Vector2D::new(0.12, 0.0);
+        let mut velocity = Vector2D::new(0.0, 0.0);
+        body.collide(&state, &mut position, &mut velocity, 0.05);
+
+        assert_close(position.x, 0.15);
+        assert_close(velocity.x, 1.0);
+    }
+}
@@ -0,0 +1,550 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ConfigError, rng::Rng, vector_2d::Vector2D};
+
+// Most lattice points or Poisson-disk cells a layout walks in one pass
+const MAX_LATTICE_POINTS: usize = 1 << 20;
+
+/// Initial particle placement used by [`crate::Sim2D::new`] and
+/// [`crate::Sim2D::reset_sim`].
+///
+/// Sizes and positions are fractions of the world box so a layout still
+/// makes sense after the canvas is resized. Positions are measured from the
+/// centre of the box, so `x` and `y` lie in `[-0.5, 0.5]`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum InitialLayout {
+    /// Evenly spaced grid filling the whole box.
+    #[default]
+    Grid,
+    /// Block of fluid held in the bottom-left corner.
+    DamBreak { width: f32, height: f32 },
+    /// Disc of fluid above an optional resting pool.
+    Droplet {
+        x: f32,
+        y: f32,
+        radius: f32,
+        pool_depth: f32,
+    },
+    /// Two columns against the side walls that collapse into each other.
+    TwoColumns { width: f32, height: f32 },
+    /// Full grid with each particle displaced by up to `amplitude` of the
+    /// grid spacing.
+    Jitter { amplitude: f32, seed: u64 },
+    /// Blue-noise fill of the whole box.
+    PoissonDisk { seed: u64 },
+    /// Fluid wherever the mask value is at least 128. The mask is stored row
+    /// by row from the top, like image data, and is stretched over the box.
+    Mask {
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+    },
+}
+
+impl InitialLayout {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let unit = |value: f32| value > 0.0 && value <= 1.0;
+
+        match self {
+            InitialLayout::Grid | InitialLayout::PoissonDisk { .. } => Ok(()),
+            InitialLayout::DamBreak { width, height } => {
+                if unit(*width) && unit(*height) {
+                    Ok(())
+                } else {
+                    Err(ConfigError::InvalidLayout(
+                        "dam break width and height must be in (0, 1]",
+                    ))
+                }
+            }
+            InitialLayout::Droplet {
+                x,
+                y,
+                radius,
+                pool_depth,
+            } => {
+                if !(-0.5..=0.5).contains(x) || !(-0.5..=0.5).contains(y) {
+                    Err(ConfigError::InvalidLayout(
+                        "droplet centre must be in [-0.5, 0.5]",
+                    ))
+                } else if !unit(*radius) {
+                    Err(ConfigError::InvalidLayout(
+                        "droplet radius must be in (0, 1]",
+                    ))
+                } else if !(0.0..1.0).contains(pool_depth) {
+                    Err(ConfigError::InvalidLayout("pool depth must be in [0, 1)"))
+                } else {
+                    Ok(())
+                }
+            }
+            InitialLayout::TwoColumns { width, height } => {
+                if *width > 0.0 && *width < 0.5 && unit(*height) {
+                    Ok(())
+                } else {
+                    Err(ConfigError::InvalidLayout(
+                        "column width must be in (0, 0.5) and height in (0, 1]",
+                    ))
+                }
+            }
+            InitialLayout::Jitter { amplitude, .. } => {
+                if (0.0..=1.0).contains(amplitude) {
+                    Ok(())
+                } else {
+                    Err(ConfigError::InvalidLayout(
+                        "jitter amplitude must be in [0, 1]",
+                    ))
+                }
+            }
+            InitialLayout::Mask {
+                width,
+                height,
+                data,
+            } => {
+                if *width == 0 || *height == 0 || width * height != data.len() {
+                    Err(ConfigError::InvalidLayout(
+                        "mask data must contain width * height values",
+                    ))
+                } else if !data.iter().any(|value| *value >= 128) {
+                    Err(ConfigError::InvalidLayout("mask is empty"))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Fails if the layout's region can't hold `particle_count` particles
+    /// without packing them closer than `particle_radius`.
+    pub fn validate_fit(
+        &self,
+        particle_count: usize,
+        world_dimensions: Vector2D,
+        particle_radius: f32,
+    ) -> Result<(), ConfigError> {
+        match self {
+            // Grids space themselves to fit any count
+            InitialLayout::Grid | InitialLayout::Jitter { .. } => Ok(()),
+            _ => self
+                .generate(particle_count, world_dimensions, particle_radius)
+                .map(|_| ()),
+        }
+    }
+
+    /// Places exactly `particle_count` particles inside the box, keeping
+    /// `particle_radius` clear of the walls. Fails, as
+    /// [`InitialLayout::validate_fit`] does, if the region is too small.
+    pub fn generate(
+        &self,
+        particle_count: usize,
+        world_dimensions: Vector2D,
+        particle_radius: f32,
+    ) -> Result<Vec<Vector2D>, ConfigError> {
+        if particle_count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let half = world_dimensions * 0.5 - Vector2D::new(particle_radius, particle_radius);
+        let min = half * -1.0;
+        let size = half * 2.0;
+
+        match self {
+            InitialLayout::Grid => Ok(grid(particle_count, half)),
+            InitialLayout::DamBreak { width, height } => {
+                let max = min + Vector2D::new(size.x * width, size.y * height);
+                fill_region(particle_count, min, max, particle_radius, |_| true)
+            }
+            InitialLayout::Droplet {
+                x,
+                y,
+                radius,
+                pool_depth,
+            } => {
+                let centre = Vector2D::new(x * size.x, y * size.y);
+                let radius = radius * size.y;
+                let pool_top = min.y + pool_depth * size.y;
+
+                fill_region(particle_count, min, half, particle_radius, |p| {
+                    let offset = p - centre;
+                    offset.dot(offset) <= radius * radius || p.y <= pool_top
+                })
+            }
+            InitialLayout::TwoColumns { width, height } => {
+                let max = Vector2D::new(half.x, min.y + size.y * height);
+                let column_width = width * size.x;
+
+                fill_region(particle_count, min, max, particle_radius, |p| {
+                    p.x <= min.x + column_width || p.x >= half.x - column_width
+                })
+            }
+            InitialLayout::Jitter { amplitude, seed } => {
+                let mut rng = Rng::new(*seed);
+                let spacing = (size.x * size.y / particle_count as f32).sqrt();
+                let max_offset = 0.5 * amplitude * spacing;
+
+                Ok(grid(particle_count, half)
+                    .into_iter()
+                    .map(|p| {
+                        let offset = Vector2D::new(
+                            rng.range(-max_offset, max_offset),
+                            rng.range(-max_offset, max_offset),
+                        );
+                        let p = p + offset;
+                        Vector2D::new(p.x.clamp(min.x, half.x), p.y.clamp(min.y, half.y))
+                    })
+                    .collect())
+            }
+            InitialLayout::PoissonDisk { seed } => poisson_disk(
+                particle_count,
+                min,
+                half,
+                particle_radius,
+                &mut Rng::new(*seed),
+            ),
+            InitialLayout::Mask {
+                width,
+                height,
+                data,
+            } => {
+                // Only the lattice over the set pixels is walked, so a small
+                // shape in a large image stays cheap
+                let (mut first, mut last) = ((*width, *height), (0, 0));
+                for (i, _) in data.iter().enumerate().filter(|(_, value)| **value >= 128) {
+                    let (col, row) = (i % width, i / width);
+                    first = (first.0.min(col), first.1.min(row));
+                    last = (last.0.max(col), last.1.max(row));
+                }
+                if first.0 > last.0 {
+                    return Err(ConfigError::InvalidLayout("mask is empty"));
+                }
+
+                let pixel = Vector2D::new(size.x / *width as f32, size.y / *height as f32);
+                let region_min = Vector2D::new(
+                    min.x + first.0 as f32 * pixel.x,
+                    (half.y - (last.1 + 1) as f32 * pixel.y).max(min.y),
+                );
+                let region_max = Vector2D::new(
+                    (min.x + (last.0 + 1) as f32 * pixel.x).min(half.x),
+                    half.y - first.1 as f32 * pixel.y,
+                );
+
+                fill_region(
+                    particle_count,
+                    region_min,
+                    region_max,
+                    particle_radius,
+                    |p| {
+                        let u = (p.x - min.x) / size.x;
+                        let v = (half.y - p.y) / size.y;
+                        let col = ((u * *width as f32) as usize).min(width - 1);
+                        let row = ((v * *height as f32) as usize).min(height - 1);
+                        data[row * width + col] >= 128
+                    },
+                )
+            }
+        }
+    }
+}
+
+const REGION_TOO_SMALL: ConfigError =
+    ConfigError::InvalidLayout("region is too small for the particle count");
+
+// Closest a layout packs particles over [min, max]: a particle radius, or
+// wider if the lattice at that spacing would be too big to walk
+fn min_spacing(min: Vector2D, max: Vector2D, particle_radius: f32) -> f32 {
+    let size = max - min;
+    particle_radius.max((size.x * size.y / MAX_LATTICE_POINTS as f32).sqrt())
+}
+
+// Share of the box [min, min + size] that `contains`, from a coarse lattice
+fn covered_fraction(min: Vector2D, size: Vector2D, contains: impl Fn(Vector2D) -> bool) -> f32 {
+    const SAMPLES: usize = 64;
+
+    let hits = (0..SAMPLES * SAMPLES)
+        .filter(|i| {
+            let u = (i % SAMPLES) as f32 + 0.5;
+            let v = (i / SAMPLES) as f32 + 0.5;
+            contains(min + Vector2D::new(u * size.x, v * size.y) / SAMPLES as f32)
+        })
+        .count();
+    hits as f32 / (SAMPLES * SAMPLES) as f32
+}
+
+fn grid(particle_count: usize, half: Vector2D) -> Vec<Vector2D> {
+    let mut positions: Vec<Vector2D> = Vec::with_capacity(particle_count);
+
+    // Calculate grid dimensions
+    let particles_per_row = (particle_count as f32).sqrt().ceil() as usize;
+    let particles_per_col = particle_count.div_ceil(particles_per_row);
+
+    // Calculate spacing between particles
+    let spacing_x = (2.0 * half.x) / (particles_per_row - 1).max(1) as f32;
+    let spacing_y = (2.0 * half.y) / (particles_per_col - 1).max(1) as f32;
+
+    let mut particle_index = 0;
+    for row in 0..particles_per_col {
+        for col in 0..particles_per_row {
+            if particle_index >= particle_count {
+                break;
+            }
+
+            let x = -half.x + col as f32 * spacing_x;
+            let y = -half.y + row as f32 * spacing_y;
+            positions.push(Vector2D::new(x, y));
+
+            particle_index += 1;
+        }
+    }
+
+    positions
+}
+
+// Square lattice over [min, max] restricted to `contains`, filled from the
+// bottom row up. The spacing starts from the region's measured area and
+// shrinks until the region holds every particle, giving up at the minimum
+// spacing.
+fn fill_region(
+    particle_count: usize,
+    min: Vector2D,
+    max: Vector2D,
+    particle_radius: f32,
+    contains: impl Fn(Vector2D) -> bool,
+) -> Result<Vec<Vector2D>, ConfigError> {
+    let size = max - min;
+    let min_spacing = min_spacing(min, max, particle_radius);
+    let area = size.x * size.y * covered_fraction(min, size, &contains);
+    let mut spacing = (area / particle_count as f32).sqrt().max(min_spacing);
+
+    loop {
+        let cols = (size.x / spacing) as usize + 1;
+        let rows = (size.y / spacing) as usize + 1;
+        let margin = Vector2D::new(
+            0.5 * (size.x - (cols - 1) as f32 * spacing),
+            0.5 * (size.y - (rows - 1) as f32 * spacing),
+        );
+
+        let mut positions = Vec::with_capacity(particle_count);
+        for row in 0..rows {
+            for col in 0..cols {
+                let p = min + margin + Vector2D::new(col as f32, row as f32) * spacing;
+                if contains(p) {
+                    positions.push(p);
+                    if positions.len() == particle_count {
+                        return Ok(positions);
+                    }
+                }
+            }
+        }
+
+        if spacing <= min_spacing {
+            return Err(REGION_TOO_SMALL);
+        }
+        spacing = (spacing * 0.95).max(min_spacing);
+    }
+}
+
+// Bridson's algorithm. The disk radius is estimated from the packing density
+// of a maximal Poisson-disk set and reduced until enough points fit, giving
+// up at the minimum spacing.
+fn poisson_disk(
+    particle_count: usize,
+    min: Vector2D,
+    max: Vector2D,
+    particle_radius: f32,
+    rng: &mut Rng,
+) -> Result<Vec<Vector2D>, ConfigError> {
+    const ATTEMPTS: usize = 30;
+
+    let size = max - min;
+    let min_radius = min_spacing(min, max, particle_radius);
+    let mut radius = (0.7 * size.x * size.y / particle_count as f32)
+        .sqrt()
+        .max(min_radius);
+
+    loop {
+        let cell_size = radius / 2f32.sqrt();
+        let cols = (size.x / cell_size) as usize + 1;
+        let rows = (size.y / cell_size) as usize + 1;
+        let cell_of = |p: Vector2D| {
+            let col = (((p.x - min.x) / cell_size) as usize).min(cols - 1);
+            let row = (((p.y - min.y) / cell_size) as usize).min(rows - 1);
+            (col, row)
+        };
+
+        let mut cells: Vec<Option<usize>> = vec![None; cols * rows];
+        let mut points = Vec::with_capacity(particle_count);
+        let mut active = Vec::new();
+
+        let first = Vector2D::new(rng.range(min.x, max.x), rng.range(min.y, max.y));
+        let (col, row) = cell_of(first);
+        cells[row * cols + col] = Some(0);
+        points.push(first);
+        active.push(0);
+
+        while !active.is_empty() && points.len() < particle_count {
+            let active_index = (rng.next_u64() % active.len() as u64) as usize;
+            let origin = points[active[active_index]];
+
+            let candidate = (0..ATTEMPTS).find_map(|_| {
+                let angle = rng.range(0.0, 2.0 * PI);
+                let distance = rng.range(radius, 2.0 * radius);
+                let (sin, cos) = libm::sincosf(angle);
+                let p = origin + Vector2D::new(cos, sin) * distance;
+
+                if p.x < min.x || p.x > max.x || p.y < min.y || p.y > max.y {
+                    return None;
+                }
+
+                let (col, row) = cell_of(p);
+                let too_close = (row.saturating_sub(2)..(row + 3).min(rows)).any(|r| {
+                    (col.saturating_sub(2)..(col + 3).min(cols)).any(|c| {
+                        cells[r * cols + c].is_some_and(|i| {
+                            let offset = points[i] - p;
+                            offset.dot(offset) < radius * radius
+                        })
+                    })
+                });
+
+                (!too_close).then_some((p, col, row))
+            });
+
+            match candidate {
+                Some((p, col, row)) => {
+                    cells[row * cols + col] = Some(points.len());
+                    active.push(points.len());
+                    points.push(p);
+                }
+                None => {
+                    active.swap_remove(active_index);
+                }
+            }
+        }
+
+        if points.len() == particle_count {
+            return Ok(points);
+        }
+
+        if radius <= min_radius {
+            return Err(REGION_TOO_SMALL);
+        }
+        radius = (radius * 0.95).max(min_radius);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD: Vector2D = Vector2D { x: 8.0, y: 4.0 };
+    const RADIUS: f32 = 0.025;
+
+    fn inside_box(positions: &[Vector2D]) -> bool {
+        let half = WORLD * 0.5 - Vector2D::new(RADIUS, RADIUS);
+        positions
+            .iter()
+            .all(|p| p.x.abs() <= half.x + 1e-4 && p.y.abs() <= half.y + 1e-4)
+    }
+
+    #[test]
+    fn layouts_place_every_particle_inside_box() {
+        let layouts = [
+            InitialLayout::Grid,
+            InitialLayout::DamBreak {
+                width: 0.4,
+                height: 0.8,
+            },
+            InitialLayout::Droplet {
+                x: 0.0,
+                y: 0.2,
+                radius: 0.15,
+                pool_depth: 0.2,
+            },
+            InitialLayout::TwoColumns {
+                width: 0.2,
+                height: 0.9,
+            },
+            InitialLayout::Jitter {
+                amplitude: 0.5,
+                seed: 7,
+            },
+            InitialLayout::PoissonDisk { seed: 7 },
+            InitialLayout::Mask {
+                width: 2,
+                height: 2,
+                data: vec![0, 255, 0, 255],
+            },
+        ];
+
+        for layout in layouts {
+            assert_eq!(layout.validate(), Ok(()));
+
+            let positions = layout.generate(500, WORLD, RADIUS).unwrap();
+            assert_eq!(positions.len(), 500, "{layout:?}");
+            assert!(inside_box(&positions), "{layout:?}");
+        }
+    }
+
+    #[test]
+    fn dam_break_stays_in_corner() {
+        let positions = InitialLayout::DamBreak {
+            width: 0.25,
+            height: 0.5,
+        }
+        .generate(300, WORLD, RADIUS)
+        .unwrap();
+
+        assert!(positions.iter().all(|p| p.x <= -2.0 && p.y <= 0.0));
+    }
+
+    #[test]
+    fn mask_fills_only_set_pixels() {
+        // Right half of the image is fluid
+        let positions = InitialLayout::Mask {
+            width: 2,
+            height: 1,
+            data: vec![0, 255],
+        }
+        .generate(300, WORLD, RADIUS)
+        .unwrap();
+
+        assert!(positions.iter().all(|p| p.x >= 0.0));
+    }
+
+    #[test]
+    fn single_pixel_mask_only_fits_a_few_particles() {
+        let mut data = vec![0; 1000 * 1000];
+        data[500 * 1000 + 500] = 255;
+        let layout = InitialLayout::Mask {
+            width: 1000,
+            height: 1000,
+            data,
+        };
+        assert_eq!(layout.validate(), Ok(()));
+
+        assert_eq!(layout.generate(500, WORLD, RADIUS), Err(REGION_TOO_SMALL));
+        assert_eq!(
+            layout.validate_fit(500, WORLD, RADIUS),
+            Err(REGION_TOO_SMALL)
+        );
+
+        // The pixel still holds one particle, at its centre
+        let positions = layout.generate(1, WORLD, RADIUS).unwrap();
+        assert_eq!(positions.len(), 1);
+        assert!(positions[0].x.abs() < 0.01 && positions[0].y.abs() < 0.01);
+    }
+
+    #[test]
+    fn poisson_disk_is_seeded() {
+        let layout = InitialLayout::PoissonDisk { seed: 3 };
+
+        assert_eq!(
+            layout.generate(200, WORLD, RADIUS).unwrap(),
+            layout.generate(200, WORLD, RADIUS).unwrap()
+        );
+    }
+}
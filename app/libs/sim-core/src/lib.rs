@@ -1,51 +1,142 @@
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod config;
+pub mod dye;
+pub mod flow;
 mod hashing;
-mod kernels;
+pub mod kernels;
+pub mod kinematics;
+pub mod layout;
+mod neighbours;
+pub mod obstacles;
+pub mod phases;
+pub mod rigid_bodies;
+mod rng;
+pub mod sdf;
+mod simd;
+pub mod snapshot;
+pub mod solver;
+pub mod spatial;
+pub mod thermal;
 pub mod vector_2d;
-
+pub mod viscosity;
+
+use crate::config::{ConfigError, SimConfig};
+use crate::dye::{DYE_CHANNELS, Dye, DyeBrush};
+use crate::flow::{Emitter, EmitterState, Sink};
+use crate::kinematics::{BodyState, KinematicBody};
+use crate::layout::InitialLayout;
+use crate::neighbours::{Neighbour, NeighbourList};
+use crate::obstacles::Obstacle;
+use crate::phases::{Material, Phase, PhaseParameters, initial_phase};
+use crate::rigid_bodies::RigidBody;
+use crate::sdf::{Boundary, SignedDistanceField};
+use crate::simd::{PressureSample, density_sums, pressure_force};
+use crate::solver::{ExplicitSolver, Solver, SolverKind, Substepping};
+use crate::spatial::{SpatialIndex, SpatialIndexKind};
+use crate::thermal::HeatSource;
 use crate::vector_2d::Vector2D;
+use crate::viscosity::ViscosityModel;
 use crate::{
-    hashing::{GRID_OFFSETS, get_cell, hash_cell, key_from_hash},
-    kernels::{
-        density_kernel, density_kernel_derivative, near_density_kernel,
-        near_density_kernel_derivative, viscosity_kernel,
-    },
+    hashing::{CHECKSUM_SEED, checksum_bytes},
+    kernels::{Kernel, KernelKind, cohesion_kernel},
 };
-use std::collections::HashMap;
 
 use rayon::prelude::*;
 
 pub struct Sim2D {
     // Fixed parameters
-    particle_count: usize,
+    initial_particle_count: usize,
+    max_particle_count: usize,
     particle_radius: f32,
-
-    // Updated every step
+    layout: InitialLayout,
+    solver: Box<dyn Solver>,
+    substepping: Substepping,
+    deterministic: bool,
+    density_kernel: KernelKind,
+    viscosity_kernel: KernelKind,
+
+    // Updated every step. Particle buffers hold `max_particle_count` up
+    // front, so emitters and sinks never move them.
+    time: f32,
+    substeps: u32,
+    particle_count: usize,
+    // Follows each particle as the buffers are sorted and emptied
+    particle_ids: Vec<u32>,
+    next_particle_id: u32,
     positions: Vec<Vector2D>,
     predicted_positions: Vec<Vector2D>,
     velocities: Vec<Vector2D>,
     velocity_magnitudes: Vec<f32>,
+    vorticities: Vec<f32>,
+    strain_rates: Vec<f32>,
+    temperatures: Vec<f32>,
+    heat_rates: Vec<f32>,
+    dyes: Vec<Dye>,
+    dye_rates: Vec<Dye>,
+    particle_phases: Vec<u8>,
     densities: Vec<(f32, f32)>,
     pressure_forces: Vec<Vector2D>,
     viscous_forces: Vec<Vector2D>,
+    surface_normals: Vec<Vector2D>,
 
-    // Spatial hashing
-    cell_keys: Vec<(usize, usize)>,
-    cell_offsets: HashMap<usize, usize>,
+    // Neighbour search, with the neighbours found once per substep
+    spatial_index: Box<dyn SpatialIndex>,
+    neighbours: NeighbourList,
 
     // Updated when canvas size changes
     world_dimensions: Vector2D,
 
+    // Static geometry
+    obstacles: Vec<Obstacle>,
+    next_obstacle_id: u32,
+    boundary: Option<Boundary>,
+    boundary_field: Option<SignedDistanceField>,
+
+    // Scripted geometry
+    kinematic_bodies: Vec<KinematicBody>,
+    next_kinematic_body_id: u32,
+
+    // Dynamic geometry, with the force and torque the fluid exerts each substep
+    rigid_bodies: Vec<RigidBody>,
+    rigid_body_states: Vec<BodyState>,
+    rigid_body_forces: Vec<(Vector2D, f32)>,
+    next_rigid_body_id: u32,
+
+    // Open flow
+    emitters: Vec<Emitter>,
+    emitter_states: Vec<EmitterState>,
+    next_emitter_id: u32,
+    sinks: Vec<Sink>,
+    next_sink_id: u32,
+
+    // Fixed temperature regions
+    heat_sources: Vec<HeatSource>,
+    next_heat_source_id: u32,
+
+    // Fluids, with solver constants per particle phase refreshed every step
+    phases: Vec<Phase>,
+    phase_parameters: Vec<PhaseParameters>,
+
     // Parameters
     gravity: f32,
     target_density: f32,
     pressure_multiplier: f32,
-    near_pressure_multiplier: f32,
     viscosity_strength: f32,
+    viscosity_model: ViscosityModel,
     collision_damping: f32,
+    interface_tension: f32,
+    surface_tension: f32,
+    vorticity_confinement: f32,
+    ambient_temperature: f32,
+    thermal_diffusivity: f32,
+    thermal_expansion: f32,
+    dye_diffusivity: f32,
 
     // User interaction
     interaction_position: Option<Vector2D>,
     interaction_scale: f32,
+    dye_brush: Option<DyeBrush>,
     interaction_strength: f32,
     interaction_radius: f32,
 
@@ -54,104 +145,233 @@ pub struct Sim2D {
 }
 
 impl Sim2D {
-    #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        particle_count: usize,
-        particle_radius: f32,
-        world_width: f32,
-        world_height: f32,
-        gravity: f32,
-        target_density: f32,
-        pressure_multiplier: f32,
-        viscosity_strength: f32,
-        smoothing_radius: f32,
-        interaction_strength: f32,
-        interaction_radius: f32,
-    ) -> Self {
-        let mut positions: Vec<Vector2D> = Vec::with_capacity(particle_count);
-
-        let half_w = 0.5 * world_width - particle_radius;
-        let half_h = 0.5 * world_height - particle_radius;
-
-        // Calculate grid dimensions
-        let particles_per_row = (particle_count as f32).sqrt().ceil() as usize;
-        let particles_per_col = particle_count.div_ceil(particles_per_row);
-
-        // Calculate spacing between particles
-        let spacing_x = (2.0 * half_w) / (particles_per_row - 1).max(1) as f32;
-        let spacing_y = (2.0 * half_h) / (particles_per_col - 1).max(1) as f32;
-
-        let mut particle_index = 0;
-        for row in 0..particles_per_col {
-            for col in 0..particles_per_row {
-                if particle_index >= particle_count {
-                    break;
-                }
-
-                let x = -half_w + col as f32 * spacing_x;
-                let y = -half_h + row as f32 * spacing_y;
-                positions.push(Vector2D::new(x, y));
-
-                particle_index += 1;
-            }
-        }
-
-        let velocities = vec![Vector2D::new(0.0, 0.0); particle_count];
-        let velocity_magnitudes = vec![0.0; particle_count];
-        let densities = vec![(0.0, 0.0); particle_count];
-        let pressure_forces = vec![Vector2D::new(0.0, 0.0); particle_count];
-        let viscous_forces = vec![Vector2D::new(0.0, 0.0); particle_count];
+    pub fn new(config: SimConfig) -> Self {
+        let positions = config
+            .layout
+            .generate(
+                config.particle_count,
+                Vector2D::new(config.world_width, config.world_height),
+                config.particle_radius,
+            )
+            .expect("a valid config's layout fits its particles");
+        let velocities = vec![Vector2D::new(0.0, 0.0); config.particle_count];
+        let particle_phases = positions
+            .iter()
+            .map(|position| initial_phase(&config.phases, *position))
+            .collect();
+
+        let mut sim = Self::from_particles(config, positions, velocities, particle_phases);
+        sim.step(0.0);
+        sim
+    }
 
-        let mut sim = Self {
-            particle_count,
+    // Builds a simulation around existing particle state without stepping it
+    fn from_particles(
+        config: SimConfig,
+        mut positions: Vec<Vector2D>,
+        mut velocities: Vec<Vector2D>,
+        mut particle_phases: Vec<u8>,
+    ) -> Self {
+        let max_particle_count = config.particle_capacity();
+        let SimConfig {
+            particle_count: initial_particle_count,
+            max_particle_count: _,
             particle_radius,
-
-            positions: positions.clone(),
-            predicted_positions: positions,
+            world_width,
+            world_height,
+            gravity,
+            target_density,
+            pressure_multiplier,
+            viscosity_strength,
+            viscosity_model,
+            smoothing_radius,
+            interaction_strength,
+            interaction_radius,
+            collision_damping,
+            interface_tension,
+            surface_tension,
+            vorticity_confinement,
+            ambient_temperature,
+            thermal_diffusivity,
+            thermal_expansion,
+            dye_diffusivity,
+            solver,
+            substepping,
+            deterministic,
+            spatial_index,
+            density_kernel,
+            viscosity_kernel,
+            layout,
+            obstacles,
+            boundary,
+            kinematic_bodies,
+            rigid_bodies,
+            emitters,
+            sinks,
+            heat_sources,
+            phases,
+        } = config;
+
+        let particle_count = positions.len();
+
+        let mut particle_ids = Vec::with_capacity(max_particle_count.max(particle_count));
+        particle_ids.extend(0..particle_count as u32);
+        let mut predicted_positions = Vec::with_capacity(max_particle_count.max(particle_count));
+        predicted_positions.extend_from_slice(&positions);
+        let mut velocity_magnitudes = Vec::with_capacity(max_particle_count.max(particle_count));
+        velocity_magnitudes.extend(velocities.iter().map(|v| v.magnitude()));
+        positions.reserve_exact(max_particle_count.saturating_sub(particle_count));
+        velocities.reserve_exact(max_particle_count.saturating_sub(particle_count));
+        particle_phases.reserve_exact(max_particle_count.saturating_sub(particle_count));
+
+        let world_dimensions = Vector2D::new(world_width, world_height);
+        let boundary_field = boundary
+            .as_ref()
+            .map(|b| SignedDistanceField::from_boundary(b, world_dimensions));
+
+        Self {
+            initial_particle_count,
+            max_particle_count,
+            particle_radius,
+            layout,
+            solver: solver.build(),
+            substepping,
+            deterministic,
+            density_kernel,
+            viscosity_kernel,
+
+            time: 0.0,
+            substeps: 0,
+            particle_count,
+            particle_ids,
+            next_particle_id: particle_count as u32,
+            predicted_positions,
+            positions,
             velocities,
             velocity_magnitudes,
-            densities,
-            pressure_forces,
-            viscous_forces,
-
-            cell_keys: vec![(0, 0); particle_count],
-            cell_offsets: HashMap::new(),
-
-            world_dimensions: Vector2D::new(world_width, world_height),
+            vorticities: particle_buffer(0.0, particle_count, max_particle_count),
+            strain_rates: particle_buffer(0.0, particle_count, max_particle_count),
+            temperatures: particle_buffer(ambient_temperature, particle_count, max_particle_count),
+            heat_rates: particle_buffer(0.0, particle_count, max_particle_count),
+            dyes: particle_buffer([0.0; DYE_CHANNELS], particle_count, max_particle_count),
+            dye_rates: particle_buffer([0.0; DYE_CHANNELS], particle_count, max_particle_count),
+            particle_phases,
+            densities: particle_buffer((0.0, 0.0), particle_count, max_particle_count),
+            pressure_forces: particle_buffer(
+                Vector2D::new(0.0, 0.0),
+                particle_count,
+                max_particle_count,
+            ),
+            viscous_forces: particle_buffer(
+                Vector2D::new(0.0, 0.0),
+                particle_count,
+                max_particle_count,
+            ),
+            surface_normals: particle_buffer(
+                Vector2D::new(0.0, 0.0),
+                particle_count,
+                max_particle_count,
+            ),
+
+            spatial_index: spatial_index.build(),
+            neighbours: NeighbourList::default(),
+
+            world_dimensions,
+
+            next_obstacle_id: obstacles.iter().map(|o| o.id + 1).max().unwrap_or(0),
+            obstacles,
+            boundary,
+            boundary_field,
+
+            next_kinematic_body_id: kinematic_bodies.iter().map(|b| b.id + 1).max().unwrap_or(0),
+            kinematic_bodies,
+
+            rigid_body_states: rigid_bodies.iter().map(|b| b.initial_state()).collect(),
+            rigid_body_forces: vec![(Vector2D::new(0.0, 0.0), 0.0); rigid_bodies.len()],
+            next_rigid_body_id: rigid_bodies.iter().map(|b| b.id + 1).max().unwrap_or(0),
+            rigid_bodies,
+
+            emitter_states: vec![EmitterState::default(); emitters.len()],
+            next_emitter_id: emitters.iter().map(|e| e.id + 1).max().unwrap_or(0),
+            emitters,
+            next_sink_id: sinks.iter().map(|s| s.id + 1).max().unwrap_or(0),
+            sinks,
+
+            next_heat_source_id: heat_sources.iter().map(|s| s.id + 1).max().unwrap_or(0),
+            heat_sources,
+
+            phases,
+            phase_parameters: Vec::new(),
 
             gravity,
             target_density,
             pressure_multiplier,
-            near_pressure_multiplier: pressure_multiplier / 100.0,
             viscosity_strength,
-            collision_damping: 0.95,
+            viscosity_model,
+            collision_damping,
+            interface_tension,
+            surface_tension,
+            vorticity_confinement,
+            ambient_temperature,
+            thermal_diffusivity,
+            thermal_expansion,
+            dye_diffusivity,
 
             interaction_position: None,
             interaction_scale: 0.0,
+            dye_brush: None,
             interaction_strength,
             interaction_radius,
 
             smoothing_radius,
-        };
-
-        sim.step(0.0);
-        sim
+        }
     }
 
     pub fn reset_sim(&mut self) {
-        *self = Self::new(
-            self.particle_count,
-            self.particle_radius,
-            self.world_dimensions.x,
-            self.world_dimensions.y,
-            self.gravity,
-            self.target_density,
-            self.pressure_multiplier,
-            self.viscosity_strength,
-            self.smoothing_radius,
-            self.interaction_strength,
-            self.interaction_radius,
-        );
+        *self = Self::new(self.config());
+    }
+
+    /// Current parameters as a [`SimConfig`], reflecting any setter calls made
+    /// since construction.
+    pub fn config(&self) -> SimConfig {
+        SimConfig {
+            particle_count: self.initial_particle_count,
+            max_particle_count: Some(self.max_particle_count),
+            particle_radius: self.particle_radius,
+            world_width: self.world_dimensions.x,
+            world_height: self.world_dimensions.y,
+            gravity: self.gravity,
+            target_density: self.target_density,
+            pressure_multiplier: self.pressure_multiplier,
+            viscosity_strength: self.viscosity_strength,
+            viscosity_model: self.viscosity_model,
+            smoothing_radius: self.smoothing_radius,
+            interaction_strength: self.interaction_strength,
+            interaction_radius: self.interaction_radius,
+            collision_damping: self.collision_damping,
+            interface_tension: self.interface_tension,
+            surface_tension: self.surface_tension,
+            vorticity_confinement: self.vorticity_confinement,
+            ambient_temperature: self.ambient_temperature,
+            thermal_diffusivity: self.thermal_diffusivity,
+            thermal_expansion: self.thermal_expansion,
+            dye_diffusivity: self.dye_diffusivity,
+            solver: self.solver.kind(),
+            substepping: self.substepping,
+            deterministic: self.deterministic,
+            spatial_index: self.spatial_index.kind(),
+            density_kernel: self.density_kernel,
+            viscosity_kernel: self.viscosity_kernel,
+            layout: self.layout.clone(),
+            obstacles: self.obstacles.clone(),
+            boundary: self.boundary.clone(),
+            kinematic_bodies: self.kinematic_bodies.clone(),
+            rigid_bodies: self.rigid_bodies.clone(),
+            emitters: self.emitters.clone(),
+            sinks: self.sinks.clone(),
+            heat_sources: self.heat_sources.clone(),
+            phases: self.phases.clone(),
+        }
     }
 
     fn update_external_forces(&mut self, dt: f32) {
@@ -159,248 +379,571 @@ impl Sim2D {
             .par_iter()
             .zip(self.predicted_positions.par_iter_mut())
             .zip(self.velocities.par_iter_mut())
-            .for_each(|((position_ref, predicted_position_ref), velocity_ref)| {
-                let mut acceleration = Vector2D::new(0.0, self.gravity);
-
-                if let Some(interaction_input_point) = self.interaction_position {
-                    let input_point_offset = interaction_input_point - *position_ref;
-                    let distance_squared = input_point_offset.dot(input_point_offset);
-
-                    if distance_squared < (self.interaction_radius * self.interaction_radius) {
-                        let distance = distance_squared.sqrt();
-                        if distance > f32::EPSILON {
-                            let t = 1.0 - distance / self.interaction_radius;
-
-                            let acceleration_weight = 1.0
-                                - (t * (self.interaction_scale * self.interaction_strength / 10.0)
-                                    .clamp(-1.0, 1.0));
-
-                            acceleration = acceleration * acceleration_weight
-                                + input_point_offset / distance
-                                    * t
-                                    * self.interaction_scale
-                                    * self.interaction_strength;
-                            acceleration -= *velocity_ref * t;
+            .zip(self.temperatures.par_iter())
+            .for_each(
+                |(((position_ref, predicted_position_ref), velocity_ref), temperature_ref)| {
+                    // Boussinesq buoyancy, where only the weight feels the
+                    // temperature, so warm fluid rises and cool fluid sinks
+                    let buoyancy = 1.0
+                        - self.thermal_expansion * (*temperature_ref - self.ambient_temperature);
+                    let mut acceleration = Vector2D::new(0.0, self.gravity * buoyancy);
+
+                    if let Some(interaction_input_point) = self.interaction_position {
+                        let input_point_offset = interaction_input_point - *position_ref;
+                        let distance_squared = input_point_offset.dot(input_point_offset);
+
+                        if distance_squared < (self.interaction_radius * self.interaction_radius) {
+                            let distance = distance_squared.sqrt();
+                            if distance > f32::EPSILON {
+                                let t = 1.0 - distance / self.interaction_radius;
+
+                                let acceleration_weight = 1.0
+                                    - (t * (self.interaction_scale * self.interaction_strength
+                                        / 10.0)
+                                        .clamp(-1.0, 1.0));
+
+                                acceleration = acceleration * acceleration_weight
+                                    + input_point_offset / distance
+                                        * t
+                                        * self.interaction_scale
+                                        * self.interaction_strength;
+                                acceleration -= *velocity_ref * t;
+                            }
                         }
                     }
-                }
 
-                *velocity_ref += acceleration * dt;
-                *predicted_position_ref = *position_ref + *velocity_ref * dt / 2.0;
-            });
+                    *velocity_ref += acceleration * dt;
+                    *predicted_position_ref = *position_ref + *velocity_ref * dt / 2.0;
+                },
+            );
+    }
+
+    fn update_spatial_index(&mut self) {
+        self.spatial_index.rebuild(
+            &self.predicted_positions,
+            self.smoothing_radius,
+            self.world_dimensions,
+            self.deterministic,
+        );
+        self.sort_particles();
+    }
+
+    // Stores the particles in bucket order, so the neighbour loops read the
+    // velocities, densities and phases of each cell from one run of memory.
+    // Only what outlives the passes is moved; the rest is rewritten before
+    // it is next read.
+    fn sort_particles(&mut self) {
+        let order = self.spatial_index.order();
+        permute(&mut self.particle_ids, order);
+        permute(&mut self.positions, order);
+        permute(&mut self.predicted_positions, order);
+        permute(&mut self.velocities, order);
+        permute(&mut self.strain_rates, order);
+        permute(&mut self.temperatures, order);
+        permute(&mut self.dyes, order);
+        permute(&mut self.particle_phases, order);
+        self.spatial_index.renumber();
+    }
+
+    fn update_neighbours(&mut self) {
+        self.neighbours.rebuild(
+            self.spatial_index.as_ref(),
+            &self.predicted_positions,
+            self.smoothing_radius,
+        );
+    }
+
+    fn update_densities(&mut self) {
+        self.densities.par_iter_mut().enumerate().for_each(
+            |(sample_index, (density_ref, near_density_ref))| {
+                (*density_ref, *near_density_ref) = density_sums(
+                    self.neighbours.of(sample_index),
+                    self.smoothing_radius,
+                    self.density_kernel,
+                    |neighbour_index| {
+                        self.phase_parameters[self.particle_phases[neighbour_index] as usize].mass
+                    },
+                );
+            },
+        );
     }
 
-    fn update_spatial_hashes(&mut self) {
-        self.cell_keys
+    fn update_pressure_forces(&mut self, dt: f32) {
+        self.pressure_forces
             .par_iter_mut()
-            .zip(self.predicted_positions.par_iter())
+            .zip(self.velocities.par_iter_mut())
             .enumerate()
-            .for_each(|(i, (cell_key_ref, predicted_position_ref))| {
-                let cell = get_cell(*predicted_position_ref, self.smoothing_radius);
-                let hash = hash_cell(cell);
-                let key = key_from_hash(hash, self.particle_count);
+            .for_each(|(sample_index, (pressure_force_ref, velocity_ref))| {
+                let phase = self.particle_phases[sample_index];
+                let parameters = self.phase_parameters[phase as usize];
+                let (density, near_density) = self.densities[sample_index];
+                let pressure = parameters.pressure(density);
+                let near_pressure =
+                    density_to_near_pressure(near_density, parameters.near_pressure_multiplier);
+
+                let neighbours = self.neighbours.of(sample_index);
+                *pressure_force_ref = pressure_force(
+                    neighbours,
+                    sample_index,
+                    self.smoothing_radius,
+                    self.density_kernel,
+                    pressure,
+                    near_pressure,
+                    |neighbour_index| {
+                        let parameters =
+                            self.phase_parameters[self.particle_phases[neighbour_index] as usize];
+                        let (density, near_density) = self.densities[neighbour_index];
+                        PressureSample {
+                            pressure: parameters.pressure(density),
+                            near_pressure: density_to_near_pressure(
+                                near_density,
+                                parameters.near_pressure_multiplier,
+                            ),
+                            mass: parameters.mass,
+                            density,
+                            near_density,
+                        }
+                    },
+                );
+
+                // Unlike fluids push each other apart along the interface
+                if self.interface_tension != 0.0 {
+                    for neighbour in neighbours.iter() {
+                        let neighbour_phase = self.particle_phases[neighbour.index];
+                        if neighbour_phase == phase {
+                            continue;
+                        }
+
+                        let distance = neighbour.distance;
+                        let direction_to_neighbour = if distance > 0.0 {
+                            neighbour.offset / distance
+                        } else {
+                            Vector2D::new(1.0, 1.0).normalise()
+                        };
+                        let neighbour_mass = self.phase_parameters[neighbour_phase as usize].mass;
+                        let neighbour_density = self.densities[neighbour.index].0;
+
+                        *pressure_force_ref -= direction_to_neighbour
+                            * self.density_kernel.value(distance, self.smoothing_radius)
+                            * self.interface_tension
+                            * neighbour_mass
+                            / neighbour_density;
+                    }
+                }
 
-                *cell_key_ref = (key, i);
+                if density != 0.0 {
+                    let acceleration = *pressure_force_ref / density;
+                    *velocity_ref += acceleration * dt;
+                }
             });
 
-        self.cell_keys.sort_unstable_by_key(|p| p.0);
-        self.cell_offsets.clear();
+        // Rigid bodies push on nearby particles, and the reaction on each body
+        // is summed as a force and torque for the next position update
+        for ((body, state), force_ref) in self
+            .rigid_bodies
+            .iter()
+            .zip(&self.rigid_body_states)
+            .zip(self.rigid_body_forces.iter_mut())
+        {
+            let reactions: Vec<(Vector2D, f32)> = self
+                .velocities
+                .par_iter_mut()
+                .enumerate()
+                .filter_map(|(sample_index, velocity_ref)| {
+                    let parameters =
+                        self.phase_parameters[self.particle_phases[sample_index] as usize];
+                    let density = self.densities[sample_index];
+                    let pressure = parameters.pressure(density.0);
+                    let near_pressure =
+                        density_to_near_pressure(density.1, parameters.near_pressure_multiplier);
+
+                    let (acceleration, contact) = body.pressure_acceleration(
+                        state,
+                        self.predicted_positions[sample_index],
+                        density,
+                        pressure,
+                        near_pressure,
+                        self.smoothing_radius,
+                        self.density_kernel,
+                    )?;
+                    *velocity_ref += acceleration * dt;
+
+                    let reaction = acceleration * -parameters.mass;
+                    Some((reaction, (contact - state.position).cross(reaction)))
+                })
+                .collect();
 
-        for (i, &(key, _original_i)) in self.cell_keys.iter().enumerate() {
-            self.cell_offsets.entry(key).or_insert(i);
+            // Summed in particle order so replays match exactly
+            for (force, torque) in reactions {
+                force_ref.0 += force;
+                force_ref.1 += torque;
+            }
         }
     }
 
-    fn update_densities(&mut self) {
-        let radius_squared = self.smoothing_radius * self.smoothing_radius;
-
-        self.densities.par_iter_mut().enumerate().for_each(
-            |(sample_index, (density_ref, near_density_ref))| {
-                let position = self.predicted_positions[sample_index];
-
-                *density_ref = 0.0;
-                *near_density_ref = 0.0;
-
-                // Optimised
-                let origin_cell = get_cell(position, self.smoothing_radius);
-                for (x, y) in GRID_OFFSETS {
-                    let hash = hash_cell((origin_cell.0 + x, origin_cell.1 + y));
-                    let key = key_from_hash(hash, self.particle_count);
-
-                    // reverse lookup key to iterate over all particles in cell
-                    if let Some(cell_index) = self.cell_offsets.get(&key) {
-                        let mut cell_index = *cell_index;
-                        while cell_index < self.particle_count {
-                            // this neighbour should share a key with current lookup cell
-                            let (neighbour_key, neighbour_index) = self.cell_keys[cell_index];
-                            cell_index += 1;
-
-                            // iterated over all particles in current cell
-                            if neighbour_key != key {
-                                break;
-                            }
+    // Smoothed colour field gradient, scaled by the smoothing radius so its
+    // length is about 1 at the surface and 0 inside the fluid
+    fn update_surface_normals(&mut self) {
+        self.surface_normals.par_iter_mut().enumerate().for_each(
+            |(sample_index, surface_normal_ref)| {
+                let phase = self.particle_phases[sample_index];
 
-                            let neighbour_position = self.predicted_positions[neighbour_index];
-                            let offset_to_neighbour = neighbour_position - position;
-                            let square_distance_to_neighbour =
-                                offset_to_neighbour.dot(offset_to_neighbour);
+                *surface_normal_ref = Vector2D::new(0.0, 0.0);
 
-                            if square_distance_to_neighbour > radius_squared {
-                                continue;
-                            }
+                for neighbour in self.neighbours.of(sample_index).iter() {
+                    let neighbour_index = neighbour.index;
+                    // don't compare with self
+                    if neighbour_index == sample_index {
+                        continue;
+                    }
+                    // each fluid only sees its own surface
+                    if self.particle_phases[neighbour_index] != phase {
+                        continue;
+                    }
 
-                            let distance = square_distance_to_neighbour.sqrt();
-                            *density_ref += density_kernel(distance, self.smoothing_radius);
-                            *near_density_ref +=
-                                near_density_kernel(distance, self.smoothing_radius);
-                        }
+                    if neighbour.distance == 0.0 {
+                        continue;
                     }
+
+                    let distance = neighbour.distance;
+                    let mass = self.phase_parameters[phase as usize].mass;
+                    let neighbour_density = self.densities[neighbour_index].0;
+
+                    // Gradient with respect to the sample, which points
+                    // into the fluid
+                    *surface_normal_ref -= neighbour.offset / distance
+                        * self
+                            .density_kernel
+                            .derivative(distance, self.smoothing_radius)
+                        * mass
+                        / neighbour_density;
                 }
+
+                *surface_normal_ref = *surface_normal_ref * self.smoothing_radius;
             },
         );
     }
 
-    fn update_pressure_forces(&mut self, dt: f32) {
-        let radius_squared = self.smoothing_radius * self.smoothing_radius;
+    // Cohesion between particles of the same fluid, plus a curvature term
+    // that flattens bulges in the surface (Akinci et al. 2013)
+    fn update_surface_tension_forces(&mut self, dt: f32) {
+        self.velocities
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(sample_index, velocity_ref)| {
+                let phase = self.particle_phases[sample_index];
+                let parameters = self.phase_parameters[phase as usize];
+                let density = self.densities[sample_index].0;
+                let normal = self.surface_normals[sample_index];
+
+                // Grains hold together through their own cohesion instead
+                if let Material::Granular { .. } = parameters.material {
+                    return;
+                }
 
-        self.pressure_forces
+                let mut acceleration = Vector2D::new(0.0, 0.0);
+
+                for neighbour in self.neighbours.of(sample_index).iter() {
+                    let neighbour_index = neighbour.index;
+                    // don't compare with self
+                    if neighbour_index == sample_index {
+                        continue;
+                    }
+                    // each fluid only coheres with itself
+                    if self.particle_phases[neighbour_index] != phase {
+                        continue;
+                    }
+
+                    if neighbour.distance == 0.0 {
+                        continue;
+                    }
+
+                    let distance = neighbour.distance;
+                    let direction_to_neighbour = neighbour.offset / distance;
+
+                    // Boosts the pull where the surface is underfilled
+                    let correction = 2.0 * parameters.target_density
+                        / (density + self.densities[neighbour_index].0);
+
+                    let cohesion = direction_to_neighbour
+                        * parameters.mass
+                        * cohesion_kernel(distance, self.smoothing_radius);
+                    let curvature = normal - self.surface_normals[neighbour_index];
+
+                    acceleration += (cohesion - curvature) * correction;
+                }
+
+                *velocity_ref += acceleration * self.surface_tension * dt;
+            });
+    }
+
+    // Curl of the velocity field, positive anticlockwise
+    fn update_vorticities(&mut self) {
+        self.vorticities
             .par_iter_mut()
-            .zip(self.velocities.par_iter_mut())
             .enumerate()
-            .for_each(|(sample_index, (pressure_force_ref, velocity_ref))| {
-                let position = self.predicted_positions[sample_index];
+            .for_each(|(sample_index, vorticity_ref)| {
+                let velocity = self.velocities[sample_index];
 
-                let (density, near_density) = self.densities[sample_index];
-                let pressure =
-                    density_to_pressure(density, self.target_density, self.pressure_multiplier);
-                let near_pressure =
-                    density_to_near_pressure(near_density, self.near_pressure_multiplier);
-
-                *pressure_force_ref = Vector2D::new(0.0, 0.0);
-
-                // Optimised
-                let origin_cell = get_cell(position, self.smoothing_radius);
-                for (x, y) in GRID_OFFSETS {
-                    let hash = hash_cell((origin_cell.0 + x, origin_cell.1 + y));
-                    let key = key_from_hash(hash, self.particle_count);
-
-                    // reverse lookup key to iterate over all particles in cell
-                    if let Some(cell_index) = self.cell_offsets.get(&key) {
-                        let mut cell_index = *cell_index;
-                        while cell_index < self.particle_count {
-                            // this neighbour should share a key with current lookup cell
-                            let (neighbour_key, neighbour_index) = self.cell_keys[cell_index];
-                            cell_index += 1;
-
-                            // don't compare with self
-                            if neighbour_index == sample_index {
-                                continue;
-                            }
-                            // iterated over all particles in current cell
-                            if neighbour_key != key {
-                                break;
-                            }
+                *vorticity_ref = 0.0;
 
-                            let neighbour_position = self.predicted_positions[neighbour_index];
-                            let offset_to_neighbour = neighbour_position - position;
-                            let squared_distance_to_neighbour =
-                                offset_to_neighbour.dot(offset_to_neighbour);
+                for neighbour in self.neighbours.of(sample_index).iter() {
+                    let neighbour_index = neighbour.index;
+                    // don't compare with self
+                    if neighbour_index == sample_index || neighbour.distance == 0.0 {
+                        continue;
+                    }
 
-                            if squared_distance_to_neighbour > radius_squared {
-                                continue;
-                            }
+                    let distance = neighbour.distance;
+                    let mass =
+                        self.phase_parameters[self.particle_phases[neighbour_index] as usize].mass;
+                    let neighbour_density = self.densities[neighbour_index].0;
+
+                    // Gradient with respect to the sample
+                    let gradient = neighbour.offset / distance
+                        * -self
+                            .density_kernel
+                            .derivative(distance, self.smoothing_radius);
+                    *vorticity_ref += gradient.cross(self.velocities[neighbour_index] - velocity)
+                        * mass
+                        / neighbour_density;
+                }
+            });
+    }
 
-                            let distance = squared_distance_to_neighbour.sqrt();
-                            let direction_to_neighbour = if distance > 0.0 {
-                                offset_to_neighbour / distance
-                            } else {
-                                Vector2D::new(1.0, 1.0).normalise()
-                            };
-
-                            let (neighbour_density, neighbour_near_density) =
-                                self.densities[neighbour_index];
-                            let neighbour_pressure = density_to_pressure(
-                                neighbour_density,
-                                self.target_density,
-                                self.pressure_multiplier,
-                            );
-                            let neighbour_near_pressure = density_to_near_pressure(
-                                neighbour_near_density,
-                                self.near_pressure_multiplier,
-                            );
-
-                            let shared_pressure = (pressure + neighbour_pressure) * 0.5;
-                            let shared_near_pressure =
-                                (near_pressure + neighbour_near_pressure) * 0.5;
-
-                            *pressure_force_ref += direction_to_neighbour
-                                * density_kernel_derivative(distance, self.smoothing_radius)
-                                * shared_pressure
-                                / neighbour_density;
-                            *pressure_force_ref += direction_to_neighbour
-                                * near_density_kernel_derivative(distance, self.smoothing_radius)
-                                * shared_near_pressure
-                                / neighbour_near_density;
-                        }
+    // Pushes each particle around the swirl it circles, putting back the
+    // rotation numerical damping takes out (Fedkiw et al. 2001)
+    fn update_vorticity_confinement_forces(&mut self, dt: f32) {
+        self.velocities
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(sample_index, velocity_ref)| {
+                let vorticity = self.vorticities[sample_index];
+
+                // Points towards the centre of the swirl
+                let mut vorticity_gradient = Vector2D::new(0.0, 0.0);
+
+                for neighbour in self.neighbours.of(sample_index).iter() {
+                    let neighbour_index = neighbour.index;
+                    // don't compare with self
+                    if neighbour_index == sample_index || neighbour.distance == 0.0 {
+                        continue;
                     }
+
+                    let distance = neighbour.distance;
+                    let mass =
+                        self.phase_parameters[self.particle_phases[neighbour_index] as usize].mass;
+                    let neighbour_density = self.densities[neighbour_index].0;
+
+                    let gradient = neighbour.offset / distance
+                        * -self
+                            .density_kernel
+                            .derivative(distance, self.smoothing_radius);
+                    vorticity_gradient += gradient
+                        * (self.vorticities[neighbour_index].abs() - vorticity.abs())
+                        * mass
+                        / neighbour_density;
                 }
 
-                if density != 0.0 {
-                    let acceleration = *pressure_force_ref / density;
-                    *velocity_ref += acceleration * dt;
+                let length = vorticity_gradient.magnitude();
+                if length <= f32::EPSILON {
+                    return;
+                }
+
+                // Cross product of the gradient direction with the vorticity,
+                // which points out of the plane
+                let direction = vorticity_gradient / length;
+                *velocity_ref += Vector2D::new(direction.y, -direction.x)
+                    * vorticity
+                    * self.vorticity_confinement
+                    * dt;
+            });
+    }
+
+    // Shear rate `sqrt(2 D:D)`, where `D` is the symmetric part of the
+    // velocity gradient
+    fn update_strain_rates(&mut self) {
+        self.strain_rates
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(sample_index, strain_rate_ref)| {
+                let velocity = self.velocities[sample_index];
+
+                // Rows of the velocity gradient, `grad u` and `grad v`
+                let mut velocity_gradient = [Vector2D::new(0.0, 0.0); 2];
+
+                for neighbour in self.neighbours.of(sample_index).iter() {
+                    let neighbour_index = neighbour.index;
+                    // don't compare with self
+                    if neighbour_index == sample_index || neighbour.distance == 0.0 {
+                        continue;
+                    }
+
+                    let distance = neighbour.distance;
+                    let mass =
+                        self.phase_parameters[self.particle_phases[neighbour_index] as usize].mass;
+                    let neighbour_density = self.densities[neighbour_index].0;
+
+                    // Gradient with respect to the sample
+                    let gradient = neighbour.offset / distance
+                        * -self
+                            .density_kernel
+                            .derivative(distance, self.smoothing_radius);
+                    let velocity_change =
+                        (self.velocities[neighbour_index] - velocity) * mass / neighbour_density;
+                    velocity_gradient[0] += gradient * velocity_change.x;
+                    velocity_gradient[1] += gradient * velocity_change.y;
                 }
+
+                let [grad_u, grad_v] = velocity_gradient;
+                let shear = 0.5 * (grad_u.y + grad_v.x);
+                *strain_rate_ref = (2.0
+                    * (grad_u.x * grad_u.x + grad_v.y * grad_v.y + 2.0 * shear * shear))
+                    .sqrt();
             });
     }
 
+    // Acceleration from the viscosity model, applied after every particle's
+    // has been found so the pass doesn't depend on particle order. Grains
+    // grip grains by friction, and meet fluid through its viscosity.
     fn update_viscous_forces(&mut self, dt: f32) {
-        let radius_squared = self.smoothing_radius * self.smoothing_radius;
+        let model = self.viscosity_model;
+        let h = self.smoothing_radius;
+        // Keeps the singular terms finite for particles on top of each other
+        let softening = 0.01 * h * h;
+        // Explicit stability limit, with no viscosity when only refreshing
+        let max_viscosity = if dt > 0.0 { 0.125 * h * h / dt } else { 0.0 };
+
+        let granular = self
+            .phase_parameters
+            .iter()
+            .any(|parameters| matches!(parameters.material, Material::Granular { .. }));
+        if model.needs_strain_rate() || granular {
+            self.update_strain_rates();
+        }
 
         self.viscous_forces
             .par_iter_mut()
             .zip(self.velocities.par_iter())
             .enumerate()
             .for_each(|(sample_index, (viscous_force_ref, velocity_ref))| {
-                let position = self.predicted_positions[sample_index];
+                let parameters = self.phase_parameters[self.particle_phases[sample_index] as usize];
+                let density = self.densities[sample_index].0;
+                let strain_rate = self.strain_rates[sample_index];
+                let viscosity = model.kinematic_viscosity(strain_rate, max_viscosity);
+                let grip =
+                    parameters.granular_grip(self.densities[sample_index], strain_rate, h, dt);
 
                 *viscous_force_ref = Vector2D::new(0.0, 0.0);
 
-                // Optimised
-                let origin_cell = get_cell(position, self.smoothing_radius);
-                for (x, y) in GRID_OFFSETS {
-                    let hash = hash_cell((origin_cell.0 + x, origin_cell.1 + y));
-                    let key = key_from_hash(hash, self.particle_count);
-
-                    // reverse lookup key to iterate over all particles in cell
-                    if let Some(cell_index) = self.cell_offsets.get(&key) {
-                        let mut cell_index = *cell_index;
-                        while cell_index < self.particle_count {
-                            // this neighbour should share a key with current lookup cell
-                            let (neighbour_key, neighbour_index) = self.cell_keys[cell_index];
-                            cell_index += 1;
-
-                            // don't compare with self
-                            if neighbour_index == sample_index {
-                                continue;
-                            }
-                            // iterated over all particles in current cell
-                            if neighbour_key != key {
-                                break;
-                            }
-
-                            let neighbour_position = self.predicted_positions[neighbour_index];
-                            let offset_to_neighbour = neighbour_position - position;
-                            let squared_distance_to_neighbour =
-                                offset_to_neighbour.dot(offset_to_neighbour);
+                for neighbour in self.neighbours.of(sample_index).iter() {
+                    let neighbour_index = neighbour.index;
+                    // don't compare with self
+                    if neighbour_index == sample_index {
+                        continue;
+                    }
 
-                            if squared_distance_to_neighbour > radius_squared {
-                                continue;
+                    let distance = neighbour.distance;
+                    let neighbour_velocity = self.velocities[neighbour_index];
+                    let neighbour_parameters =
+                        self.phase_parameters[self.particle_phases[neighbour_index] as usize];
+                    let neighbour_density = self.densities[neighbour_index].0;
+                    let neighbour_strain_rate = self.strain_rates[neighbour_index];
+                    let mean_density = 0.5 * (density + neighbour_density);
+
+                    let neighbour_grip = neighbour_parameters.granular_grip(
+                        self.densities[neighbour_index],
+                        neighbour_strain_rate,
+                        h,
+                        dt,
+                    );
+                    let viscosities = match (grip, neighbour_grip) {
+                        (Some(grip), Some(neighbour_grip)) => {
+                            // Blends the pair's velocities like XSPH, which
+                            // stays stable however firmly the grains hold
+                            if dt > 0.0 {
+                                *viscous_force_ref += (neighbour_velocity - *velocity_ref)
+                                    * self.viscosity_kernel.value(distance, h)
+                                    * neighbour_parameters.mass
+                                    / mean_density
+                                    * (0.5 * (grip + neighbour_grip))
+                                    / dt;
                             }
-
-                            let distance = squared_distance_to_neighbour.sqrt();
-                            let neighbour_velocity = self.velocities[neighbour_index];
-                            *viscous_force_ref += (neighbour_velocity - *velocity_ref)
-                                * viscosity_kernel(distance, self.smoothing_radius);
+                            None
                         }
+                        _ => match model {
+                            ViscosityModel::Diffusion => {
+                                // Mean of the two viscosities, weighted by mass
+                                // so the pair exchanges equal and opposite
+                                // momentum
+                                let strength = (parameters.viscosity_strength
+                                    + neighbour_parameters.viscosity_strength)
+                                    * neighbour_parameters.mass
+                                    / (parameters.mass + neighbour_parameters.mass);
+                                *viscous_force_ref += (neighbour_velocity - *velocity_ref)
+                                    * self.viscosity_kernel.value(distance, h)
+                                    * strength;
+                                None
+                            }
+                            ViscosityModel::Xsph { epsilon } => {
+                                // A velocity correction, spread over the substep
+                                if dt > 0.0 {
+                                    *viscous_force_ref += (neighbour_velocity - *velocity_ref)
+                                        * self.viscosity_kernel.value(distance, h)
+                                        * neighbour_parameters.mass
+                                        / mean_density
+                                        * epsilon
+                                        / dt;
+                                }
+                                None
+                            }
+                            ViscosityModel::Artificial { alpha, beta } => {
+                                // Relative to the neighbour, so negative when
+                                // the pair is closing in
+                                let relative_position = neighbour.offset * -1.0;
+                                let closing =
+                                    (*velocity_ref - neighbour_velocity).dot(relative_position);
+                                if closing < 0.0 && distance > 0.0 {
+                                    // Speed of sound of the linear equation of
+                                    // state
+                                    let sound_speed = 0.5
+                                        * (parameters.pressure_multiplier.sqrt()
+                                            + neighbour_parameters.pressure_multiplier.sqrt());
+                                    let mu = h * closing / (distance * distance + softening);
+                                    let pi =
+                                        (-alpha * sound_speed * mu + beta * mu * mu) / mean_density;
+
+                                    let gradient = relative_position / distance
+                                        * self.viscosity_kernel.derivative(distance, h);
+                                    *viscous_force_ref -= gradient * neighbour_parameters.mass * pi;
+                                }
+                                None
+                            }
+                            ViscosityModel::Laplacian { .. }
+                            | ViscosityModel::PowerLaw { .. }
+                            | ViscosityModel::Cross { .. }
+                            | ViscosityModel::Bingham { .. } => Some((
+                                viscosity,
+                                model.kinematic_viscosity(neighbour_strain_rate, max_viscosity),
+                            )),
+                        },
+                    };
+
+                    // Laplacian of the velocity (Morris et al. 1997)
+                    if let Some((viscosity, neighbour_viscosity)) = viscosities
+                        && distance > 0.0
+                    {
+                        // Position dotted with the kernel gradient is
+                        // `r W'(r)`, which is negative, so the sample is
+                        // pulled towards its neighbour's velocity
+                        let laplacian = distance * self.viscosity_kernel.derivative(distance, h)
+                            / (distance * distance + softening);
+                        // Sum of the dynamic viscosities over the two
+                        // densities, symmetric so momentum is conserved
+                        *viscous_force_ref += (*velocity_ref - neighbour_velocity)
+                            * (viscosity / neighbour_density + neighbour_viscosity / density)
+                            * neighbour_parameters.mass
+                            * laplacian;
                     }
                 }
             });
@@ -409,10 +952,111 @@ impl Sim2D {
             .par_iter_mut()
             .zip(self.viscous_forces.par_iter())
             .for_each(|(velocity_ref, viscous_force_ref)| {
-                *velocity_ref += *viscous_force_ref * self.viscosity_strength * dt;
+                *velocity_ref += *viscous_force_ref * dt;
+            });
+    }
+
+    // Weight of a neighbour in the Laplacian of a quantity the particles
+    // carry (Cleary and Monaghan 1999). `r W'(r)` is negative, so the
+    // quantity flows from the particle with more of it, and the weight is
+    // symmetric in the densities so it is only moved, never made.
+    fn diffusion_weight(&self, sample_index: usize, neighbour: Neighbour) -> f32 {
+        // don't compare with self
+        if neighbour.index == sample_index || neighbour.distance == 0.0 {
+            return 0.0;
+        }
+
+        let h = self.smoothing_radius;
+        let distance = neighbour.distance;
+        let mass = self.phase_parameters[self.particle_phases[neighbour.index] as usize].mass;
+        let mean_density =
+            0.5 * (self.densities[sample_index].0 + self.densities[neighbour.index].0);
+
+        2.0 * mass / mean_density * distance * self.density_kernel.derivative(distance, h)
+            / (distance * distance + 0.01 * h * h)
+    }
+
+    // Diffuses a quantity the particles carry for one step, leaving each
+    // particle's rate of change per unit diffusivity in `rates`. Both are
+    // taken out of the simulation so the pass can borrow the rest of it.
+    fn diffuse<T: Diffused>(&self, values: &mut [T], rates: &mut [T], diffusivity: f32, dt: f32) {
+        let h = self.smoothing_radius;
+        // Explicit stability limit, as for the viscosity
+        let diffusivity = diffusivity.min(0.125 * h * h / dt);
+
+        rates
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(sample_index, rate_ref)| {
+                let value = values[sample_index];
+
+                *rate_ref = T::ZERO;
+
+                for neighbour in self.neighbours.of(sample_index).iter() {
+                    let weight = self.diffusion_weight(sample_index, neighbour);
+                    rate_ref.add_difference(value, values[neighbour.index], weight);
+                }
+            });
+
+        values
+            .par_iter_mut()
+            .zip(rates.par_iter())
+            .for_each(|(value_ref, rate_ref)| value_ref.add_scaled(*rate_ref, diffusivity * dt));
+    }
+
+    // Heat diffusion between neighbours, then heating from the heat sources
+    fn update_temperatures(&mut self, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        let mut temperatures = std::mem::take(&mut self.temperatures);
+        let mut heat_rates = std::mem::take(&mut self.heat_rates);
+        self.diffuse(
+            &mut temperatures,
+            &mut heat_rates,
+            self.thermal_diffusivity,
+            dt,
+        );
+        self.temperatures = temperatures;
+        self.heat_rates = heat_rates;
+
+        let h = self.smoothing_radius;
+        self.temperatures
+            .par_iter_mut()
+            .zip(self.positions.par_iter())
+            .for_each(|(temperature_ref, position_ref)| {
+                for source in &self.heat_sources {
+                    *temperature_ref += source.heat(*position_ref, *temperature_ref, h, dt);
+                }
             });
     }
 
+    // Dye diffusion between neighbours, channel by channel
+    fn update_dyes(&mut self, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        let mut dyes = std::mem::take(&mut self.dyes);
+        let mut dye_rates = std::mem::take(&mut self.dye_rates);
+        self.diffuse(&mut dyes, &mut dye_rates, self.dye_diffusivity, dt);
+        self.dyes = dyes;
+        self.dye_rates = dye_rates;
+    }
+
+    // Dye under the brush, painted once a frame
+    fn paint_dyes(&mut self) {
+        if let Some(brush) = self.dye_brush {
+            self.dyes
+                .par_iter_mut()
+                .zip(self.positions.par_iter())
+                .for_each(|(dye_ref, position_ref)| {
+                    brush.paint(*position_ref, self.interaction_radius, dye_ref);
+                });
+        }
+    }
+
     fn handle_collisions(
         position: &mut Vector2D,
         velocity: &mut Vector2D,
@@ -434,68 +1078,383 @@ impl Sim2D {
         }
     }
 
+    pub(crate) fn kinematic_body_states_at(&self, time: f32) -> Vec<BodyState> {
+        self.kinematic_bodies
+            .iter()
+            .map(|body| body.state(time))
+            .collect()
+    }
+
+    // Pushes a particle out of the obstacles, the kinematic bodies posed at
+    // `body_states`, the boundary and the walls, bouncing its velocity off
+    // whatever it hit
+    pub(crate) fn collide(
+        &self,
+        body_states: &[BodyState],
+        position: &mut Vector2D,
+        velocity: &mut Vector2D,
+    ) {
+        for obstacle in &self.obstacles {
+            obstacle.collide(position, velocity, self.particle_radius);
+        }
+        for (body, state) in self.kinematic_bodies.iter().zip(body_states) {
+            body.collide(state, position, velocity, self.particle_radius);
+        }
+        if let (Some(boundary), Some(field)) = (&self.boundary, &self.boundary_field) {
+            field.collide(
+                position,
+                velocity,
+                self.particle_radius,
+                boundary.restitution,
+                boundary.friction,
+            );
+        }
+        Self::handle_collisions(
+            position,
+            velocity,
+            self.world_dimensions,
+            self.particle_radius,
+            self.collision_damping,
+        );
+    }
+
     fn update_positions(&mut self, dt: f32) {
-        self.positions
+        self.time += dt;
+        let body_states = self.kinematic_body_states_at(self.time);
+
+        let mut positions = std::mem::take(&mut self.positions);
+        let mut velocities = std::mem::take(&mut self.velocities);
+        positions
             .par_iter_mut()
-            .zip(self.velocities.par_iter_mut())
-            .for_each(|(position_ref, velocity_ref)| {
+            .zip(velocities.par_iter_mut())
+            .zip(self.particle_phases.par_iter())
+            .for_each(|((position_ref, velocity_ref), phase_ref)| {
                 *position_ref += *velocity_ref * dt;
-                Self::handle_collisions(
-                    position_ref,
-                    velocity_ref,
-                    self.world_dimensions,
-                    self.particle_radius,
-                    self.collision_damping,
-                );
+                let velocity = *velocity_ref;
+                self.collide(&body_states, position_ref, velocity_ref);
+                self.phase_parameters[*phase_ref as usize].grip(velocity, velocity_ref);
             });
+        self.positions = positions;
+        self.velocities = velocities;
     }
 
-    pub fn step(&mut self, dt: f32) {
-        let interval = 2;
-        for _ in 0..interval {
-            self.update_external_forces(dt / (interval as f32));
-            self.update_spatial_hashes();
-            self.update_densities();
-            self.update_pressure_forces(dt / (interval as f32));
-            self.update_viscous_forces(dt / (interval as f32));
-            self.update_positions(dt / (interval as f32));
+    fn update_rigid_bodies(&mut self, dt: f32) {
+        let gravity = Vector2D::new(0.0, self.gravity);
+
+        for ((body, state_ref), force_ref) in self
+            .rigid_bodies
+            .iter()
+            .zip(self.rigid_body_states.iter_mut())
+            .zip(self.rigid_body_forces.iter_mut())
+        {
+            let (force, torque) = std::mem::replace(force_ref, (Vector2D::new(0.0, 0.0), 0.0));
+
+            state_ref.velocity += (gravity + force / body.mass) * dt;
+            state_ref.angular_velocity += torque / body.inertia() * dt;
+            state_ref.position += state_ref.velocity * dt;
+            state_ref.rotation += state_ref.angular_velocity * dt;
+
+            body.collide_walls(state_ref, self.world_dimensions);
         }
 
-        self.velocity_magnitudes
-            .par_iter_mut()
-            .zip(self.velocities.par_iter())
-            .for_each(|(velocity_magnitudes_ref, velocity_ref)| {
-                *velocity_magnitudes_ref = velocity_ref.magnitude()
-            });
+        // Particles bounce off the moved bodies, which take the opposite impulse
+        for (body, state_ref) in self
+            .rigid_bodies
+            .iter()
+            .zip(self.rigid_body_states.iter_mut())
+        {
+            let state = *state_ref;
+            let impulses: Vec<(Vector2D, Vector2D)> = self
+                .positions
+                .par_iter_mut()
+                .zip(self.velocities.par_iter_mut())
+                .zip(self.particle_phases.par_iter())
+                .filter_map(|((position_ref, velocity_ref), phase_ref)| {
+                    let velocity = *velocity_ref;
+                    body.collide(&state, position_ref, velocity_ref, self.particle_radius);
+
+                    let impulse = (velocity - *velocity_ref)
+                        * self.phase_parameters[*phase_ref as usize].mass;
+                    (impulse != Vector2D::new(0.0, 0.0))
+                        .then_some((*position_ref - state.position, impulse))
+                })
+                .collect();
+
+            for (arm, impulse) in impulses {
+                body.apply_impulse(state_ref, arm, impulse);
+            }
+        }
     }
 
-    #[cfg(feature = "direct-access")]
-    pub fn get_positions(&self) -> Vec<Vector2D> {
-        self.positions.clone()
-    }
+    // Deletes particles inside sinks, then spawns what the emitters owe
+    fn update_flow(&mut self, dt: f32) {
+        if !self.sinks.is_empty() {
+            // Back to front, so each swap brings in a particle already checked
+            for i in (0..self.particle_count).rev() {
+                if self
+                    .sinks
+                    .iter()
+                    .any(|sink| sink.contains(self.positions[i]))
+                {
+                    self.remove_particle(i);
+                }
+            }
+        }
 
-    #[cfg(feature = "direct-access")]
-    pub fn get_velocity_magnitudes(&self) -> Vec<f32> {
-        self.velocity_magnitudes.clone()
-    }
+        for (emitter, state) in self.emitters.iter().zip(self.emitter_states.iter_mut()) {
+            for position in emitter.emit(state, dt, self.particle_radius) {
+                if self.particle_count == self.max_particle_count {
+                    break;
+                }
 
-    #[cfg(feature = "direct-access")]
-    pub fn get_world_width(&self) -> f32 {
-        self.world_dimensions.x
+                self.particle_count += 1;
+                self.particle_ids.push(self.next_particle_id);
+                self.next_particle_id = self.next_particle_id.wrapping_add(1);
+                self.positions.push(position);
+                self.predicted_positions.push(position);
+                self.velocities.push(emitter.velocity);
+                self.velocity_magnitudes.push(emitter.velocity.magnitude());
+                self.vorticities.push(0.0);
+                self.strain_rates.push(0.0);
+                self.temperatures.push(self.ambient_temperature);
+                self.heat_rates.push(0.0);
+                self.dyes.push([0.0; DYE_CHANNELS]);
+                self.dye_rates.push([0.0; DYE_CHANNELS]);
+                self.particle_phases.push(emitter.phase);
+                self.densities.push((0.0, 0.0));
+                self.pressure_forces.push(Vector2D::new(0.0, 0.0));
+                self.viscous_forces.push(Vector2D::new(0.0, 0.0));
+                self.surface_normals.push(Vector2D::new(0.0, 0.0));
+            }
+        }
     }
 
-    #[cfg(feature = "direct-access")]
-    pub fn get_world_height(&self) -> f32 {
-        self.world_dimensions.y
+    // Swaps the last particle into slot `i`, so buffers never reallocate
+    fn remove_particle(&mut self, i: usize) {
+        self.particle_count -= 1;
+        self.particle_ids.swap_remove(i);
+        self.positions.swap_remove(i);
+        self.predicted_positions.swap_remove(i);
+        self.velocities.swap_remove(i);
+        self.velocity_magnitudes.swap_remove(i);
+        self.vorticities.swap_remove(i);
+        self.strain_rates.swap_remove(i);
+        self.temperatures.swap_remove(i);
+        self.heat_rates.swap_remove(i);
+        self.dyes.swap_remove(i);
+        self.dye_rates.swap_remove(i);
+        self.particle_phases.swap_remove(i);
+        self.densities.swap_remove(i);
+        self.pressure_forces.swap_remove(i);
+        self.viscous_forces.swap_remove(i);
+        self.surface_normals.swap_remove(i);
     }
 
-    #[cfg(feature = "direct-access")]
-    pub fn get_particle_radius(&self) -> f32 {
-        self.particle_radius
+    // Per-phase constants follow the base fluid, whose parameters have setters
+    fn update_phase_parameters(&mut self) {
+        let base = self.target_density;
+
+        self.phase_parameters.clear();
+        self.phase_parameters.push(PhaseParameters::new(
+            self.target_density,
+            self.pressure_multiplier,
+            self.viscosity_strength,
+            Material::Fluid,
+            base,
+        ));
+        self.phase_parameters
+            .extend(self.phases.iter().map(|phase| {
+                PhaseParameters::new(
+                    phase.target_density,
+                    phase.pressure_multiplier,
+                    phase.viscosity_strength,
+                    phase.material,
+                    base,
+                )
+            }));
     }
 
-    /* Pointers */
-    pub fn get_positions_ptr(&self) -> *const u8 {
+    // Fastest particle speed and acceleration, from the forces of the
+    // previous substep
+    fn max_motion(&self) -> (f32, f32) {
+        let max_speed = self
+            .velocities
+            .par_iter()
+            .map(|velocity| velocity.magnitude())
+            .reduce(|| 0.0, f32::max);
+        let max_fluid_acceleration = self
+            .pressure_forces
+            .par_iter()
+            .zip(self.viscous_forces.par_iter())
+            .zip(self.densities.par_iter())
+            .map(|((pressure_force, viscous_force), (density, _))| {
+                let pressure_acceleration = if *density != 0.0 {
+                    pressure_force.magnitude() / density
+                } else {
+                    0.0
+                };
+                pressure_acceleration + viscous_force.magnitude()
+            })
+            .reduce(|| 0.0, f32::max);
+
+        // Heated particles can weigh less than nothing, and cooled ones more
+        let max_buoyancy = if self.thermal_expansion > 0.0 {
+            self.temperatures
+                .par_iter()
+                .map(|temperature| {
+                    (1.0 - self.thermal_expansion * (temperature - self.ambient_temperature)).abs()
+                })
+                .reduce(|| 1.0, f32::max)
+        } else {
+            1.0
+        };
+
+        let mut max_acceleration = self.gravity.abs() * max_buoyancy + max_fluid_acceleration;
+        if self.interaction_position.is_some() {
+            max_acceleration += self.interaction_strength.abs();
+        }
+
+        (max_speed, max_acceleration)
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        self.update_phase_parameters();
+
+        let (max_speed, max_acceleration) = self.max_motion();
+        self.substeps =
+            self.substepping
+                .substeps(dt, max_speed, max_acceleration, self.smoothing_radius);
+
+        // Swapped out so the solver can borrow the rest of the simulation
+        let mut solver = std::mem::replace(&mut self.solver, Box::new(ExplicitSolver));
+        for _ in 0..self.substeps {
+            solver.step(self, dt / self.substeps as f32);
+        }
+        self.solver = solver;
+
+        // Before the flow changes the particle count, while the neighbour
+        // list still matches it
+        self.update_vorticities();
+        self.paint_dyes();
+        self.update_flow(dt);
+
+        self.velocity_magnitudes
+            .par_iter_mut()
+            .zip(self.velocities.par_iter())
+            .for_each(|(velocity_magnitudes_ref, velocity_ref)| {
+                *velocity_magnitudes_ref = velocity_ref.magnitude()
+            });
+    }
+
+    /// Number of live particles, which emitters and sinks change. Only this
+    /// many entries of the particle buffers are valid.
+    pub fn particle_count(&self) -> usize {
+        self.particle_count
+    }
+
+    pub fn max_particle_count(&self) -> usize {
+        self.max_particle_count
+    }
+
+    /// Id of each live particle. Particles are sorted by cell every substep,
+    /// so an index only names the same particle until the next step; the id
+    /// names it for as long as it lives. Emitted particles take fresh ids.
+    pub fn particle_ids(&self) -> &[u32] {
+        &self.particle_ids[..self.particle_count]
+    }
+
+    /// Positions of the live particles.
+    pub fn positions(&self) -> &[Vector2D] {
+        &self.positions[..self.particle_count]
+    }
+
+    pub fn velocities(&self) -> &[Vector2D] {
+        &self.velocities[..self.particle_count]
+    }
+
+    /// Shear rate of each live particle from the last viscous pass, only
+    /// kept up to date by the non-Newtonian viscosity models.
+    pub fn strain_rates(&self) -> &[f32] {
+        &self.strain_rates[..self.particle_count]
+    }
+
+    /// Temperature of each live particle.
+    pub fn temperatures(&self) -> &[f32] {
+        &self.temperatures[..self.particle_count]
+    }
+
+    /// Dye carried by each live particle.
+    pub fn dyes(&self) -> &[Dye] {
+        &self.dyes[..self.particle_count]
+    }
+
+    /// Pressure of each live particle, from the densities found in the last
+    /// substep.
+    pub fn pressures(&self) -> Vec<f32> {
+        self.densities[..self.particle_count]
+            .iter()
+            .zip(&self.particle_phases)
+            .map(|(&(density, _), &phase)| self.phase_parameters[phase as usize].pressure(density))
+            .collect()
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_particle_ids(&self) -> Vec<u32> {
+        self.particle_ids.clone()
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_positions(&self) -> Vec<Vector2D> {
+        self.positions.clone()
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_velocity_magnitudes(&self) -> Vec<f32> {
+        self.velocity_magnitudes.clone()
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_vorticities(&self) -> Vec<f32> {
+        self.vorticities.clone()
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_temperatures(&self) -> Vec<f32> {
+        self.temperatures.clone()
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_dyes(&self) -> Vec<Dye> {
+        self.dyes.clone()
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_particle_phases(&self) -> Vec<u8> {
+        self.particle_phases.clone()
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_world_width(&self) -> f32 {
+        self.world_dimensions.x
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_world_height(&self) -> f32 {
+        self.world_dimensions.y
+    }
+
+    #[cfg(feature = "direct-access")]
+    pub fn get_particle_radius(&self) -> f32 {
+        self.particle_radius
+    }
+
+    /* Pointers */
+    /// One `u32` id per particle, see [`Sim2D::particle_ids`].
+    pub fn get_particle_ids_ptr(&self) -> *const u8 {
+        self.particle_ids.as_ptr() as *const u8
+    }
+
+    pub fn get_positions_ptr(&self) -> *const u8 {
         self.positions.as_ptr() as *const u8
     }
 
@@ -503,9 +1462,152 @@ impl Sim2D {
         self.velocity_magnitudes.as_ptr() as *const u8
     }
 
+    /// One `f32` vorticity per particle, positive anticlockwise, from the
+    /// end of the last step.
+    pub fn get_vorticities_ptr(&self) -> *const u8 {
+        self.vorticities.as_ptr() as *const u8
+    }
+
+    /// One `f32` temperature per particle.
+    pub fn get_temperatures_ptr(&self) -> *const u8 {
+        self.temperatures.as_ptr() as *const u8
+    }
+
+    /// [`DYE_CHANNELS`] `f32` concentrations per particle, interleaved.
+    pub fn get_dyes_ptr(&self) -> *const u8 {
+        self.dyes.as_ptr() as *const u8
+    }
+
+    /// One `u8` phase index per particle, where 0 is the base fluid and `i`
+    /// is [`Sim2D::phases`]`[i - 1]`.
+    pub fn get_particle_phases_ptr(&self) -> *const u8 {
+        self.particle_phases.as_ptr()
+    }
+
+    /// Rigid body states as `[x, y, rotation, vx, vy, angular_velocity]`
+    /// `f32`s, in the same order as [`Sim2D::rigid_bodies`].
+    pub fn get_rigid_body_states_ptr(&self) -> *const u8 {
+        self.rigid_body_states.as_ptr() as *const u8
+    }
+
+    /* Solver */
+    pub fn solver(&self) -> SolverKind {
+        self.solver.kind()
+    }
+
+    /// Switches solver from the next step on, keeping the particle state.
+    pub fn set_solver(&mut self, solver: SolverKind) {
+        self.solver = solver.build();
+    }
+
+    pub fn substepping(&self) -> Substepping {
+        self.substepping
+    }
+
+    pub fn set_substepping(&mut self, substepping: Substepping) {
+        self.substepping = substepping;
+    }
+
+    /// Substeps the last [`Sim2D::step`] was split into.
+    pub fn substeps(&self) -> u32 {
+        self.substeps
+    }
+
+    pub fn spatial_index(&self) -> SpatialIndexKind {
+        self.spatial_index.kind()
+    }
+
+    /// Switches neighbour search from the next step on. Fails, keeping the
+    /// current index, if a flat grid over the world would be too big.
+    pub fn set_spatial_index(
+        &mut self,
+        spatial_index: SpatialIndexKind,
+    ) -> Result<(), ConfigError> {
+        spatial_index.validate(self.smoothing_radius, self.world_dimensions)?;
+
+        self.spatial_index = spatial_index.build();
+        Ok(())
+    }
+
+    pub fn density_kernel(&self) -> KernelKind {
+        self.density_kernel
+    }
+
+    pub fn viscosity_kernel(&self) -> KernelKind {
+        self.viscosity_kernel
+    }
+
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Hash of the particle and rigid body state. In deterministic mode, two
+    /// simulations built from the same config and given the same calls agree
+    /// on it after every step, so a client and server can check they are in
+    /// lock-step.
+    pub fn checksum(&self) -> u64 {
+        let mut hash = CHECKSUM_SEED;
+        hash = checksum_bytes(hash, &(self.particle_count as u64).to_le_bytes());
+        hash = checksum_bytes(hash, &self.time.to_le_bytes());
+        hash = checksum_bytes(hash, bytemuck::cast_slice(&self.particle_ids));
+        hash = checksum_bytes(hash, bytemuck::cast_slice(&self.positions));
+        hash = checksum_bytes(hash, bytemuck::cast_slice(&self.velocities));
+        hash = checksum_bytes(hash, &self.particle_phases);
+        checksum_bytes(hash, bytemuck::cast_slice(&self.rigid_body_states))
+    }
+
     /* Parameters */
-    pub fn set_world_dimensions(&mut self, world_width: f32, world_height: f32) {
-        self.world_dimensions = Vector2D::new(world_width, world_height);
+    /// Takes effect on the next [`Sim2D::reset_sim`]. Fails, keeping the
+    /// current layout, for a layout a config would reject, including one too
+    /// small for the particles.
+    pub fn set_layout(&mut self, layout: InitialLayout) -> Result<(), ConfigError> {
+        layout.validate()?;
+        layout.validate_fit(
+            self.initial_particle_count,
+            self.world_dimensions,
+            self.particle_radius,
+        )?;
+
+        self.layout = layout;
+        Ok(())
+    }
+
+    /// Fails, keeping the current world, for dimensions a config would reject.
+    pub fn set_world_dimensions(
+        &mut self,
+        world_width: f32,
+        world_height: f32,
+    ) -> Result<(), ConfigError> {
+        if !world_width.is_finite() {
+            return Err(ConfigError::NotFinite("worldWidth"));
+        }
+        if !world_height.is_finite() {
+            return Err(ConfigError::NotFinite("worldHeight"));
+        }
+        if world_width <= 2.0 * self.particle_radius || world_height <= 2.0 * self.particle_radius {
+            return Err(ConfigError::WorldTooSmall);
+        }
+        let world_dimensions = Vector2D::new(world_width, world_height);
+        self.spatial_index
+            .kind()
+            .validate(self.smoothing_radius, world_dimensions)?;
+        self.layout.validate_fit(
+            self.initial_particle_count,
+            world_dimensions,
+            self.particle_radius,
+        )?;
+        if let Some(boundary) = &self.boundary {
+            boundary.validate_in(world_dimensions)?;
+        }
+
+        self.world_dimensions = world_dimensions;
+        // The field is sampled over the world box, so it has to follow it
+        self.resample_boundary();
+        Ok(())
     }
 
     pub fn set_gravity(&mut self, gravity: f32) {
@@ -518,15 +1620,89 @@ impl Sim2D {
 
     pub fn set_pressure_multiplier(&mut self, pressure_multiplier: f32) {
         self.pressure_multiplier = pressure_multiplier;
-        self.near_pressure_multiplier = pressure_multiplier / 100.0;
     }
 
     pub fn set_viscosity_strength(&mut self, viscosity_strength: f32) {
         self.viscosity_strength = viscosity_strength;
     }
 
-    pub fn set_smoothing_radius(&mut self, smoothing_radius: f32) {
+    pub fn viscosity_model(&self) -> ViscosityModel {
+        self.viscosity_model
+    }
+
+    /// Switches viscosity model from the next step on.
+    pub fn set_viscosity_model(&mut self, viscosity_model: ViscosityModel) {
+        self.viscosity_model = viscosity_model;
+    }
+
+    /// Fails, keeping the current tension, for a tension a config would
+    /// reject.
+    pub fn set_interface_tension(&mut self, interface_tension: f32) -> Result<(), ConfigError> {
+        self.interface_tension = non_negative("interfaceTension", interface_tension)?;
+        Ok(())
+    }
+
+    /// Strength of the cohesion that beads droplets and holds thin sheets
+    /// together. 0 turns it off. Fails, keeping the current strength, for a
+    /// strength a config would reject.
+    pub fn set_surface_tension(&mut self, surface_tension: f32) -> Result<(), ConfigError> {
+        self.surface_tension = non_negative("surfaceTension", surface_tension)?;
+        Ok(())
+    }
+
+    /// Strength of the force that keeps small swirls spinning. 0 turns it
+    /// off. Fails, keeping the current strength, for a strength a config
+    /// would reject.
+    pub fn set_vorticity_confinement(
+        &mut self,
+        vorticity_confinement: f32,
+    ) -> Result<(), ConfigError> {
+        self.vorticity_confinement = non_negative("vorticityConfinement", vorticity_confinement)?;
+        Ok(())
+    }
+
+    /// Rate heat spreads between particles. 0 insulates them. Fails, keeping
+    /// the current rate, for a rate a config would reject.
+    pub fn set_thermal_diffusivity(&mut self, thermal_diffusivity: f32) -> Result<(), ConfigError> {
+        self.thermal_diffusivity = non_negative("thermalDiffusivity", thermal_diffusivity)?;
+        Ok(())
+    }
+
+    /// Fractional change in weight per degree above the ambient temperature.
+    /// 0 turns buoyancy off. Fails, keeping the current expansion, for an
+    /// expansion a config would reject.
+    pub fn set_thermal_expansion(&mut self, thermal_expansion: f32) -> Result<(), ConfigError> {
+        self.thermal_expansion = non_negative("thermalExpansion", thermal_expansion)?;
+        Ok(())
+    }
+
+    /// Rate dye spreads between particles. 0 keeps it sharp. Fails, keeping
+    /// the current rate, for a rate a config would reject.
+    pub fn set_dye_diffusivity(&mut self, dye_diffusivity: f32) -> Result<(), ConfigError> {
+        self.dye_diffusivity = non_negative("dyeDiffusivity", dye_diffusivity)?;
+        Ok(())
+    }
+
+    /// Washes the dye out of every particle.
+    pub fn clear_dyes(&mut self) {
+        self.dyes.fill([0.0; DYE_CHANNELS]);
+    }
+
+    /// Fails, keeping the current radius, for a radius a config would
+    /// reject, including one too small for a flat grid over the world.
+    pub fn set_smoothing_radius(&mut self, smoothing_radius: f32) -> Result<(), ConfigError> {
+        if !smoothing_radius.is_finite() {
+            return Err(ConfigError::NotFinite("smoothingRadius"));
+        }
+        if smoothing_radius <= 0.0 {
+            return Err(ConfigError::NotPositive("smoothingRadius"));
+        }
+        self.spatial_index
+            .kind()
+            .validate(smoothing_radius, self.world_dimensions)?;
+
         self.smoothing_radius = smoothing_radius;
+        Ok(())
     }
 
     pub fn set_interaction_strength(&mut self, interaction_strength: f32) {
@@ -537,6 +1713,213 @@ impl Sim2D {
         self.interaction_radius = interaction_radius;
     }
 
+    /* Obstacles */
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    /// Adds an obstacle and returns the id assigned to it. Ids are handed out
+    /// in order, so replaying the same calls gives the same ids.
+    pub fn add_obstacle(&mut self, mut obstacle: Obstacle) -> u32 {
+        obstacle.id = self.next_obstacle_id;
+        self.next_obstacle_id += 1;
+        self.obstacles.push(obstacle);
+        self.next_obstacle_id - 1
+    }
+
+    pub fn remove_obstacle(&mut self, id: u32) -> bool {
+        let count = self.obstacles.len();
+        self.obstacles.retain(|o| o.id != id);
+        self.obstacles.len() != count
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.obstacles.clear();
+    }
+
+    /* Kinematic bodies */
+    /// Simulation time in seconds, which drives the kinematic bodies.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn kinematic_bodies(&self) -> &[KinematicBody] {
+        &self.kinematic_bodies
+    }
+
+    /// Current pose and velocity of each kinematic body, in the same order
+    /// as [`Sim2D::kinematic_bodies`].
+    pub fn kinematic_body_states(&self) -> Vec<BodyState> {
+        self.kinematic_body_states_at(self.time)
+    }
+
+    /// Adds a kinematic body and returns the id assigned to it.
+    pub fn add_kinematic_body(&mut self, mut body: KinematicBody) -> u32 {
+        body.id = self.next_kinematic_body_id;
+        self.next_kinematic_body_id += 1;
+        self.kinematic_bodies.push(body);
+        self.next_kinematic_body_id - 1
+    }
+
+    pub fn remove_kinematic_body(&mut self, id: u32) -> bool {
+        let count = self.kinematic_bodies.len();
+        self.kinematic_bodies.retain(|b| b.id != id);
+        self.kinematic_bodies.len() != count
+    }
+
+    pub fn clear_kinematic_bodies(&mut self) {
+        self.kinematic_bodies.clear();
+    }
+
+    /* Rigid bodies */
+    pub fn rigid_bodies(&self) -> &[RigidBody] {
+        &self.rigid_bodies
+    }
+
+    pub fn rigid_body_states(&self) -> &[BodyState] {
+        &self.rigid_body_states
+    }
+
+    /// Adds a rigid body at its initial pose and returns the id assigned to
+    /// it. Invalidates the rigid body state pointer.
+    pub fn add_rigid_body(&mut self, mut body: RigidBody) -> u32 {
+        body.id = self.next_rigid_body_id;
+        self.next_rigid_body_id += 1;
+        self.rigid_body_states.push(body.initial_state());
+        self.rigid_body_forces.push((Vector2D::new(0.0, 0.0), 0.0));
+        self.rigid_bodies.push(body);
+        self.next_rigid_body_id - 1
+    }
+
+    pub fn remove_rigid_body(&mut self, id: u32) -> bool {
+        let Some(index) = self.rigid_bodies.iter().position(|b| b.id == id) else {
+            return false;
+        };
+
+        self.rigid_bodies.remove(index);
+        self.rigid_body_states.remove(index);
+        self.rigid_body_forces.remove(index);
+        true
+    }
+
+    pub fn clear_rigid_bodies(&mut self) {
+        self.rigid_bodies.clear();
+        self.rigid_body_states.clear();
+        self.rigid_body_forces.clear();
+    }
+
+    /* Open flow */
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    /// Adds an emitter and returns the id assigned to it. Fails for an
+    /// emitter a config would reject, including one for a phase that doesn't
+    /// exist.
+    pub fn add_emitter(&mut self, mut emitter: Emitter) -> Result<u32, ConfigError> {
+        emitter.validate()?;
+        if emitter.phase as usize > self.phases.len() {
+            return Err(ConfigError::InvalidEmitter("phase does not exist"));
+        }
+
+        emitter.id = self.next_emitter_id;
+        self.next_emitter_id += 1;
+        self.emitters.push(emitter);
+        self.emitter_states.push(EmitterState::default());
+        Ok(self.next_emitter_id - 1)
+    }
+
+    pub fn remove_emitter(&mut self, id: u32) -> bool {
+        let Some(index) = self.emitters.iter().position(|e| e.id == id) else {
+            return false;
+        };
+
+        self.emitters.remove(index);
+        self.emitter_states.remove(index);
+        true
+    }
+
+    pub fn clear_emitters(&mut self) {
+        self.emitters.clear();
+        self.emitter_states.clear();
+    }
+
+    pub fn sinks(&self) -> &[Sink] {
+        &self.sinks
+    }
+
+    /// Adds a sink and returns the id assigned to it.
+    pub fn add_sink(&mut self, mut sink: Sink) -> u32 {
+        sink.id = self.next_sink_id;
+        self.next_sink_id += 1;
+        self.sinks.push(sink);
+        self.next_sink_id - 1
+    }
+
+    pub fn remove_sink(&mut self, id: u32) -> bool {
+        let count = self.sinks.len();
+        self.sinks.retain(|s| s.id != id);
+        self.sinks.len() != count
+    }
+
+    pub fn clear_sinks(&mut self) {
+        self.sinks.clear();
+    }
+
+    /* Heat sources */
+    pub fn heat_sources(&self) -> &[HeatSource] {
+        &self.heat_sources
+    }
+
+    /// Adds a heat source and returns the id assigned to it.
+    pub fn add_heat_source(&mut self, mut source: HeatSource) -> u32 {
+        source.id = self.next_heat_source_id;
+        self.next_heat_source_id += 1;
+        self.heat_sources.push(source);
+        self.next_heat_source_id - 1
+    }
+
+    pub fn remove_heat_source(&mut self, id: u32) -> bool {
+        let count = self.heat_sources.len();
+        self.heat_sources.retain(|s| s.id != id);
+        self.heat_sources.len() != count
+    }
+
+    pub fn clear_heat_sources(&mut self) {
+        self.heat_sources.clear();
+    }
+
+    /* Phases */
+    /// Fluids mixed into the base fluid. Particle phase `i` is `phases()[i - 1]`.
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    /* Boundary */
+    pub fn boundary(&self) -> Option<&Boundary> {
+        self.boundary.as_ref()
+    }
+
+    /// Replaces the container geometry and resamples its distance field.
+    /// The world box still bounds the simulation either way. Fails, keeping
+    /// the current boundary, for a boundary a config would reject.
+    pub fn set_boundary(&mut self, boundary: Option<Boundary>) -> Result<(), ConfigError> {
+        if let Some(boundary) = &boundary {
+            boundary.validate_in(self.world_dimensions)?;
+        }
+
+        self.boundary = boundary;
+        self.resample_boundary();
+        Ok(())
+    }
+
+    fn resample_boundary(&mut self) {
+        self.boundary_field = self
+            .boundary
+            .as_ref()
+            .map(|b| SignedDistanceField::from_boundary(b, self.world_dimensions));
+    }
+
     /* Interaction */
     pub fn enable_pull_interaction(&mut self, x: f32, y: f32) {
         self.interaction_position = Some(Vector2D::new(x, y));
@@ -552,12 +1935,457 @@ impl Sim2D {
         self.interaction_position = None;
         self.interaction_scale = 0.0;
     }
+
+    /// Paints dye `channel` into the particles within the interaction radius
+    /// of the point, once a step until disabled. Fails, keeping the current
+    /// brush, for a channel past [`DYE_CHANNELS`].
+    pub fn enable_dye_interaction(
+        &mut self,
+        x: f32,
+        y: f32,
+        channel: usize,
+    ) -> Result<(), ConfigError> {
+        if channel >= DYE_CHANNELS {
+            return Err(ConfigError::InvalidDyeChannel(channel));
+        }
+
+        self.dye_brush = Some(DyeBrush {
+            position: Vector2D::new(x, y),
+            channel,
+        });
+        Ok(())
+    }
+
+    pub fn disable_dye_interaction(&mut self) {
+        self.dye_brush = None;
+    }
+}
+
+// A quantity the particles carry and spread to their neighbours, such as
+// heat or dye
+trait Diffused: Copy + Send + Sync {
+    const ZERO: Self;
+
+    // `self += (value - neighbour) * weight`
+    fn add_difference(&mut self, value: Self, neighbour: Self, weight: f32);
+
+    // `self += rate * scale`
+    fn add_scaled(&mut self, rate: Self, scale: f32);
+}
+
+impl Diffused for f32 {
+    const ZERO: Self = 0.0;
+
+    fn add_difference(&mut self, value: Self, neighbour: Self, weight: f32) {
+        *self += (value - neighbour) * weight;
+    }
+
+    fn add_scaled(&mut self, rate: Self, scale: f32) {
+        *self += rate * scale;
+    }
+}
+
+// Channel by channel
+impl<const N: usize> Diffused for [f32; N] {
+    const ZERO: Self = [0.0; N];
+
+    fn add_difference(&mut self, value: Self, neighbour: Self, weight: f32) {
+        for channel in 0..N {
+            self[channel].add_difference(value[channel], neighbour[channel], weight);
+        }
+    }
+
+    fn add_scaled(&mut self, rate: Self, scale: f32) {
+        for channel in 0..N {
+            self[channel].add_scaled(rate[channel], scale);
+        }
+    }
+}
+
+// Moves `buffer[order[i]]` to `buffer[i]`, in place so the buffer keeps its
+// capacity
+fn permute<T: Copy + Send + Sync>(buffer: &mut [T], order: &[usize]) {
+    let permuted: Vec<T> = order.par_iter().map(|&i| buffer[i]).collect();
+    buffer.copy_from_slice(&permuted);
+}
+
+// Buffer of `len` copies of `value` that can grow to `capacity` in place
+fn particle_buffer<T: Clone>(value: T, len: usize, capacity: usize) -> Vec<T> {
+    let mut buffer = Vec::with_capacity(capacity.max(len));
+    buffer.resize(len, value);
+    buffer
 }
 
-fn density_to_pressure(density: f32, target_density: f32, pressure_multiplier: f32) -> f32 {
-    (density - target_density) * pressure_multiplier
+// The checks `SimConfig::validate` makes of a parameter that 0 turns off
+fn non_negative(field: &'static str, value: f32) -> Result<f32, ConfigError> {
+    if !value.is_finite() {
+        return Err(ConfigError::NotFinite(field));
+    }
+    if value < 0.0 {
+        return Err(ConfigError::Negative(field));
+    }
+
+    Ok(value)
 }
 
 fn density_to_near_pressure(near_density: f32, near_pressure_multiplier: f32) -> f32 {
     near_pressure_multiplier * near_density
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::InitialLayout;
+
+    // Distance from the centre of the fluid to its furthest particle
+    fn spread(sim: &Sim2D) -> f32 {
+        let centre = sim
+            .positions
+            .iter()
+            .fold(Vector2D::new(0.0, 0.0), |sum, p| sum + *p)
+            / sim.particle_count() as f32;
+        sim.positions
+            .iter()
+            .map(|p| (*p - centre).magnitude())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn surface_tension_holds_drop_together() {
+        // Weightless square of fluid, sparser than its rest density
+        let square = |surface_tension: f32| {
+            let mut sim = Sim2D::new(
+                SimConfig::builder()
+                    .particle_count(400)
+                    .world_dimensions(6.0, 6.0)
+                    .gravity(0.0)
+                    .surface_tension(surface_tension)
+                    .layout(InitialLayout::Mask {
+                        width: 3,
+                        height: 3,
+                        data: vec![0, 0, 0, 0, 255, 0, 0, 0, 0],
+                    })
+                    .build()
+                    .unwrap(),
+            );
+            for _ in 0..120 {
+                sim.step(1.0 / 60.0);
+            }
+            spread(&sim)
+        };
+
+        // Without cohesion the corners spread out to the walls
+        assert!(square(2.0) < 0.5 * square(0.0));
+    }
+
+    #[test]
+    fn setters_reject_what_the_config_would() {
+        let mut sim = Sim2D::new(
+            SimConfig::builder()
+                .particle_count(100)
+                .world_dimensions(4.0, 3.0)
+                .build()
+                .unwrap(),
+        );
+
+        assert_eq!(
+            sim.set_smoothing_radius(0.0),
+            Err(ConfigError::NotPositive("smoothingRadius"))
+        );
+        assert_eq!(
+            sim.set_smoothing_radius(f32::NAN),
+            Err(ConfigError::NotFinite("smoothingRadius"))
+        );
+        // Millions of flat grid cells, which would be hashed instead
+        assert!(sim.set_smoothing_radius(1e-4).is_err());
+        assert!(sim.set_world_dimensions(4000.0, 3000.0).is_err());
+        assert_eq!(
+            sim.set_world_dimensions(0.01, 3.0),
+            Err(ConfigError::WorldTooSmall)
+        );
+        // Rejected values leave the simulation as it was
+        assert_eq!(sim.config().smoothing_radius, 0.35);
+        assert_eq!(sim.config().world_width, 4.0);
+
+        sim.set_spatial_index(SpatialIndexKind::HashGrid).unwrap();
+        sim.set_smoothing_radius(1e-4).unwrap();
+        assert!(sim.set_spatial_index(SpatialIndexKind::FlatGrid).is_err());
+        assert_eq!(sim.spatial_index(), SpatialIndexKind::HashGrid);
+        sim.step(1.0 / 60.0);
+    }
+
+    #[test]
+    fn deterministic_mode_ignores_thread_count() {
+        let run = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut sim = Sim2D::new(
+                    SimConfig::builder()
+                        .particle_count(400)
+                        .world_dimensions(4.0, 3.0)
+                        .deterministic(true)
+                        .layout(InitialLayout::DamBreak {
+                            width: 0.4,
+                            height: 0.8,
+                        })
+                        .build()
+                        .unwrap(),
+                );
+                sim.enable_push_interaction(-1.5, -1.0);
+                (0..30)
+                    .map(|_| {
+                        sim.step(1.0 / 60.0);
+                        sim.checksum()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let checksums = run(1);
+        assert_eq!(run(4), checksums);
+        assert_ne!(checksums[0], checksums[1]);
+    }
+
+    #[test]
+    fn particles_are_stored_in_bucket_order_under_stable_ids() {
+        let mut sim = Sim2D::new(
+            SimConfig::builder()
+                .particle_count(400)
+                .world_dimensions(4.0, 3.0)
+                .gravity(0.0)
+                .layout(InitialLayout::PoissonDisk { seed: 5 })
+                .build()
+                .unwrap(),
+        );
+        let mut start = vec![Vector2D::new(0.0, 0.0); 400];
+        for (id, position) in sim.particle_ids.iter().zip(&sim.positions) {
+            start[*id as usize] = *position;
+        }
+
+        sim.step(1.0 / 600.0);
+
+        // Each id still names the particle that started there
+        for (id, position) in sim.particle_ids.iter().zip(&sim.positions) {
+            let moved = (*position - start[*id as usize]).magnitude();
+            assert!(moved < 0.05, "particle {id} moved {moved}");
+        }
+        // Already in order, so bucketing again leaves them where they are
+        sim.spatial_index.rebuild(
+            &sim.predicted_positions,
+            sim.smoothing_radius,
+            sim.world_dimensions,
+            true,
+        );
+        assert!(
+            sim.spatial_index
+                .order()
+                .iter()
+                .enumerate()
+                .all(|(i, &index)| i == index)
+        );
+    }
+
+    #[test]
+    fn density_kernels_agree_on_a_lattice() {
+        let kernels = [
+            KernelKind::Spiky,
+            KernelKind::Poly6,
+            KernelKind::CubicSpline,
+            KernelKind::WendlandC2,
+            KernelKind::WendlandC4,
+        ];
+        // Mean density of the initial grid, away from the walls
+        let densities = kernels.map(|kernel| {
+            let mut sim = Sim2D::new(
+                SimConfig::builder()
+                    .particle_count(900)
+                    .world_dimensions(3.0, 3.0)
+                    .density_kernel(kernel)
+                    .build()
+                    .unwrap(),
+            );
+            assert_eq!(sim.config().density_kernel, kernel);
+
+            let interior: Vec<f32> = sim
+                .positions()
+                .iter()
+                .zip(&sim.densities)
+                .filter(|(p, _)| p.x.abs() < 1.0 && p.y.abs() < 1.0)
+                .map(|(_, density)| density.0)
+                .collect();
+            let density = interior.iter().sum::<f32>() / interior.len() as f32;
+
+            for _ in 0..60 {
+                sim.step(1.0 / 60.0);
+            }
+            assert!(
+                sim.positions()
+                    .iter()
+                    .all(|p| p.x.is_finite() && p.y.is_finite()),
+                "{kernel:?}"
+            );
+            density
+        });
+
+        // Every kernel integrates to 1, so they see about the same density
+        for (kernel, density) in kernels.iter().zip(densities) {
+            assert!(
+                (density / densities[0] - 1.0).abs() < 0.05,
+                "{kernel:?}: {density} against {}",
+                densities[0]
+            );
+        }
+    }
+
+    // Weightless box of fluid turning anticlockwise at `angular_velocity`
+    fn spinning_box(angular_velocity: f32, vorticity_confinement: f32) -> Sim2D {
+        let mut sim = Sim2D::new(
+            SimConfig::builder()
+                .particle_count(900)
+                .world_dimensions(3.0, 3.0)
+                .gravity(0.0)
+                .vorticity_confinement(vorticity_confinement)
+                .build()
+                .unwrap(),
+        );
+        for (velocity, position) in sim.velocities.iter_mut().zip(&sim.positions) {
+            *velocity = position.perpendicular() * angular_velocity;
+        }
+        sim
+    }
+
+    #[test]
+    fn rigid_rotation_has_twice_its_angular_velocity_as_vorticity() {
+        let mut sim = spinning_box(1.5, 0.0);
+        sim.update_vorticities();
+
+        for (position, vorticity) in sim.positions.iter().zip(&sim.vorticities) {
+            if position.x.abs() < 1.0 && position.y.abs() < 1.0 {
+                assert!((vorticity - 3.0).abs() < 0.3, "{vorticity} at {position:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn vorticity_confinement_keeps_swirls_spinning() {
+        let mean_vorticity = |vorticity_confinement: f32| {
+            let mut sim = spinning_box(2.0, vorticity_confinement);
+            for _ in 0..60 {
+                sim.step(1.0 / 60.0);
+            }
+            sim.vorticities.iter().map(|v| v.abs()).sum::<f32>() / sim.particle_count() as f32
+        };
+
+        assert!(mean_vorticity(2.0) > 1.2 * mean_vorticity(0.0));
+    }
+
+    #[test]
+    fn vorticity_confinement_setter_keeps_the_current_strength_on_bad_input() {
+        let mut sim = spinning_box(2.0, 0.0);
+        sim.set_vorticity_confinement(1.0).unwrap();
+
+        assert_eq!(
+            sim.set_vorticity_confinement(-1.0),
+            Err(ConfigError::Negative("vorticityConfinement"))
+        );
+        assert_eq!(
+            sim.set_vorticity_confinement(f32::INFINITY),
+            Err(ConfigError::NotFinite("vorticityConfinement"))
+        );
+        assert_eq!(sim.config().vorticity_confinement, 1.0);
+    }
+
+    #[test]
+    fn interface_tension_setter_keeps_the_current_tension_on_bad_input() {
+        let mut sim = Sim2D::new(SimConfig::builder().particle_count(100).build().unwrap());
+        sim.set_interface_tension(2.0).unwrap();
+
+        assert_eq!(
+            sim.set_interface_tension(-2.0),
+            Err(ConfigError::Negative("interfaceTension"))
+        );
+        assert_eq!(
+            sim.set_interface_tension(f32::NAN),
+            Err(ConfigError::NotFinite("interfaceTension"))
+        );
+        assert_eq!(sim.config().interface_tension, 2.0);
+    }
+
+    #[test]
+    fn surface_tension_setter_keeps_the_current_strength_on_bad_input() {
+        let mut sim = Sim2D::new(SimConfig::builder().particle_count(100).build().unwrap());
+        sim.set_surface_tension(0.3).unwrap();
+
+        assert_eq!(
+            sim.set_surface_tension(-0.3),
+            Err(ConfigError::Negative("surfaceTension"))
+        );
+        assert_eq!(
+            sim.set_surface_tension(f32::NAN),
+            Err(ConfigError::NotFinite("surfaceTension"))
+        );
+        assert_eq!(sim.config().surface_tension, 0.3);
+    }
+
+    #[test]
+    fn thermal_setters_keep_the_current_value_on_bad_input() {
+        let mut sim = Sim2D::new(SimConfig::builder().particle_count(100).build().unwrap());
+        sim.set_thermal_diffusivity(0.5).unwrap();
+
+        assert_eq!(
+            sim.set_thermal_diffusivity(-1.0),
+            Err(ConfigError::Negative("thermalDiffusivity"))
+        );
+        assert_eq!(
+            sim.set_thermal_expansion(f32::NAN),
+            Err(ConfigError::NotFinite("thermalExpansion"))
+        );
+        assert_eq!(sim.config().thermal_diffusivity, 0.5);
+        assert!(sim.config().validate().is_ok());
+    }
+
+    #[test]
+    fn emitters_for_missing_phases_are_rejected() {
+        let mut sim = Sim2D::new(SimConfig::builder().particle_count(100).build().unwrap());
+        let emitter = Emitter {
+            id: 0,
+            position: Vector2D::new(0.0, 0.0),
+            velocity: Vector2D::new(1.0, 0.0),
+            rate: 50.0,
+            width: 0.1,
+            phase: 1,
+        };
+
+        assert_eq!(
+            sim.add_emitter(emitter.clone()),
+            Err(ConfigError::InvalidEmitter("phase does not exist"))
+        );
+        assert!(sim.emitters().is_empty());
+        assert_eq!(
+            sim.add_emitter(Emitter {
+                phase: 0,
+                ..emitter
+            }),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn dye_diffusivity_setter_keeps_the_current_rate_on_bad_input() {
+        let mut sim = Sim2D::new(SimConfig::builder().particle_count(100).build().unwrap());
+        sim.set_dye_diffusivity(0.2).unwrap();
+
+        assert_eq!(
+            sim.set_dye_diffusivity(-0.1),
+            Err(ConfigError::Negative("dyeDiffusivity"))
+        );
+        assert_eq!(
+            sim.set_dye_diffusivity(f32::INFINITY),
+            Err(ConfigError::NotFinite("dyeDiffusivity"))
+        );
+        assert_eq!(sim.config().dye_diffusivity, 0.2);
+    }
+}
@@ -0,0 +1,104 @@
+use rayon::prelude::*;
+
+use crate::{
+    hashing::{GRID_OFFSETS, get_cell},
+    spatial::SpatialIndex,
+    vector_2d::Vector2D,
+};
+
+// Particle within the smoothing radius of a sample particle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Neighbour {
+    pub index: usize,
+    // From the sample to the neighbour
+    pub offset: Vector2D,
+    pub distance: f32,
+}
+
+// One particle's neighbours as columns, so the SIMD passes can load several
+// distances or offsets at once
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Neighbours {
+    pub indices: Vec<usize>,
+    pub offsets_x: Vec<f32>,
+    pub offsets_y: Vec<f32>,
+    pub distances: Vec<f32>,
+}
+
+impl Neighbours {
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.indices.clear();
+        self.offsets_x.clear();
+        self.offsets_y.clear();
+        self.distances.clear();
+    }
+
+    pub fn push(&mut self, neighbour: Neighbour) {
+        self.indices.push(neighbour.index);
+        self.offsets_x.push(neighbour.offset.x);
+        self.offsets_y.push(neighbour.offset.y);
+        self.distances.push(neighbour.distance);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Neighbour> + '_ {
+        (0..self.len()).map(|k| Neighbour {
+            index: self.indices[k],
+            offset: Vector2D::new(self.offsets_x[k], self.offsets_y[k]),
+            distance: self.distances[k],
+        })
+    }
+}
+
+// Every particle's neighbours, found once per substep so the density, force
+// and extension passes share a single grid walk
+#[derive(Default)]
+pub(crate) struct NeighbourList {
+    lists: Vec<Neighbours>,
+}
+
+impl NeighbourList {
+    // Lists are cleared rather than dropped, so after the first few steps
+    // this doesn't allocate
+    pub fn rebuild(&mut self, index: &dyn SpatialIndex, positions: &[Vector2D], radius: f32) {
+        let radius_squared = radius * radius;
+
+        self.lists.resize_with(positions.len(), Neighbours::default);
+        self.lists
+            .par_iter_mut()
+            .zip(positions.par_iter())
+            .for_each(|(list_ref, position_ref)| {
+                list_ref.clear();
+
+                let origin_cell = get_cell(*position_ref, radius);
+                for (x, y) in GRID_OFFSETS {
+                    let (neighbour_indices, neighbour_positions) =
+                        index.cell((origin_cell.0 + x, origin_cell.1 + y));
+
+                    for (&neighbour_index, &neighbour_position) in
+                        neighbour_indices.iter().zip(neighbour_positions)
+                    {
+                        let offset = neighbour_position - *position_ref;
+                        let squared_distance = offset.dot(offset);
+                        if squared_distance > radius_squared {
+                            continue;
+                        }
+
+                        list_ref.push(Neighbour {
+                            index: neighbour_index,
+                            offset,
+                            distance: squared_distance.sqrt(),
+                        });
+                    }
+                }
+            });
+    }
+
+    // Neighbours of particle `index`, including itself at distance 0
+    pub fn of(&self, index: usize) -> &Neighbours {
+        &self.lists[index]
+    }
+}
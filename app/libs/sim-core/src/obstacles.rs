@@ -0,0 +1,397 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ConfigError, vector_2d::Vector2D};
+
+/// Static collision geometry placed inside the world box.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Shape {
+    Circle {
+        centre: Vector2D,
+        radius: f32,
+    },
+    /// Rectangle rotated anticlockwise by `rotation` radians about its centre.
+    OrientedBox {
+        centre: Vector2D,
+        half_extents: Vector2D,
+        rotation: f32,
+    },
+    /// Segment swept by a disc of radius `thickness`.
+    Segment {
+        start: Vector2D,
+        end: Vector2D,
+        thickness: f32,
+    },
+    /// Convex polygon, in either winding order.
+    Polygon {
+        vertices: Vec<Vector2D>,
+    },
+}
+
+impl Shape {
+    /// Signed distance from `point` to the surface (negative inside) and the
+    /// outward surface normal at the closest point.
+    pub fn signed_distance(&self, point: Vector2D) -> (f32, Vector2D) {
+        match self {
+            Shape::Circle { centre, radius } => {
+                let offset = point - *centre;
+                let distance = offset.magnitude();
+                let normal = if distance > f32::EPSILON {
+                    offset / distance
+                } else {
+                    Vector2D::new(0.0, 1.0)
+                };
+
+                (distance - radius, normal)
+            }
+            Shape::OrientedBox {
+                centre,
+                half_extents,
+                rotation,
+            } => {
+                let local = (point - *centre).rotate(-rotation);
+                let q = local.abs() - *half_extents;
+                let sign = Vector2D::new(local.x.signum(), local.y.signum());
+
+                let (distance, normal) = if q.x > 0.0 || q.y > 0.0 {
+                    let outside = Vector2D::new(q.x.max(0.0), q.y.max(0.0));
+                    let distance = outside.magnitude();
+                    (distance, outside * sign / distance)
+                } else if q.x > q.y {
+                    (q.x, Vector2D::new(sign.x, 0.0))
+                } else {
+                    (q.y, Vector2D::new(0.0, sign.y))
+                };
+
+                (distance, normal.rotate(*rotation))
+            }
+            Shape::Segment {
+                start,
+                end,
+                thickness,
+            } => {
+                let (distance, normal) = segment_distance(point, *start, *end);
+                (distance - thickness, normal)
+            }
+            Shape::Polygon { vertices } => polygon_distance(point, vertices),
+        }
+    }
+
+    /// The same shape rotated anticlockwise by `rotation` radians about the
+    /// origin, then moved by `offset`.
+    pub fn transformed(&self, offset: Vector2D, rotation: f32) -> Shape {
+        let point = |p: Vector2D| p.rotate(rotation) + offset;
+
+        match self {
+            Shape::Circle { centre, radius } => Shape::Circle {
+                centre: point(*centre),
+                radius: *radius,
+            },
+            Shape::OrientedBox {
+                centre,
+                half_extents,
+                rotation: box_rotation,
+            } => Shape::OrientedBox {
+                centre: point(*centre),
+                half_extents: *half_extents,
+                rotation: box_rotation + rotation,
+            },
+            Shape::Segment {
+                start,
+                end,
+                thickness,
+            } => Shape::Segment {
+                start: point(*start),
+                end: point(*end),
+                thickness: *thickness,
+            },
+            Shape::Polygon { vertices } => Shape::Polygon {
+                vertices: vertices.iter().map(|v| point(*v)).collect(),
+            },
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            Shape::Circle { radius, .. } if *radius > 0.0 => Ok(()),
+            Shape::Circle { .. } => Err(ConfigError::InvalidObstacle(
+                "circle radius must be greater than 0",
+            )),
+            Shape::OrientedBox { half_extents, .. }
+                if half_extents.x > 0.0 && half_extents.y > 0.0 =>
+            {
+                Ok(())
+            }
+            Shape::OrientedBox { .. } => Err(ConfigError::InvalidObstacle(
+                "box half extents must be greater than 0",
+            )),
+            Shape::Segment {
+                start,
+                end,
+                thickness,
+            } => {
+                if *thickness < 0.0 {
+                    Err(ConfigError::InvalidObstacle(
+                        "segment thickness must not be negative",
+                    ))
+                } else if start == end && *thickness == 0.0 {
+                    Err(ConfigError::InvalidObstacle("segment is degenerate"))
+                } else {
+                    Ok(())
+                }
+            }
+            Shape::Polygon { vertices } => {
+                if vertices.len() < 3 {
+                    return Err(ConfigError::InvalidObstacle(
+                        "polygon needs at least 3 vertices",
+                    ));
+                }
+
+                let turns: Vec<f32> = (0..vertices.len())
+                    .map(|i| {
+                        let a = vertices[i];
+                        let b = vertices[(i + 1) % vertices.len()];
+                        let c = vertices[(i + 2) % vertices.len()];
+                        (b - a).cross(c - b)
+                    })
+                    .collect();
+
+                if turns.iter().all(|t| *t > 0.0) || turns.iter().all(|t| *t < 0.0) {
+                    Ok(())
+                } else {
+                    Err(ConfigError::InvalidObstacle("polygon must be convex"))
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Obstacle {
+    /// Assigned by [`crate::Sim2D::add_obstacle`]; must be unique in a config.
+    #[serde(default)]
+    pub id: u32,
+    pub shape: Shape,
+    /// Fraction of normal velocity kept after a bounce.
+    pub restitution: f32,
+    /// Fraction of tangential velocity removed on contact.
+    pub friction: f32,
+}
+
+impl Obstacle {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(0.0..=1.0).contains(&self.restitution) {
+            return Err(ConfigError::OutOfRange("restitution"));
+        }
+        if !(0.0..=1.0).contains(&self.friction) {
+            return Err(ConfigError::OutOfRange("friction"));
+        }
+
+        self.shape.validate()
+    }
+
+    pub(crate) fn collide(
+        &self,
+        position: &mut Vector2D,
+        velocity: &mut Vector2D,
+        particle_radius: f32,
+    ) {
+        let (distance, normal) = self.shape.signed_distance(*position);
+        if distance < particle_radius {
+            resolve_contact(
+                position,
+                velocity,
+                particle_radius - distance,
+                normal,
+                self.restitution,
+                self.friction,
+            );
+        }
+    }
+}
+
+/// Pushes a particle `depth` along `normal` and reflects the approaching part
+/// of its velocity.
+pub(crate) fn resolve_contact(
+    position: &mut Vector2D,
+    velocity: &mut Vector2D,
+    depth: f32,
+    normal: Vector2D,
+    restitution: f32,
+    friction: f32,
+) {
+    *position += normal * depth;
+
+    let normal_speed = velocity.dot(normal);
+    if normal_speed < 0.0 {
+        let normal_velocity = normal * normal_speed;
+        let tangent_velocity = *velocity - normal_velocity;
+        *velocity = tangent_velocity * (1.0 - friction) - normal_velocity * restitution;
+    }
+}
+
+pub(crate) fn segment_distance(point: Vector2D, start: Vector2D, end: Vector2D) -> (f32, Vector2D) {
+    let edge = end - start;
+    let length_squared = edge.dot(edge);
+    let t = if length_squared > 0.0 {
+        ((point - start).dot(edge) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let offset = point - (start + edge * t);
+    let distance = offset.magnitude();
+    let normal = if distance > f32::EPSILON {
+        offset / distance
+    } else if length_squared > 0.0 {
+        edge.perpendicular().normalise()
+    } else {
+        Vector2D::new(0.0, 1.0)
+    };
+
+    (distance, normal)
+}
+
+fn polygon_distance(point: Vector2D, vertices: &[Vector2D]) -> (f32, Vector2D) {
+    let area: f32 = (0..vertices.len())
+        .map(|i| vertices[i].cross(vertices[(i + 1) % vertices.len()]))
+        .sum();
+    // Outward normals point right of each edge for anticlockwise polygons
+    let winding = if area > 0.0 { -1.0 } else { 1.0 };
+
+    let mut max_separation = f32::NEG_INFINITY;
+    let mut max_normal = Vector2D::new(0.0, 1.0);
+    let mut closest = (f32::INFINITY, max_normal);
+
+    for i in 0..vertices.len() {
+        let a = vertices[i];
+        let b = vertices[(i + 1) % vertices.len()];
+        let normal = (b - a).perpendicular().normalise() * winding;
+
+        let separation = (point - a).dot(normal);
+        if separation > max_separation {
+            max_separation = separation;
+            max_normal = normal;
+        }
+
+        let edge = segment_distance(point, a, b);
+        if edge.0 < closest.0 {
+            closest = edge;
+        }
+    }
+
+    if max_separation <= 0.0 {
+        (max_separation, max_normal)
+    } else {
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn circle_distance() {
+        let circle = Shape::Circle {
+            centre: Vector2D::new(1.0, 1.0),
+            radius: 0.5,
+        };
+
+        let (distance, normal) = circle.signed_distance(Vector2D::new(2.0, 1.0));
+        assert_close(distance, 0.5);
+        assert_eq!(normal, Vector2D::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn oriented_box_distance() {
+        let square = Shape::OrientedBox {
+            centre: Vector2D::new(0.0, 0.0),
+            half_extents: Vector2D::new(1.0, 1.0),
+            rotation: std::f32::consts::FRAC_PI_4,
+        };
+
+        // Corner of the rotated square lies on the x axis at sqrt(2)
+        let (distance, _) = square.signed_distance(Vector2D::new(2.0, 0.0));
+        assert_close(distance, 2.0 - 2f32.sqrt());
+
+        let (distance, normal) = square.signed_distance(Vector2D::new(0.0, 0.0));
+        assert_close(distance, -1.0);
+        assert_close(normal.magnitude(), 1.0);
+    }
+
+    #[test]
+    fn polygon_distance_either_winding() {
+        let triangle = vec![
+            Vector2D::new(0.0, 0.0),
+            Vector2D::new(2.0, 0.0),
+            Vector2D::new(0.0, 2.0),
+        ];
+        let reversed: Vec<Vector2D> = triangle.iter().rev().copied().collect();
+
+        for vertices in [triangle, reversed] {
+            let polygon = Shape::Polygon { vertices };
+
+            let (distance, normal) = polygon.signed_distance(Vector2D::new(0.5, -1.0));
+            assert_close(distance, 1.0);
+            assert_eq!(normal, Vector2D::new(0.0, -1.0));
+
+            let (distance, normal) = polygon.signed_distance(Vector2D::new(0.5, 0.25));
+            assert_close(distance, -0.25);
+            assert_close(normal.y, -1.0);
+        }
+    }
+
+    #[test]
+    fn contact_reflects_approaching_velocity() {
+        let obstacle = Obstacle {
+            id: 0,
+            shape: Shape::Segment {
+                start: Vector2D::new(-1.0, 0.0),
+                end: Vector2D::new(1.0, 0.0),
+                thickness: 0.0,
+            },
+            restitution: 0.5,
+            friction: 0.0,
+        };
+
+        let mut position = Vector2D::new(0.0, 0.05);
+        let mut velocity = Vector2D::new(1.0, -2.0);
+        obstacle.collide(&mut position, &mut velocity, 0.1);
+
+        assert_close(position.y, 0.1);
+        assert_eq!(velocity, Vector2D::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn obstacle_rejects_concave_polygon() {
+        let obstacle = Obstacle {
+            id: 0,
+            shape: Shape::Polygon {
+                vertices: vec![
+                    Vector2D::new(0.0, 0.0),
+                    Vector2D::new(2.0, 0.0),
+                    Vector2D::new(1.0, 0.5),
+                    Vector2D::new(2.0, 2.0),
+                    Vector2D::new(0.0, 2.0),
+                ],
+            },
+            restitution: 0.5,
+            friction: 0.1,
+        };
+
+        assert_eq!(
+            obstacle.validate(),
+            Err(ConfigError::InvalidObstacle("polygon must be convex"))
+        );
+    }
+}
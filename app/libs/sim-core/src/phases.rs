@@ -0,0 +1,337 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ConfigError, obstacles::Shape, vector_2d::Vector2D};
+
+/// Most phases a config can add to the base fluid, so a particle's phase fits
+/// in a `u8`.
+pub const MAX_PHASES: usize = u8::MAX as usize;
+
+/// What a phase is made of.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Material {
+    #[default]
+    Fluid,
+    /// Sand or snow, with a Drucker-Prager yield stress of
+    /// `cohesion + pressure * tan(friction_angle)`. Grains move together
+    /// below it and flow above it, so they pile up at about `friction_angle`
+    /// radians, and grip walls with the same friction.
+    Granular { friction_angle: f32, cohesion: f32 },
+}
+
+impl Material {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match *self {
+            Material::Fluid => Ok(()),
+            Material::Granular {
+                friction_angle,
+                cohesion,
+            } => {
+                if !(0.0..std::f32::consts::FRAC_PI_2).contains(&friction_angle) {
+                    return Err(ConfigError::InvalidPhase(
+                        "friction angle must be between 0 and pi / 2",
+                    ));
+                }
+                if !cohesion.is_finite() || cohesion < 0.0 {
+                    return Err(ConfigError::InvalidPhase(
+                        "cohesion must be finite and not negative",
+                    ));
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Fluid or granular material mixed into the base fluid, such as oil
+/// floating on water or sand poured into it.
+///
+/// Particle phase 0 is the base fluid described by the top-level
+/// [`crate::config::SimConfig`] parameters, and phase `i` is `phases[i - 1]`.
+/// Particle mass scales with the rest density, so denser phases sink.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Phase {
+    pub target_density: f32,
+    pub pressure_multiplier: f32,
+    pub viscosity_strength: f32,
+    /// Display colour as RGB in `0..=1`.
+    pub colour: [f32; 3],
+    #[serde(default)]
+    pub material: Material,
+    /// Particles that start inside this shape take this phase. Later phases
+    /// win where regions overlap.
+    #[serde(default)]
+    pub region: Option<Shape>,
+}
+
+impl Phase {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let values = [
+            self.target_density,
+            self.pressure_multiplier,
+            self.viscosity_strength,
+        ];
+        if values.iter().any(|value| !value.is_finite()) {
+            return Err(ConfigError::InvalidPhase("values must be finite"));
+        }
+        if self.target_density <= 0.0 {
+            return Err(ConfigError::InvalidPhase(
+                "target density must be greater than 0",
+            ));
+        }
+        if self.pressure_multiplier < 0.0 || self.viscosity_strength < 0.0 {
+            return Err(ConfigError::InvalidPhase(
+                "pressure multiplier and viscosity strength must not be negative",
+            ));
+        }
+        if self.colour.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            return Err(ConfigError::InvalidPhase(
+                "colour channels must be between 0 and 1",
+            ));
+        }
+
+        self.material.validate()?;
+
+        match &self.region {
+            Some(region) => region.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Phase a particle starting at `position` takes.
+pub(crate) fn initial_phase(phases: &[Phase], position: Vector2D) -> u8 {
+    phases
+        .iter()
+        .rposition(|phase| {
+            phase
+                .region
+                .as_ref()
+                .is_some_and(|region| region.signed_distance(position).0 < 0.0)
+        })
+        .map_or(0, |i| i as u8 + 1)
+}
+
+// Solver constants for one phase, indexed by particle phase
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PhaseParameters {
+    pub mass: f32,
+    pub target_density: f32,
+    pub pressure_multiplier: f32,
+    pub near_pressure_multiplier: f32,
+    pub viscosity_strength: f32,
+    pub material: Material,
+    // Coulomb friction coefficient, `tan(friction_angle)`, or 0 for fluids
+    pub friction: f32,
+}
+
+impl PhaseParameters {
+    // Mass is relative to the base fluid, whose particles weigh 1
+    pub fn new(
+        target_density: f32,
+        pressure_multiplier: f32,
+        viscosity_strength: f32,
+        material: Material,
+        base_target_density: f32,
+    ) -> Self {
+        Self {
+            mass: target_density / base_target_density,
+            target_density,
+            pressure_multiplier,
+            near_pressure_multiplier: pressure_multiplier / 100.0,
+            viscosity_strength,
+            material,
+            // Found once a step, through libm so deterministic runs agree across
+            // platforms
+            friction: match material {
+                Material::Fluid => 0.0,
+                Material::Granular { friction_angle, .. } => libm::tanf(friction_angle),
+            },
+        }
+    }
+
+    pub fn pressure(&self, density: f32) -> f32 {
+        (density - self.target_density) * self.pressure_multiplier
+    }
+
+    // Share of a grain's shear against its neighbours that friction can stop
+    // within a substep of `dt`, from 0 for free flow to 1 for grains that
+    // move together below the yield stress. None for fluids.
+    pub fn granular_grip(
+        &self,
+        (density, near_density): (f32, f32),
+        strain_rate: f32,
+        smoothing_radius: f32,
+        dt: f32,
+    ) -> Option<f32> {
+        let Material::Granular { cohesion, .. } = self.material else {
+            return None;
+        };
+
+        // Loose grains rest on each other through the near pressure alone
+        let pressure = self.pressure(density) + self.near_pressure_multiplier * near_density;
+        let yield_stress = cohesion + pressure.max(0.0) * self.friction;
+        // Against the stress a viscosity of `h^2 / dt` would need to stop
+        // the shear in one substep
+        let stopping_stress = density * strain_rate * smoothing_radius * smoothing_radius / dt;
+        Some((yield_stress / stopping_stress.max(f32::EPSILON)).min(1.0))
+    }
+
+    // Coulomb friction against whatever changed the velocity from
+    // `velocity_before`, limited by how hard the particle hit it
+    pub fn grip(&self, velocity_before: Vector2D, velocity: &mut Vector2D) {
+        if self.friction <= 0.0 {
+            return;
+        }
+
+        let impulse = *velocity - velocity_before;
+        let impulse_length = impulse.magnitude();
+        if impulse_length <= f32::EPSILON {
+            return;
+        }
+
+        let normal = impulse / impulse_length;
+        let tangential = *velocity - normal * velocity.dot(normal);
+        let tangential_speed = tangential.magnitude();
+        if tangential_speed <= f32::EPSILON {
+            return;
+        }
+
+        let slowdown = (self.friction * impulse_length).min(tangential_speed);
+        *velocity -= tangential / tangential_speed * slowdown;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Sim2D, config::SimConfig, layout::InitialLayout};
+
+    fn oil(region: Shape) -> Phase {
+        Phase {
+            target_density: 40.0,
+            pressure_multiplier: 500.0,
+            viscosity_strength: 0.1,
+            colour: [0.9, 0.7, 0.1],
+            material: Material::Fluid,
+            region: Some(region),
+        }
+    }
+
+    #[test]
+    fn later_regions_take_priority() {
+        let left = Shape::OrientedBox {
+            centre: Vector2D::new(-1.0, 0.0),
+            half_extents: Vector2D::new(1.0, 1.0),
+            rotation: 0.0,
+        };
+        let middle = Shape::Circle {
+            centre: Vector2D::new(0.0, 0.0),
+            radius: 0.5,
+        };
+        let phases = [oil(left), oil(middle)];
+
+        assert_eq!(initial_phase(&phases, Vector2D::new(-1.5, 0.0)), 1);
+        assert_eq!(initial_phase(&phases, Vector2D::new(-0.2, 0.0)), 2);
+        assert_eq!(initial_phase(&phases, Vector2D::new(1.5, 0.0)), 0);
+    }
+
+    #[test]
+    fn lighter_phase_rises() {
+        // Light fluid starts under the base fluid
+        let mut sim = Sim2D::new(
+            SimConfig::builder()
+                .particle_count(400)
+                .world_dimensions(2.0, 3.0)
+                .target_density(80.0)
+                .layout(InitialLayout::DamBreak {
+                    width: 1.0,
+                    height: 0.4,
+                })
+                .phase(oil(Shape::OrientedBox {
+                    centre: Vector2D::new(0.0, -1.2),
+                    half_extents: Vector2D::new(1.0, 0.3),
+                    rotation: 0.0,
+                }))
+                .build()
+                .unwrap(),
+        );
+
+        let mean_height = |sim: &Sim2D, phase: u8| {
+            let heights: Vec<f32> = sim
+                .positions
+                .iter()
+                .zip(&sim.particle_phases)
+                .filter(|(_, p)| **p == phase)
+                .map(|(position, _)| position.y)
+                .collect();
+            heights.iter().sum::<f32>() / heights.len() as f32
+        };
+        assert!(mean_height(&sim, 1) < mean_height(&sim, 0));
+
+        for _ in 0..600 {
+            sim.step(1.0 / 60.0);
+        }
+        assert!(mean_height(&sim, 1) > mean_height(&sim, 0));
+    }
+
+    #[test]
+    fn sand_piles_up_where_fluid_spreads() {
+        // Low block on the middle of the floor, all of it one phase
+        let slump = |material: Material| {
+            let mut data = vec![0; 64];
+            data[58..62].fill(255);
+            let mut sim = Sim2D::new(
+                SimConfig::builder()
+                    .particle_count(150)
+                    .world_dimensions(8.0, 4.0)
+                    .layout(InitialLayout::Mask {
+                        width: 8,
+                        height: 8,
+                        data,
+                    })
+                    .phase(Phase {
+                        material,
+                        ..oil(Shape::OrientedBox {
+                            centre: Vector2D::new(0.0, 0.0),
+                            half_extents: Vector2D::new(4.0, 2.0),
+                            rotation: 0.0,
+                        })
+                    })
+                    .build()
+                    .unwrap(),
+            );
+            for _ in 0..180 {
+                sim.step(1.0 / 60.0);
+            }
+            // Width holding most of the particles, ignoring splashes
+            let mut spread: Vec<f32> = sim.positions().iter().map(|p| p.x.abs()).collect();
+            spread.sort_by(f32::total_cmp);
+            spread[spread.len() * 9 / 10]
+        };
+
+        let sand = slump(Material::Granular {
+            friction_angle: 0.6,
+            cohesion: 0.0,
+        });
+        let fluid = slump(Material::Fluid);
+        assert!(sand < 0.7 * fluid, "sand spread {sand}, fluid {fluid}");
+    }
+
+    #[test]
+    fn friction_angle_must_be_below_a_right_angle() {
+        let sand = |friction_angle: f32| Material::Granular {
+            friction_angle,
+            cohesion: 0.0,
+        };
+        assert_eq!(sand(0.6).validate(), Ok(()));
+        assert!(sand(std::f32::consts::FRAC_PI_2).validate().is_err());
+        assert!(sand(f32::NAN).validate().is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ConfigError,
+    kernels::{Kernel, KernelKind, near_density_kernel_derivative},
+    kinematics::{BodyState, collide_moving},
+    obstacles::Shape,
+    vector_2d::Vector2D,
+};
+
+/// Dynamic body that floats, sinks and is pushed around by the fluid.
+///
+/// Particles have unit mass, so a body floats when its mass is below the
+/// target density times its area. Bodies collide with the fluid and the
+/// world box, not with other geometry.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RigidBody {
+    /// Assigned by [`crate::Sim2D::add_rigid_body`]; must be unique in a
+    /// config.
+    #[serde(default)]
+    pub id: u32,
+    /// Convex shape in body coordinates, about the centre of mass.
+    pub shape: Shape,
+    pub mass: f32,
+    /// Moment of inertia about the centre of mass. Defaults to that of a
+    /// uniformly dense `shape`.
+    #[serde(default)]
+    pub inertia: Option<f32>,
+    /// Initial pose and velocity.
+    pub position: Vector2D,
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "zero")]
+    pub velocity: Vector2D,
+    #[serde(default)]
+    pub angular_velocity: f32,
+    /// Fraction of normal velocity kept after a bounce.
+    pub restitution: f32,
+    /// Fraction of tangential velocity removed on contact.
+    pub friction: f32,
+}
+
+fn zero() -> Vector2D {
+    Vector2D::new(0.0, 0.0)
+}
+
+impl RigidBody {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(0.0..=1.0).contains(&self.restitution) {
+            return Err(ConfigError::OutOfRange("restitution"));
+        }
+        if !(0.0..=1.0).contains(&self.friction) {
+            return Err(ConfigError::OutOfRange("friction"));
+        }
+        if !self.mass.is_finite() || self.mass <= 0.0 {
+            return Err(ConfigError::InvalidRigidBody("mass must be greater than 0"));
+        }
+        if let Some(inertia) = self.inertia
+            && (!inertia.is_finite() || inertia <= 0.0)
+        {
+            return Err(ConfigError::InvalidRigidBody(
+                "inertia must be greater than 0",
+            ));
+        }
+
+        let state = [
+            self.position.x,
+            self.position.y,
+            self.rotation,
+            self.velocity.x,
+            self.velocity.y,
+            self.angular_velocity,
+        ];
+        if state.iter().any(|value| !value.is_finite()) {
+            return Err(ConfigError::InvalidRigidBody(
+                "initial state must be finite",
+            ));
+        }
+
+        self.shape.validate()
+    }
+
+    pub fn inertia(&self) -> f32 {
+        self.inertia
+            .unwrap_or_else(|| self.mass * unit_inertia(&self.shape))
+    }
+
+    pub fn initial_state(&self) -> BodyState {
+        BodyState {
+            position: self.position,
+            rotation: self.rotation,
+            velocity: self.velocity,
+            angular_velocity: self.angular_velocity,
+        }
+    }
+
+    /// Pressure acceleration the body applies to a particle at `position`,
+    /// and the surface point it acts through. The body is treated as a mirror
+    /// image of the particle, so walls push with the particle's own pressure.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn pressure_acceleration(
+        &self,
+        state: &BodyState,
+        position: Vector2D,
+        density: (f32, f32),
+        pressure: f32,
+        near_pressure: f32,
+        smoothing_radius: f32,
+        kernel: KernelKind,
+    ) -> Option<(Vector2D, Vector2D)> {
+        let local = (position - state.position).rotate(-state.rotation);
+        let (distance, normal) = self.shape.signed_distance(local);
+
+        let ghost_distance = 2.0 * distance.max(0.0);
+        if ghost_distance >= smoothing_radius {
+            return None;
+        }
+
+        // Walls only push, so negative pressure doesn't glue particles on
+        let force = kernel.derivative(ghost_distance, smoothing_radius) * pressure.max(0.0)
+            / density.0
+            + near_density_kernel_derivative(ghost_distance, smoothing_radius) * near_pressure
+                / density.1;
+
+        let normal = normal.rotate(state.rotation);
+        let acceleration = normal * -force / density.0;
+
+        Some((acceleration, position - normal * distance))
+    }
+
+    pub(crate) fn collide(
+        &self,
+        state: &BodyState,
+        position: &mut Vector2D,
+        velocity: &mut Vector2D,
+        particle_radius: f32,
+    ) {
+        collide_moving(
+            &self.shape,
+            state,
+            position,
+            velocity,
+            particle_radius,
+            self.restitution,
+            self.friction,
+        );
+    }
+
+    /// Keeps the body inside the world box, with an impulse through the
+    /// middle of the points past each wall so flat faces land flat.
+    pub(crate) fn collide_walls(&self, state: &mut BodyState, world_dimensions: Vector2D) {
+        let half = world_dimensions * 0.5;
+        let walls = [
+            (Vector2D::new(1.0, 0.0), half.x),
+            (Vector2D::new(-1.0, 0.0), half.x),
+            (Vector2D::new(0.0, 1.0), half.y),
+            (Vector2D::new(0.0, -1.0), half.y),
+        ];
+
+        for (normal, offset) in walls {
+            // Wall at `point . normal = -offset`, with `normal` pointing inwards
+            let penetrating: Vec<(Vector2D, f32)> = extreme_points(&self.shape)
+                .into_iter()
+                .map(|(point, radius)| {
+                    let point = point.rotate(state.rotation) + state.position;
+                    (point - normal * radius, offset + point.dot(normal) - radius)
+                })
+                .filter(|(_, separation)| *separation < 0.0)
+                .collect();
+
+            if penetrating.is_empty() {
+                continue;
+            }
+
+            let depth = penetrating
+                .iter()
+                .map(|(_, separation)| -separation)
+                .fold(0.0, f32::max);
+            let contact = penetrating
+                .iter()
+                .fold(Vector2D::new(0.0, 0.0), |sum, (point, _)| sum + *point)
+                / penetrating.len() as f32;
+
+            state.position += normal * depth;
+            self.apply_contact_impulse(state, contact + normal * depth, normal);
+        }
+    }
+
+    fn apply_contact_impulse(&self, state: &mut BodyState, contact: Vector2D, normal: Vector2D) {
+        let inverse_mass = 1.0 / self.mass;
+        let inverse_inertia = 1.0 / self.inertia();
+        let arm = contact - state.position;
+
+        let normal_speed = state.point_velocity(contact).dot(normal);
+        if normal_speed >= 0.0 {
+            return;
+        }
+
+        let arm_normal = arm.cross(normal);
+        let normal_impulse = -(1.0 + self.restitution) * normal_speed
+            / (inverse_mass + arm_normal * arm_normal * inverse_inertia);
+        self.apply_impulse(state, arm, normal * normal_impulse);
+
+        // Coulomb friction, limited by the normal impulse
+        let tangent = normal.perpendicular();
+        let arm_tangent = arm.cross(tangent);
+        let tangent_speed = state.point_velocity(contact).dot(tangent);
+        let tangent_impulse =
+            (-tangent_speed / (inverse_mass + arm_tangent * arm_tangent * inverse_inertia)).clamp(
+                -self.friction * normal_impulse,
+                self.friction * normal_impulse,
+            );
+        self.apply_impulse(state, arm, tangent * tangent_impulse);
+    }
+
+    /// Applies `impulse` at offset `arm` from the centre of mass.
+    pub(crate) fn apply_impulse(&self, state: &mut BodyState, arm: Vector2D, impulse: Vector2D) {
+        state.velocity += impulse / self.mass;
+        state.angular_velocity += arm.cross(impulse) / self.inertia();
+    }
+}
+
+// Points that bound the shape, each with the radius swept around it
+fn extreme_points(shape: &Shape) -> Vec<(Vector2D, f32)> {
+    match shape {
+        Shape::Circle { centre, radius } => vec![(*centre, *radius)],
+        Shape::OrientedBox {
+            centre,
+            half_extents,
+            rotation,
+        } => [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)]
+            .into_iter()
+            .map(|(x, y)| {
+                let corner = Vector2D::new(half_extents.x * x, half_extents.y * y);
+                (corner.rotate(*rotation) + *centre, 0.0)
+            })
+            .collect(),
+        Shape::Segment {
+            start,
+            end,
+            thickness,
+        } => vec![(*start, *thickness), (*end, *thickness)],
+        Shape::Polygon { vertices } => vertices.iter().map(|v| (*v, 0.0)).collect(),
+    }
+}
+
+// Moment of inertia per unit mass about the origin, for a uniform density
+fn unit_inertia(shape: &Shape) -> f32 {
+    match shape {
+        Shape::Circle { centre, radius } => radius * radius / 2.0 + centre.dot(*centre),
+        Shape::OrientedBox {
+            centre,
+            half_extents,
+            ..
+        } => half_extents.dot(*half_extents) / 3.0 + centre.dot(*centre),
+        // Approximated by the rectangle the disc sweeps
+        Shape::Segment {
+            start,
+            end,
+            thickness,
+        } => {
+            let length = (*end - *start).magnitude();
+            let middle = (*start + *end) * 0.5;
+            (length * length + 4.0 * thickness * thickness) / 12.0 + middle.dot(middle)
+        }
+        Shape::Polygon { vertices } => {
+            let (mut numerator, mut denominator) = (0.0, 0.0);
+            for i in 0..vertices.len() {
+                let a = vertices[i];
+                let b = vertices[(i + 1) % vertices.len()];
+                let cross = a.cross(b);
+                numerator += cross * (a.dot(a) + a.dot(b) + b.dot(b));
+                denominator += cross;
+            }
+            numerator / (6.0 * denominator)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{a} != {b}");
+    }
+
+    fn square(mass: f32) -> RigidBody {
+        RigidBody {
+            id: 0,
+            shape: Shape::Polygon {
+                vertices: vec![
+                    Vector2D::new(-0.5, -0.5),
+                    Vector2D::new(0.5, -0.5),
+                    Vector2D::new(0.5, 0.5),
+                    Vector2D::new(-0.5, 0.5),
+                ],
+            },
+            mass,
+            inertia: None,
+            position: Vector2D::new(0.0, 0.0),
+            rotation: 0.0,
+            velocity: Vector2D::new(0.0, 0.0),
+            angular_velocity: 0.0,
+            restitution: 0.0,
+            friction: 0.0,
+        }
+    }
+
+    #[test]
+    fn uniform_inertia_matches_box() {
+        let polygon = square(6.0);
+        let oriented_box = RigidBody {
+            shape: Shape::OrientedBox {
+                centre: Vector2D::new(0.0, 0.0),
+                half_extents: Vector2D::new(0.5, 0.5),
+                rotation: 0.3,
+            },
+            ..square(6.0)
+        };
+
+        // m (w^2 + h^2) / 12
+        assert_close(polygon.inertia(), 1.0);
+        assert_close(oriented_box.inertia(), 1.0);
+    }
+
+    #[test]
+    fn wall_contact_stops_falling_body() {
+        let body = square(1.0);
+        let mut state = BodyState {
+            position: Vector2D::new(0.0, -1.55),
+            velocity: Vector2D::new(0.0, -2.0),
+            ..body.initial_state()
+        };
+        body.collide_walls(&mut state, Vector2D::new(4.0, 4.0));
+
+        assert_close(state.position.y, -1.5);
+        assert_close(state.velocity.y, 0.0);
+        assert_close(state.angular_velocity, 0.0);
+    }
+
+    #[test]
+    fn pressure_pushes_particle_away_from_body() {
+        let body = square(1.0);
+        let state = body.initial_state();
+
+        let (acceleration, contact) = body
+            .pressure_acceleration(
+                &state,
+                Vector2D::new(0.6, 0.0),
+                (80.0, 10.0),
+                100.0,
+                1.0,
+                0.35,
+                KernelKind::Spiky,
+            )
+            .unwrap();
+
+        assert!(acceleration.x > 0.0);
+        assert_close(acceleration.y, 0.0);
+        assert_close(contact.x, 0.5);
+
+        assert!(
+            body.pressure_acceleration(
+                &state,
+                Vector2D::new(1.0, 0.0),
+                (80.0, 10.0),
+                100.0,
+                1.0,
+                0.35,
+                KernelKind::Spiky,
+            )
+            .is_none()
+        );
+    }
+}
@@ -0,0 +1,443 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ConfigError,
+    obstacles::{resolve_contact, segment_distance},
+    vector_2d::Vector2D,
+};
+
+/// Largest number of samples a boundary may use, about 2048 x 2048.
+pub const MAX_SDF_SAMPLES: usize = 1 << 22;
+
+// Stand-in for "no feature" in the distance transform
+const FAR: f64 = 1e20;
+
+/// Where the solid geometry of a [`Boundary`] comes from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum BoundarySource {
+    /// Walls traced along each polyline, swept by a disc of radius
+    /// `thickness`.
+    Polylines {
+        lines: Vec<Vec<Vector2D>>,
+        thickness: f32,
+    },
+    /// Greyscale mask stretched over the world box, rows from top to bottom.
+    /// Values of 128 and above are solid.
+    Image {
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+    },
+}
+
+/// Container of arbitrary shape, such as a funnel or a maze. It is sampled
+/// into a [`SignedDistanceField`] covering the world box, and particles are
+/// kept on the open side of it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Boundary {
+    pub source: BoundarySource,
+    /// Spacing between distance samples in world units.
+    pub cell_size: f32,
+    /// Fraction of normal velocity kept after a bounce.
+    pub restitution: f32,
+    /// Fraction of tangential velocity removed on contact.
+    pub friction: f32,
+}
+
+impl Boundary {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.cell_size.is_finite() || self.cell_size <= 0.0 {
+            return Err(ConfigError::InvalidBoundary(
+                "cell size must be greater than 0",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.restitution) {
+            return Err(ConfigError::OutOfRange("restitution"));
+        }
+        if !(0.0..=1.0).contains(&self.friction) {
+            return Err(ConfigError::OutOfRange("friction"));
+        }
+
+        match &self.source {
+            BoundarySource::Polylines { lines, thickness } => {
+                if !thickness.is_finite() || *thickness <= 0.0 {
+                    Err(ConfigError::InvalidBoundary(
+                        "wall thickness must be greater than 0",
+                    ))
+                } else if lines.is_empty() || lines.iter().any(|line| line.len() < 2) {
+                    Err(ConfigError::InvalidBoundary(
+                        "each polyline needs at least 2 points",
+                    ))
+                } else if lines
+                    .iter()
+                    .flatten()
+                    .any(|p| !p.x.is_finite() || !p.y.is_finite())
+                {
+                    Err(ConfigError::InvalidBoundary(
+                        "polyline points must be finite",
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            BoundarySource::Image {
+                width,
+                height,
+                data,
+            } => {
+                if *width == 0 || *height == 0 || width * height != data.len() {
+                    Err(ConfigError::InvalidBoundary(
+                        "image data must contain width * height values",
+                    ))
+                } else if data.iter().all(|value| *value >= 128) {
+                    Err(ConfigError::InvalidBoundary("image has no open space"))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// Number of samples the field needs to cover a world of this size.
+    pub fn sample_count(&self, world_dimensions: Vector2D) -> usize {
+        let (columns, rows) = grid_size(world_dimensions, self.cell_size);
+        columns.saturating_mul(rows)
+    }
+
+    /// [`Boundary::validate`], and that the field over a world of this size
+    /// stays within [`MAX_SDF_SAMPLES`].
+    pub fn validate_in(&self, world_dimensions: Vector2D) -> Result<(), ConfigError> {
+        self.validate()?;
+
+        if self.sample_count(world_dimensions) > MAX_SDF_SAMPLES {
+            return Err(ConfigError::InvalidBoundary(
+                "cell size is too small for the world",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Signed distance to solid geometry, sampled on a regular grid over the
+/// world box. Distances are positive in open space and negative inside
+/// walls, and are bilinearly interpolated between samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedDistanceField {
+    // Position of sample (0, 0), the bottom-left corner of the world
+    origin: Vector2D,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    // Row-major, bottom row first
+    distances: Vec<f32>,
+}
+
+impl SignedDistanceField {
+    pub fn from_boundary(boundary: &Boundary, world_dimensions: Vector2D) -> Self {
+        match &boundary.source {
+            BoundarySource::Polylines { lines, thickness } => {
+                Self::from_polylines(lines, *thickness, world_dimensions, boundary.cell_size)
+            }
+            BoundarySource::Image {
+                width,
+                height,
+                data,
+            } => Self::from_image(*width, *height, data, world_dimensions, boundary.cell_size),
+        }
+    }
+
+    /// Exact field for walls of radius `thickness` around each polyline.
+    pub fn from_polylines(
+        lines: &[Vec<Vector2D>],
+        thickness: f32,
+        world_dimensions: Vector2D,
+        cell_size: f32,
+    ) -> Self {
+        let mut field = Self::empty(world_dimensions, cell_size);
+
+        for row in 0..field.rows {
+            for column in 0..field.columns {
+                let point = field.sample_position(column, row);
+                let distance = lines
+                    .iter()
+                    .flat_map(|line| line.windows(2))
+                    .map(|segment| segment_distance(point, segment[0], segment[1]).0)
+                    .fold(f32::INFINITY, f32::min);
+
+                field.distances[row * field.columns + column] = distance - thickness;
+            }
+        }
+
+        field
+    }
+
+    /// Field for a greyscale mask stretched over the world box, computed with
+    /// an exact Euclidean distance transform of the rasterised mask.
+    pub fn from_image(
+        width: usize,
+        height: usize,
+        data: &[u8],
+        world_dimensions: Vector2D,
+        cell_size: f32,
+    ) -> Self {
+        let mut field = Self::empty(world_dimensions, cell_size);
+
+        let solid: Vec<bool> = (0..field.rows)
+            .flat_map(|row| (0..field.columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let point = field.sample_position(column, row);
+                let u = (point.x - field.origin.x) / world_dimensions.x;
+                let v = (world_dimensions.y * 0.5 - point.y) / world_dimensions.y;
+                let x = ((u * width as f32) as usize).min(width - 1);
+                let y = ((v * height as f32) as usize).min(height - 1);
+                data[y * width + x] >= 128
+            })
+            .collect();
+
+        let mut to_solid: Vec<f64> = solid.iter().map(|s| if *s { 0.0 } else { FAR }).collect();
+        let mut to_open: Vec<f64> = solid.iter().map(|s| if *s { FAR } else { 0.0 }).collect();
+        distance_transform(&mut to_solid, field.columns, field.rows);
+        distance_transform(&mut to_open, field.columns, field.rows);
+
+        // Samples sit on either side of the surface, so it lies half a cell
+        // from the nearest sample of the other kind
+        for (i, distance) in field.distances.iter_mut().enumerate() {
+            *distance = if solid[i] {
+                0.5 - to_open[i].sqrt() as f32
+            } else {
+                to_solid[i].sqrt() as f32 - 0.5
+            } * cell_size;
+        }
+
+        field
+    }
+
+    fn empty(world_dimensions: Vector2D, cell_size: f32) -> Self {
+        let (columns, rows) = grid_size(world_dimensions, cell_size);
+
+        Self {
+            origin: world_dimensions * -0.5,
+            cell_size,
+            columns,
+            rows,
+            distances: vec![0.0; columns * rows],
+        }
+    }
+
+    fn sample_position(&self, column: usize, row: usize) -> Vector2D {
+        self.origin + Vector2D::new(column as f32, row as f32) * self.cell_size
+    }
+
+    /// Bilinearly interpolated distance at `point`. Points outside the world
+    /// use the nearest edge of the field.
+    pub fn sample(&self, point: Vector2D) -> f32 {
+        let grid = (point - self.origin) / self.cell_size;
+        let x = grid.x.clamp(0.0, (self.columns - 1) as f32);
+        let y = grid.y.clamp(0.0, (self.rows - 1) as f32);
+
+        let column = (x as usize).min(self.columns - 2);
+        let row = (y as usize).min(self.rows - 2);
+        let tx = x - column as f32;
+        let ty = y - row as f32;
+
+        let index = row * self.columns + column;
+        let bottom = lerp(self.distances[index], self.distances[index + 1], tx);
+        let top = lerp(
+            self.distances[index + self.columns],
+            self.distances[index + self.columns + 1],
+            tx,
+        );
+
+        lerp(bottom, top, ty)
+    }
+
+    /// Central-difference gradient of [`SignedDistanceField::sample`], which
+    /// points away from the nearest wall.
+    pub fn gradient(&self, point: Vector2D) -> Vector2D {
+        let h = self.cell_size;
+        let dx = Vector2D::new(h, 0.0);
+        let dy = Vector2D::new(0.0, h);
+
+        Vector2D::new(
+            self.sample(point + dx) - self.sample(point - dx),
+            self.sample(point + dy) - self.sample(point - dy),
+        ) / (2.0 * h)
+    }
+
+    pub(crate) fn collide(
+        &self,
+        position: &mut Vector2D,
+        velocity: &mut Vector2D,
+        particle_radius: f32,
+        restitution: f32,
+        friction: f32,
+    ) {
+        let distance = self.sample(*position);
+        if distance >= particle_radius {
+            return;
+        }
+
+        let gradient = self.gradient(*position);
+        let length = gradient.magnitude();
+        if length > f32::EPSILON {
+            resolve_contact(
+                position,
+                velocity,
+                particle_radius - distance,
+                gradient / length,
+                restitution,
+                friction,
+            );
+        }
+    }
+}
+
+fn grid_size(world_dimensions: Vector2D, cell_size: f32) -> (usize, usize) {
+    let columns = (world_dimensions.x / cell_size).ceil() as usize + 1;
+    let rows = (world_dimensions.y / cell_size).ceil() as usize + 1;
+    (columns.max(2), rows.max(2))
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Squared Euclidean distance transform in place (Felzenszwalb & Huttenlocher),
+// with 0 at feature samples and FAR everywhere else
+fn distance_transform(grid: &mut [f64], columns: usize, rows: usize) {
+    let longest = columns.max(rows);
+    let mut line = vec![0.0; longest];
+    let mut parabolas = vec![0; longest];
+    let mut boundaries = vec![0.0; longest + 1];
+
+    for column in 0..columns {
+        for row in 0..rows {
+            line[row] = grid[row * columns + column];
+        }
+        distance_transform_1d(&mut line[..rows], &mut parabolas, &mut boundaries);
+        for row in 0..rows {
+            grid[row * columns + column] = line[row];
+        }
+    }
+
+    for row in grid.chunks_exact_mut(columns) {
+        distance_transform_1d(row, &mut parabolas, &mut boundaries);
+    }
+}
+
+fn distance_transform_1d(f: &mut [f64], parabolas: &mut [usize], boundaries: &mut [f64]) {
+    let intersect = |f: &[f64], q: usize, p: usize| {
+        ((f[q] + (q * q) as f64) - (f[p] + (p * p) as f64)) / (2 * (q - p)) as f64
+    };
+
+    // Lower envelope of the parabolas rooted at each sample
+    let mut k = 0;
+    parabolas[0] = 0;
+    boundaries[0] = f64::NEG_INFINITY;
+    boundaries[1] = f64::INFINITY;
+    for q in 1..f.len() {
+        let mut s = intersect(f, q, parabolas[k]);
+        while s <= boundaries[k] {
+            k -= 1;
+            s = intersect(f, q, parabolas[k]);
+        }
+        k += 1;
+        parabolas[k] = q;
+        boundaries[k] = s;
+        boundaries[k + 1] = f64::INFINITY;
+    }
+
+    let source = f.to_vec();
+    k = 0;
+    for (q, value) in f.iter_mut().enumerate() {
+        while boundaries[k + 1] < q as f64 {
+            k += 1;
+        }
+        let offset = q as f64 - parabolas[k] as f64;
+        *value = offset * offset + source[parabolas[k]];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32, tolerance: f32) {
+        assert!((a - b).abs() < tolerance, "{a} != {b}");
+    }
+
+    #[test]
+    fn polyline_field_matches_wall_distance() {
+        // U-shaped container open at the top
+        let lines = vec![vec![
+            Vector2D::new(-1.5, 1.5),
+            Vector2D::new(-1.5, -1.5),
+            Vector2D::new(1.5, -1.5),
+            Vector2D::new(1.5, 1.5),
+        ]];
+        let field = SignedDistanceField::from_polylines(&lines, 0.1, Vector2D::new(4.0, 4.0), 0.05);
+
+        assert_close(field.sample(Vector2D::new(0.0, 0.0)), 1.4, 1e-4);
+        assert_close(field.sample(Vector2D::new(0.0, -1.5)), -0.1, 1e-4);
+        assert_close(field.sample(Vector2D::new(1.0, 0.12)), 0.4, 1e-4);
+
+        let gradient = field.gradient(Vector2D::new(0.0, -1.0));
+        assert_close(gradient.x, 0.0, 1e-3);
+        assert_close(gradient.y, 1.0, 1e-3);
+    }
+
+    #[test]
+    fn image_field_is_signed_distance() {
+        // Solid bottom half
+        let data: Vec<u8> = (0..16 * 16)
+            .map(|i| if i >= 8 * 16 { 255 } else { 0 })
+            .collect();
+        let field = SignedDistanceField::from_image(16, 16, &data, Vector2D::new(4.0, 4.0), 0.1);
+
+        assert_close(field.sample(Vector2D::new(0.0, 1.0)), 1.0, 0.06);
+        assert_close(field.sample(Vector2D::new(0.0, -1.0)), -1.0, 0.06);
+        assert_close(field.gradient(Vector2D::new(0.5, 0.5)).y, 1.0, 1e-3);
+    }
+
+    #[test]
+    fn boundary_sample_count_is_bounded_by_the_world() {
+        let boundary = Boundary {
+            source: BoundarySource::Polylines {
+                lines: vec![vec![Vector2D::new(-2.0, 0.0), Vector2D::new(2.0, 0.0)]],
+                thickness: 0.1,
+            },
+            cell_size: 0.001,
+            restitution: 0.5,
+            friction: 0.0,
+        };
+
+        assert_eq!(boundary.validate(), Ok(()));
+        assert_eq!(boundary.validate_in(Vector2D::new(1.0, 1.0)), Ok(()));
+        assert_eq!(
+            boundary.validate_in(Vector2D::new(16.0, 9.0)),
+            Err(ConfigError::InvalidBoundary(
+                "cell size is too small for the world"
+            ))
+        );
+    }
+
+    #[test]
+    fn collision_pushes_particle_out_of_wall() {
+        let lines = vec![vec![Vector2D::new(-2.0, 0.0), Vector2D::new(2.0, 0.0)]];
+        let field = SignedDistanceField::from_polylines(&lines, 0.1, Vector2D::new(4.0, 4.0), 0.05);
+
+        let mut position = Vector2D::new(0.0, 0.12);
+        let mut velocity = Vector2D::new(1.0, -2.0);
+        field.collide(&mut position, &mut velocity, 0.05, 0.5, 0.0);
+
+        assert_close(position.y, 0.15, 1e-4);
+        assert_close(velocity.x, 1.0, 1e-4);
+        assert_close(velocity.y, 1.0, 1e-4);
+    }
+}
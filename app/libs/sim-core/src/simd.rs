@@ -0,0 +1,335 @@
+// Neighbour sums for the density and pressure passes. With the `simd`
+// feature they run four neighbours per instruction, using SSE or NEON
+// natively and simd128 on wasm32, otherwise one at a time. Only the
+// neighbour lists are columnar: the per-particle buffers stay as arrays of
+// vectors, so each lane's mass and pressure are gathered one at a time.
+
+use crate::{
+    kernels::{Kernel, KernelKind},
+    neighbours::Neighbours,
+    vector_2d::Vector2D,
+};
+
+#[cfg(feature = "simd")]
+pub(crate) use lanes::{density_sums, pressure_force};
+#[cfg(not(feature = "simd"))]
+pub(crate) use scalar::{density_sums, pressure_force};
+
+// What a pressure sum needs from each neighbour
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PressureSample {
+    pub pressure: f32,
+    pub near_pressure: f32,
+    pub mass: f32,
+    pub density: f32,
+    pub near_density: f32,
+}
+
+#[cfg_attr(feature = "simd", allow(dead_code))]
+mod scalar {
+    use super::*;
+    use crate::kernels::{near_density_kernel, near_density_kernel_derivative};
+
+    // Density and near density, with each neighbour weighted by its mass
+    pub fn density_sums(
+        neighbours: &Neighbours,
+        radius: f32,
+        kernel: KernelKind,
+        mass_of: impl Fn(usize) -> f32,
+    ) -> (f32, f32) {
+        let mut density = 0.0;
+        let mut near_density = 0.0;
+        for neighbour in neighbours.iter() {
+            let mass = mass_of(neighbour.index);
+            density += mass * kernel.value(neighbour.distance, radius);
+            near_density += mass * near_density_kernel(neighbour.distance, radius);
+        }
+        (density, near_density)
+    }
+
+    // Pressure and near pressure force on `sample_index`, which is skipped
+    // in its own list
+    pub fn pressure_force(
+        neighbours: &Neighbours,
+        sample_index: usize,
+        radius: f32,
+        kernel: KernelKind,
+        pressure: f32,
+        near_pressure: f32,
+        sample_of: impl Fn(usize) -> PressureSample,
+    ) -> Vector2D {
+        let mut force = Vector2D::new(0.0, 0.0);
+        for neighbour in neighbours.iter() {
+            if neighbour.index == sample_index {
+                continue;
+            }
+
+            let direction = if neighbour.distance > 0.0 {
+                neighbour.offset / neighbour.distance
+            } else {
+                Vector2D::new(1.0, 1.0).normalise()
+            };
+            let sample = sample_of(neighbour.index);
+            let shared_pressure = (pressure + sample.pressure) * 0.5;
+            let shared_near_pressure = (near_pressure + sample.near_pressure) * 0.5;
+
+            force += direction
+                * kernel.derivative(neighbour.distance, radius)
+                * shared_pressure
+                * sample.mass
+                / sample.density;
+            force += direction
+                * near_density_kernel_derivative(neighbour.distance, radius)
+                * shared_near_pressure
+                * sample.mass
+                / sample.near_density;
+        }
+        force
+    }
+}
+
+#[cfg(feature = "simd")]
+mod lanes {
+    use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+    use wide::{CmpGt, CmpLe, f32x4};
+
+    use super::*;
+    use crate::kernels::{
+        density_kernel_volume, near_density_kernel_volume, viscosity_kernel_volume,
+    };
+
+    const LANES: usize = 4;
+
+    // `Kernel::value` four distances at a time, each within the radius
+    fn kernel_value(kernel: KernelKind, distances: f32x4, radius: f32) -> f32x4 {
+        let q = distances / f32x4::splat(radius);
+        // 1 - q, or 0 past the radius
+        let t = (f32x4::ONE - q).max(f32x4::ZERO);
+        match kernel {
+            KernelKind::Spiky => {
+                let q = (f32x4::splat(radius) - distances).max(f32x4::ZERO);
+                q * q / f32x4::splat(density_kernel_volume(radius))
+            }
+            KernelKind::Poly6 => {
+                let d = (f32x4::splat(radius * radius) - distances * distances).max(f32x4::ZERO);
+                d * d * d / f32x4::splat(viscosity_kernel_volume(radius))
+            }
+            KernelKind::CubicSpline => {
+                let scale = f32x4::splat(40.0 / (7.0 * PI * radius * radius));
+                let inner = f32x4::splat(6.0) * (q * q * q - q * q) + f32x4::ONE;
+                let outer = f32x4::splat(2.0) * t * t * t;
+                q.cmp_le(f32x4::splat(0.5)).blend(inner, outer) * scale
+            }
+            KernelKind::WendlandC2 => {
+                let scale = f32x4::splat(7.0 / (PI * radius * radius));
+                let t2 = t * t;
+                scale * t2 * t2 * (f32x4::ONE + f32x4::splat(4.0) * q)
+            }
+            KernelKind::WendlandC4 => {
+                let scale = f32x4::splat(9.0 / (PI * radius * radius));
+                let t3 = t * t * t;
+                let polynomial =
+                    f32x4::ONE + f32x4::splat(6.0) * q + f32x4::splat(35.0 / 3.0) * q * q;
+                scale * t3 * t3 * polynomial
+            }
+        }
+    }
+
+    // `Kernel::derivative` four distances at a time, each within the radius
+    fn kernel_derivative(kernel: KernelKind, distances: f32x4, radius: f32) -> f32x4 {
+        let q = distances / f32x4::splat(radius);
+        let t = (f32x4::ONE - q).max(f32x4::ZERO);
+        match kernel {
+            KernelKind::Spiky => {
+                let q = (f32x4::splat(radius) - distances).max(f32x4::ZERO);
+                q * f32x4::splat(-2.0 / density_kernel_volume(radius))
+            }
+            KernelKind::Poly6 => {
+                let d = (f32x4::splat(radius * radius) - distances * distances).max(f32x4::ZERO);
+                f32x4::splat(-6.0 / viscosity_kernel_volume(radius)) * distances * d * d
+            }
+            KernelKind::CubicSpline => {
+                let scale = f32x4::splat(40.0 / (7.0 * PI * radius.powi(3)));
+                let inner = f32x4::splat(6.0) * (f32x4::splat(3.0) * q * q - f32x4::splat(2.0) * q);
+                let outer = f32x4::splat(-6.0) * t * t;
+                q.cmp_le(f32x4::splat(0.5)).blend(inner, outer) * scale
+            }
+            KernelKind::WendlandC2 => {
+                let scale = f32x4::splat(7.0 / (PI * radius.powi(3)));
+                scale * f32x4::splat(-20.0) * q * t * t * t
+            }
+            KernelKind::WendlandC4 => {
+                let scale = f32x4::splat(9.0 / (PI * radius.powi(3)));
+                let t2 = t * t;
+                scale
+                    * f32x4::splat(-56.0 / 3.0)
+                    * q
+                    * (f32x4::ONE + f32x4::splat(5.0) * q)
+                    * t2
+                    * t2
+                    * t
+            }
+        }
+    }
+
+    // Short tails are padded with neighbours at the radius, where every
+    // kernel is 0
+    pub fn density_sums(
+        neighbours: &Neighbours,
+        radius: f32,
+        kernel: KernelKind,
+        mass_of: impl Fn(usize) -> f32,
+    ) -> (f32, f32) {
+        let radius_lanes = f32x4::splat(radius);
+        let mut density = f32x4::ZERO;
+        let mut near_density = f32x4::ZERO;
+
+        for start in (0..neighbours.len()).step_by(LANES) {
+            let mut distances = [radius; LANES];
+            let mut masses = [0.0; LANES];
+            for lane in 0..LANES.min(neighbours.len() - start) {
+                distances[lane] = neighbours.distances[start + lane];
+                masses[lane] = mass_of(neighbours.indices[start + lane]);
+            }
+
+            let distances = f32x4::from(distances);
+            let masses = f32x4::from(masses);
+            let q = (radius_lanes - distances).max(f32x4::ZERO);
+            density += masses * kernel_value(kernel, distances, radius);
+            near_density += masses * q * q * q;
+        }
+
+        (
+            density.reduce_add(),
+            near_density.reduce_add() / near_density_kernel_volume(radius),
+        )
+    }
+
+    pub fn pressure_force(
+        neighbours: &Neighbours,
+        sample_index: usize,
+        radius: f32,
+        kernel: KernelKind,
+        pressure: f32,
+        near_pressure: f32,
+        sample_of: impl Fn(usize) -> PressureSample,
+    ) -> Vector2D {
+        let radius_lanes = f32x4::splat(radius);
+        let near_density_scale = f32x4::splat(-3.0 / near_density_kernel_volume(radius));
+        let mut force_x = f32x4::ZERO;
+        let mut force_y = f32x4::ZERO;
+
+        for start in (0..neighbours.len()).step_by(LANES) {
+            // The sample itself and padding keep a weight of 0
+            let mut offsets_x = [0.0; LANES];
+            let mut offsets_y = [0.0; LANES];
+            let mut distances = [radius; LANES];
+            let mut pressure_weights = [0.0; LANES];
+            let mut near_pressure_weights = [0.0; LANES];
+            for lane in 0..LANES.min(neighbours.len() - start) {
+                let k = start + lane;
+                let index = neighbours.indices[k];
+                if index == sample_index {
+                    continue;
+                }
+
+                let sample = sample_of(index);
+                offsets_x[lane] = neighbours.offsets_x[k];
+                offsets_y[lane] = neighbours.offsets_y[k];
+                distances[lane] = neighbours.distances[k];
+                pressure_weights[lane] =
+                    (pressure + sample.pressure) * 0.5 * sample.mass / sample.density;
+                near_pressure_weights[lane] =
+                    (near_pressure + sample.near_pressure) * 0.5 * sample.mass
+                        / sample.near_density;
+            }
+
+            let distances = f32x4::from(distances);
+            let q = (radius_lanes - distances).max(f32x4::ZERO);
+            let scale = kernel_derivative(kernel, distances, radius)
+                * f32x4::from(pressure_weights)
+                + q * q * near_density_scale * f32x4::from(near_pressure_weights);
+
+            // Stacked particles push apart diagonally, as in the scalar pass
+            let has_length = distances.cmp_gt(f32x4::ZERO);
+            let fallback = f32x4::splat(FRAC_1_SQRT_2);
+            force_x += has_length.blend(f32x4::from(offsets_x) / distances, fallback) * scale;
+            force_y += has_length.blend(f32x4::from(offsets_y) / distances, fallback) * scale;
+        }
+
+        Vector2D::new(force_x.reduce_add(), force_y.reduce_add())
+    }
+}
+
+#[cfg(all(test, feature = "simd"))]
+mod tests {
+    use super::*;
+    use crate::{neighbours::Neighbour, rng::Rng};
+
+    const RADIUS: f32 = 0.35;
+
+    // Random neighbourhood including the sample itself at index 0 and a
+    // particle stacked on top of it
+    fn neighbourhood(rng: &mut Rng, count: usize) -> Neighbours {
+        let mut neighbours = Neighbours::default();
+        for index in 0..count {
+            let offset = match index {
+                0 | 1 => Vector2D::new(0.0, 0.0),
+                _ => Vector2D::new(rng.range(-0.24, 0.24), rng.range(-0.24, 0.24)),
+            };
+            neighbours.push(Neighbour {
+                index,
+                offset,
+                distance: offset.magnitude(),
+            });
+        }
+        neighbours
+    }
+
+    fn sample(index: usize) -> PressureSample {
+        let i = index as f32;
+        PressureSample {
+            pressure: 40.0 - 7.0 * i,
+            near_pressure: 0.3 * i,
+            mass: 1.0 + 0.1 * (index % 3) as f32,
+            density: 70.0 + i,
+            near_density: 200.0 + 3.0 * i,
+        }
+    }
+
+    fn assert_close(simd: f32, scalar: f32) {
+        let tolerance = 1e-5 * scalar.abs().max(1.0);
+        assert!((simd - scalar).abs() <= tolerance, "{simd} != {scalar}");
+    }
+
+    #[test]
+    fn lanes_match_scalar_sums() {
+        for kernel in [
+            KernelKind::Spiky,
+            KernelKind::Poly6,
+            KernelKind::CubicSpline,
+            KernelKind::WendlandC2,
+            KernelKind::WendlandC4,
+        ] {
+            let mut rng = Rng::new(3);
+            for count in 0..40 {
+                let neighbours = neighbourhood(&mut rng, count);
+                let mass_of = |index: usize| sample(index).mass;
+
+                let (density, near_density) =
+                    lanes::density_sums(&neighbours, RADIUS, kernel, mass_of);
+                let expected = scalar::density_sums(&neighbours, RADIUS, kernel, mass_of);
+                assert_close(density, expected.0);
+                assert_close(near_density, expected.1);
+
+                let force =
+                    lanes::pressure_force(&neighbours, 0, RADIUS, kernel, 12.0, 2.0, sample);
+                let expected =
+                    scalar::pressure_force(&neighbours, 0, RADIUS, kernel, 12.0, 2.0, sample);
+                assert_close(force.x, expected.x);
+                assert_close(force.y, expected.y);
+            }
+        }
+    }
+}
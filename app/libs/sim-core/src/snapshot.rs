@@ -0,0 +1,536 @@
+use std::fmt;
+
+use crate::{
+    Sim2D,
+    config::{ConfigError, SimConfig},
+    dye::{DYE_CHANNELS, DyeBrush},
+    flow::EmitterState,
+    kinematics::BodyState,
+    vector_2d::Vector2D,
+};
+
+const MAGIC: &[u8; 4] = b"SIM2";
+pub const SNAPSHOT_VERSION: u32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    TrailingBytes,
+    InvalidConfig,
+    InvalidParameters(ConfigError),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidMagic => write!(f, "not a Sim2D snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::TrailingBytes => write!(f, "snapshot has trailing bytes"),
+            SnapshotError::InvalidConfig => write!(f, "snapshot has an invalid config"),
+            SnapshotError::InvalidParameters(error) => {
+                write!(f, "snapshot has invalid parameters: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Sim2D {
+    /// Serialises the full simulation state into a versioned little-endian
+    /// binary blob that [`Sim2D::from_snapshot`] can resume from exactly.
+    ///
+    /// Layout: magic `SIM2`, `u32` version, `u32` length followed by the
+    /// current [`SimConfig`] as MessagePack, the next obstacle, kinematic
+    /// body, rigid body, emitter, sink and heat source ids, `f32` simulation
+    /// time, the state of each rigid body and emitter in config order,
+    /// interaction and dye brush state, `u64` particle count, `u32` next
+    /// particle id, one `u32` id per particle, positions and velocities as
+    /// interleaved `f32` pairs, one `u8` phase per particle, one `f32`
+    /// temperature per particle, then the dye channels of each particle as
+    /// `f32`s.
+    ///
+    /// Fails if the parameters have been set to values
+    /// [`SimConfig::validate`] rejects, since the snapshot couldn't be
+    /// restored.
+    pub fn snapshot(&self) -> Result<Vec<u8>, SnapshotError> {
+        let config = self.config();
+        config
+            .validate()
+            .map_err(SnapshotError::InvalidParameters)?;
+        let config = rmp_serde::to_vec_named(&config).expect("SimConfig is serialisable");
+        let mut writer = Writer(Vec::with_capacity(
+            68 + config.len() + self.particle_count * (25 + 4 * DYE_CHANNELS),
+        ));
+
+        writer.bytes(MAGIC);
+        writer.u32(SNAPSHOT_VERSION);
+
+        // Parameters
+        writer.u32(config.len() as u32);
+        writer.bytes(&config);
+        writer.u32(self.next_obstacle_id);
+        writer.u32(self.next_kinematic_body_id);
+        writer.u32(self.next_rigid_body_id);
+        writer.u32(self.next_emitter_id);
+        writer.u32(self.next_sink_id);
+        writer.u32(self.next_heat_source_id);
+        writer.f32(self.time);
+
+        // Rigid bodies
+        for state in &self.rigid_body_states {
+            writer.body_state(*state);
+        }
+
+        // Emitters
+        for state in &self.emitter_states {
+            writer.f32(state.pending);
+            writer.u32(state.emitted);
+        }
+
+        // User interaction
+        writer.u8(self.interaction_position.is_some() as u8);
+        writer.vector(self.interaction_position.unwrap_or(Vector2D::new(0.0, 0.0)));
+        writer.f32(self.interaction_scale);
+        writer.u8(self.dye_brush.is_some() as u8);
+        writer.vector(
+            self.dye_brush
+                .map_or(Vector2D::new(0.0, 0.0), |brush| brush.position),
+        );
+        writer.u32(self.dye_brush.map_or(0, |brush| brush.channel as u32));
+
+        // Particles
+        writer.u64(self.particle_count as u64);
+        writer.u32(self.next_particle_id);
+        writer.u32s(&self.particle_ids);
+        writer.vectors(&self.positions);
+        writer.vectors(&self.velocities);
+        writer.bytes(&self.particle_phases);
+        writer.f32s(&self.temperatures);
+        writer.f32s(self.dyes.as_flattened());
+
+        Ok(writer.0)
+    }
+
+    /// Rebuilds a simulation from [`Sim2D::snapshot`] output. Derived buffers
+    /// are recomputed on the next step, so stepping the restored simulation
+    /// matches stepping the original.
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader(snapshot);
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+
+        let version = reader.u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let config_len = reader.u32()? as usize;
+        let config: SimConfig = rmp_serde::from_slice(reader.take(config_len)?)
+            .map_err(|_| SnapshotError::InvalidConfig)?;
+        // Held to the same checks as a new simulation, so a corrupted or
+        // edited snapshot can't load parameters that would break a step
+        config
+            .validate()
+            .map_err(SnapshotError::InvalidParameters)?;
+        let next_obstacle_id = reader.u32()?;
+        let next_kinematic_body_id = reader.u32()?;
+        let next_rigid_body_id = reader.u32()?;
+        let next_emitter_id = reader.u32()?;
+        let next_sink_id = reader.u32()?;
+        let next_heat_source_id = reader.u32()?;
+        let time = reader.f32()?;
+
+        let rigid_body_states = (0..config.rigid_bodies.len())
+            .map(|_| reader.body_state())
+            .collect::<Result<Vec<_>, _>>()?;
+        let emitter_states = (0..config.emitters.len())
+            .map(|_| {
+                Ok(EmitterState {
+                    pending: reader.f32()?,
+                    emitted: reader.u32()?,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let interaction_active = reader.u8()? != 0;
+        let interaction_position = reader.vector()?;
+        let interaction_scale = reader.f32()?;
+        let dye_brush_active = reader.u8()? != 0;
+        let dye_brush_position = reader.vector()?;
+        let dye_brush_channel = reader.u32()? as usize;
+        if dye_brush_channel >= DYE_CHANNELS {
+            return Err(SnapshotError::InvalidConfig);
+        }
+
+        let particle_count = reader.u64()? as usize;
+        if particle_count > config.particle_capacity() {
+            return Err(SnapshotError::InvalidConfig);
+        }
+        let next_particle_id = reader.u32()?;
+        let particle_ids = reader.u32s(particle_count)?;
+        let positions = reader.vectors(particle_count)?;
+        let velocities = reader.vectors(particle_count)?;
+        let particle_phases = reader.take(particle_count)?.to_vec();
+        if particle_phases
+            .iter()
+            .any(|phase| *phase as usize > config.phases.len())
+        {
+            return Err(SnapshotError::InvalidConfig);
+        }
+        let temperatures = reader.f32s(particle_count)?;
+        let dyes = reader.f32s(particle_count * DYE_CHANNELS)?;
+
+        if !reader.0.is_empty() {
+            return Err(SnapshotError::TrailingBytes);
+        }
+
+        let mut sim = Self::from_particles(config, positions, velocities, particle_phases);
+        sim.next_obstacle_id = next_obstacle_id;
+        sim.next_kinematic_body_id = next_kinematic_body_id;
+        sim.next_rigid_body_id = next_rigid_body_id;
+        sim.next_emitter_id = next_emitter_id;
+        sim.next_sink_id = next_sink_id;
+        sim.next_heat_source_id = next_heat_source_id;
+        sim.particle_ids.copy_from_slice(&particle_ids);
+        sim.next_particle_id = next_particle_id;
+        sim.temperatures.copy_from_slice(&temperatures);
+        sim.dyes.as_flattened_mut().copy_from_slice(&dyes);
+        sim.time = time;
+        sim.rigid_body_states = rigid_body_states;
+        sim.emitter_states = emitter_states;
+        sim.interaction_position = interaction_active.then_some(interaction_position);
+        sim.interaction_scale = interaction_scale;
+        sim.dye_brush = dye_brush_active.then_some(DyeBrush {
+            position: dye_brush_position,
+            channel: dye_brush_channel,
+        });
+
+        Ok(sim)
+    }
+
+    /// Replaces the current state with a snapshot. Buffer pointers are
+    /// invalidated and must be fetched again.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), SnapshotError> {
+        *self = Self::from_snapshot(snapshot)?;
+        Ok(())
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn vector(&mut self, value: Vector2D) {
+        self.f32(value.x);
+        self.f32(value.y);
+    }
+
+    fn body_state(&mut self, value: BodyState) {
+        self.vector(value.position);
+        self.f32(value.rotation);
+        self.vector(value.velocity);
+        self.f32(value.angular_velocity);
+    }
+
+    fn vectors(&mut self, values: &[Vector2D]) {
+        for value in values {
+            self.vector(*value);
+        }
+    }
+
+    fn u32s(&mut self, values: &[u32]) {
+        for value in values {
+            self.u32(*value);
+        }
+    }
+
+    fn f32s(&mut self, values: &[f32]) {
+        for value in values {
+            self.f32(*value);
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.0.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, SnapshotError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn vector(&mut self) -> Result<Vector2D, SnapshotError> {
+        Ok(Vector2D::new(self.f32()?, self.f32()?))
+    }
+
+    fn body_state(&mut self) -> Result<BodyState, SnapshotError> {
+        Ok(BodyState {
+            position: self.vector()?,
+            rotation: self.f32()?,
+            velocity: self.vector()?,
+            angular_velocity: self.f32()?,
+        })
+    }
+
+    fn vectors(&mut self, count: usize) -> Result<Vec<Vector2D>, SnapshotError> {
+        // Check the length up front so a corrupt count can't trigger a huge allocation
+        if self.0.len() / 8 < count {
+            return Err(SnapshotError::Truncated);
+        }
+
+        (0..count).map(|_| self.vector()).collect()
+    }
+
+    fn u32s(&mut self, count: usize) -> Result<Vec<u32>, SnapshotError> {
+        if self.0.len() / 4 < count {
+            return Err(SnapshotError::Truncated);
+        }
+
+        (0..count).map(|_| self.u32()).collect()
+    }
+
+    fn f32s(&mut self, count: usize) -> Result<Vec<f32>, SnapshotError> {
+        if self.0.len() / 4 < count {
+            return Err(SnapshotError::Truncated);
+        }
+
+        (0..count).map(|_| self.f32()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        flow::Emitter,
+        kinematics::{KinematicBody, Motion},
+        layout::InitialLayout,
+        obstacles::{Obstacle, Shape},
+        rigid_bodies::RigidBody,
+        thermal::HeatSource,
+    };
+
+    fn small_sim() -> Sim2D {
+        Sim2D::new(
+            SimConfig::builder()
+                .particle_count(200)
+                .max_particle_count(260)
+                .world_dimensions(4.0, 3.0)
+                .layout(InitialLayout::DamBreak {
+                    width: 0.5,
+                    height: 0.8,
+                })
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn snapshot_resumes_identically() {
+        let mut original = small_sim();
+        original.enable_pull_interaction(0.5, -0.5);
+        original.enable_dye_interaction(-0.5, -0.5, 2).unwrap();
+        let obstacle = original.add_obstacle(Obstacle {
+            id: 0,
+            shape: Shape::Circle {
+                centre: Vector2D::new(0.5, 0.0),
+                radius: 0.3,
+            },
+            restitution: 0.5,
+            friction: 0.1,
+        });
+        original.add_kinematic_body(KinematicBody {
+            id: 0,
+            shape: Shape::Segment {
+                start: Vector2D::new(0.0, -0.5),
+                end: Vector2D::new(0.0, 0.5),
+                thickness: 0.05,
+            },
+            motion: Motion::Oscillator {
+                centre: Vector2D::new(-1.0, -1.0),
+                rotation: 0.0,
+                amplitude: Vector2D::new(0.4, 0.0),
+                angular_amplitude: 0.0,
+                frequency: 1.0,
+                phase: 0.0,
+                spin: 0.0,
+            },
+            restitution: 0.0,
+            friction: 0.2,
+        });
+        original.add_rigid_body(RigidBody {
+            id: 0,
+            shape: Shape::Circle {
+                centre: Vector2D::new(0.0, 0.0),
+                radius: 0.2,
+            },
+            mass: 5.0,
+            inertia: None,
+            position: Vector2D::new(-1.2, 0.5),
+            rotation: 0.0,
+            velocity: Vector2D::new(0.0, 0.0),
+            angular_velocity: 0.0,
+            restitution: 0.2,
+            friction: 0.3,
+        });
+        original
+            .add_emitter(Emitter {
+                id: 0,
+                position: Vector2D::new(1.0, 1.0),
+                velocity: Vector2D::new(-1.0, 0.0),
+                rate: 100.0,
+                width: 0.1,
+                phase: 0,
+            })
+            .unwrap();
+        original.add_heat_source(HeatSource {
+            id: 0,
+            shape: Shape::OrientedBox {
+                centre: Vector2D::new(0.0, -1.5),
+                half_extents: Vector2D::new(2.0, 0.1),
+                rotation: 0.0,
+            },
+            temperature: 5.0,
+            conductance: 2.0,
+        });
+        for _ in 0..10 {
+            original.step(1.0 / 60.0);
+        }
+
+        let mut restored = Sim2D::from_snapshot(&original.snapshot().unwrap()).unwrap();
+        assert_eq!(restored.snapshot(), original.snapshot());
+        assert_eq!(restored.obstacles()[0].id, obstacle);
+        assert_eq!(restored.time(), original.time());
+
+        for _ in 0..10 {
+            original.step(1.0 / 60.0);
+            restored.step(1.0 / 60.0);
+        }
+        assert_eq!(restored.positions, original.positions);
+        assert_eq!(restored.velocities, original.velocities);
+        assert_eq!(restored.temperatures, original.temperatures);
+        assert_eq!(restored.dyes, original.dyes);
+        assert!(restored.particle_count() > 200);
+        assert_eq!(restored.rigid_body_states, original.rigid_body_states);
+    }
+
+    #[test]
+    fn snapshot_rejects_bad_input() {
+        let snapshot = small_sim().snapshot().unwrap();
+
+        assert_eq!(
+            Sim2D::from_snapshot(b"nope").err(),
+            Some(SnapshotError::InvalidMagic)
+        );
+        assert_eq!(
+            Sim2D::from_snapshot(&snapshot[..snapshot.len() - 1]).err(),
+            Some(SnapshotError::Truncated)
+        );
+
+        // Config swapped for one that decodes but doesn't validate
+        let config_len = u32::from_le_bytes(snapshot[8..12].try_into().unwrap()) as usize;
+        let mut config: SimConfig = rmp_serde::from_slice(&snapshot[12..12 + config_len]).unwrap();
+        config.smoothing_radius = 0.0;
+        let config = rmp_serde::to_vec_named(&config).unwrap();
+        let mut edited = snapshot[..8].to_vec();
+        edited.extend_from_slice(&(config.len() as u32).to_le_bytes());
+        edited.extend_from_slice(&config);
+        edited.extend_from_slice(&snapshot[12 + config_len..]);
+        assert_eq!(
+            Sim2D::from_snapshot(&edited).err(),
+            Some(SnapshotError::InvalidParameters(ConfigError::NotPositive(
+                "smoothingRadius"
+            )))
+        );
+
+        let mut future = snapshot.clone();
+        future[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Sim2D::from_snapshot(&future).err(),
+            Some(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn snapshots_only_hold_parameters_that_restore() {
+        type Setter = fn(&mut Sim2D);
+        let setters: [(Setter, bool); 4] = [
+            (|sim| sim.set_gravity(-4.0), true),
+            (|sim| sim.set_viscosity_strength(0.5), true),
+            (|sim| sim.set_viscosity_strength(-1.0), false),
+            (|sim| sim.set_pressure_multiplier(f32::NAN), false),
+        ];
+
+        for (i, (set, valid)) in setters.into_iter().enumerate() {
+            let mut sim = small_sim();
+            set(&mut sim);
+
+            // Either both succeed or the snapshot is never written
+            match sim.snapshot() {
+                Ok(snapshot) => {
+                    assert!(valid, "setter {i}");
+                    assert!(Sim2D::from_snapshot(&snapshot).is_ok(), "setter {i}");
+                }
+                Err(error) => {
+                    assert!(!valid, "setter {i}");
+                    assert!(
+                        matches!(error, SnapshotError::InvalidParameters(_)),
+                        "setter {i}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,533 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Sim2D, config::ConfigError, kernels::Kernel, kinematics::BodyState, vector_2d::Vector2D,
+};
+
+/// Most constraint iterations a [`SolverKind::PositionBased`] solver may run
+/// per step.
+pub const MAX_SOLVER_ITERATIONS: u32 = 100;
+
+/// Most substeps a single [`Sim2D::step`] may be split into.
+pub const MAX_SUBSTEPS: u32 = 64;
+
+// Softens the density constraint so sparse neighbourhoods don't overshoot
+const CONSTRAINT_RELAXATION: f32 = 10.0;
+
+/// Which [`Solver`] advances the fluid, as stored in a
+/// [`crate::config::SimConfig`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SolverKind {
+    /// Explicit double-density relaxation. Cheap, but compressible and
+    /// unstable at large time steps.
+    #[default]
+    Explicit,
+    /// Position-based fluids (Macklin and Müller 2013), which iterate a
+    /// density constraint to keep the fluid close to incompressible at large
+    /// time steps. Rigid bodies are only pushed by particle contacts.
+    PositionBased { iterations: u32 },
+}
+
+impl SolverKind {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            SolverKind::Explicit => Ok(()),
+            SolverKind::PositionBased { iterations } => {
+                if (1..=MAX_SOLVER_ITERATIONS).contains(iterations) {
+                    Ok(())
+                } else {
+                    Err(ConfigError::InvalidSolver(
+                        "iterations must be between 1 and 100",
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn build(self) -> Box<dyn Solver> {
+        match self {
+            SolverKind::Explicit => Box::new(ExplicitSolver),
+            SolverKind::PositionBased { iterations } => Box::new(PositionBasedSolver {
+                iterations,
+                lambdas: Vec::new(),
+                corrections: Vec::new(),
+            }),
+        }
+    }
+}
+
+/// How [`Sim2D::step`] splits a frame into substeps, as stored in a
+/// [`crate::config::SimConfig`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Substepping {
+    /// The same number of substeps every frame.
+    Fixed { substeps: u32 },
+    /// Enough substeps that no particle moves further than `velocity_cfl`
+    /// smoothing radii in one, and that the fastest acceleration `a` keeps
+    /// each substep under `force_cfl * sqrt(h / a)`. Fast or strongly pushed
+    /// fluid then doesn't tunnel through walls.
+    Adaptive {
+        velocity_cfl: f32,
+        force_cfl: f32,
+        max_substeps: u32,
+    },
+}
+
+impl Default for Substepping {
+    fn default() -> Self {
+        Substepping::Fixed { substeps: 2 }
+    }
+}
+
+impl Substepping {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            Substepping::Fixed { substeps } => {
+                if (1..=MAX_SUBSTEPS).contains(substeps) {
+                    Ok(())
+                } else {
+                    Err(ConfigError::InvalidSubstepping(
+                        "substeps must be between 1 and 64",
+                    ))
+                }
+            }
+            Substepping::Adaptive {
+                velocity_cfl,
+                force_cfl,
+                max_substeps,
+            } => {
+                if !velocity_cfl.is_finite() || !force_cfl.is_finite() {
+                    return Err(ConfigError::InvalidSubstepping(
+                        "CFL numbers must be finite",
+                    ));
+                }
+                if *velocity_cfl <= 0.0 || *force_cfl <= 0.0 {
+                    return Err(ConfigError::InvalidSubstepping(
+                        "CFL numbers must be greater than 0",
+                    ));
+                }
+                if !(1..=MAX_SUBSTEPS).contains(max_substeps) {
+                    return Err(ConfigError::InvalidSubstepping(
+                        "max substeps must be between 1 and 64",
+                    ));
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Substeps to split `dt` into, given the fastest particle speed and
+    /// acceleration.
+    pub fn substeps(
+        &self,
+        dt: f32,
+        max_speed: f32,
+        max_acceleration: f32,
+        smoothing_radius: f32,
+    ) -> u32 {
+        match *self {
+            Substepping::Fixed { substeps } => substeps,
+            Substepping::Adaptive {
+                velocity_cfl,
+                force_cfl,
+                max_substeps,
+            } => {
+                let mut limit = f32::INFINITY;
+                if max_speed > 0.0 {
+                    limit = limit.min(velocity_cfl * smoothing_radius / max_speed);
+                }
+                if max_acceleration > 0.0 {
+                    limit = limit.min(force_cfl * (smoothing_radius / max_acceleration).sqrt());
+                }
+
+                let substeps = (dt / limit).ceil();
+                if substeps.is_nan() {
+                    return 1;
+                }
+                (substeps as u32).clamp(1, max_substeps)
+            }
+        }
+    }
+}
+
+/// Scheme that moves the particles forward in time.
+///
+/// [`Sim2D::step`] refreshes the per-phase constants and splits the frame
+/// into substeps, calling [`Solver::step`] once for each. It then updates
+/// the vorticities, paints dye, runs emitters and sinks and updates the
+/// velocity magnitudes.
+pub trait Solver: Send + Sync {
+    fn kind(&self) -> SolverKind;
+
+    /// Advances the particles by one substep of `dt`, including collisions
+    /// and rigid body coupling. A `dt` of 0 only refreshes derived buffers
+    /// such as the densities.
+    fn step(&mut self, sim: &mut Sim2D, dt: f32);
+}
+
+/// See [`SolverKind::Explicit`].
+pub struct ExplicitSolver;
+
+impl Solver for ExplicitSolver {
+    fn kind(&self) -> SolverKind {
+        SolverKind::Explicit
+    }
+
+    fn step(&mut self, sim: &mut Sim2D, dt: f32) {
+        sim.update_external_forces(dt);
+        sim.update_spatial_index();
+        sim.update_neighbours();
+        sim.update_densities();
+        sim.update_pressure_forces(dt);
+        if sim.surface_tension > 0.0 {
+            sim.update_surface_normals();
+            sim.update_surface_tension_forces(dt);
+        }
+        sim.update_viscous_forces(dt);
+        if sim.vorticity_confinement > 0.0 {
+            sim.update_vorticities();
+            sim.update_vorticity_confinement_forces(dt);
+        }
+        if sim.thermal_diffusivity > 0.0 || !sim.heat_sources.is_empty() {
+            sim.update_temperatures(dt);
+        }
+        if sim.dye_diffusivity > 0.0 {
+            sim.update_dyes(dt);
+        }
+        sim.update_positions(dt);
+        sim.update_rigid_bodies(dt);
+    }
+}
+
+/// See [`SolverKind::PositionBased`].
+pub struct PositionBasedSolver {
+    iterations: u32,
+    // Scratch per particle, sized on every step
+    lambdas: Vec<f32>,
+    corrections: Vec<Vector2D>,
+}
+
+impl Solver for PositionBasedSolver {
+    fn kind(&self) -> SolverKind {
+        SolverKind::PositionBased {
+            iterations: self.iterations,
+        }
+    }
+
+    fn step(&mut self, sim: &mut Sim2D, dt: f32) {
+        if dt <= 0.0 {
+            sim.update_spatial_index();
+            sim.update_neighbours();
+            sim.update_densities();
+            return;
+        }
+
+        sim.update_external_forces(dt);
+
+        // Predict a whole step ahead, where the explicit solver samples half
+        // way
+        sim.predicted_positions
+            .par_iter_mut()
+            .zip(sim.positions.par_iter())
+            .zip(sim.velocities.par_iter())
+            .for_each(|((predicted_position_ref, position_ref), velocity_ref)| {
+                *predicted_position_ref = *position_ref + *velocity_ref * dt;
+            });
+        sim.update_spatial_index();
+        sim.update_neighbours();
+
+        self.lambdas.resize(sim.particle_count, 0.0);
+        self.corrections
+            .resize(sim.particle_count, Vector2D::new(0.0, 0.0));
+
+        // Where the kinematic bodies will be at the end of the step
+        let body_states = sim.kinematic_body_states_at(sim.time + dt);
+        for _ in 0..self.iterations {
+            sim.update_densities();
+            self.update_lambdas(sim);
+            self.update_corrections(sim);
+            self.apply_corrections(sim, &body_states);
+            // Same neighbours, at their corrected distances
+            sim.spatial_index.refresh(&sim.predicted_positions);
+            sim.update_neighbours();
+        }
+
+        // The corrected positions give the new velocities, which the usual
+        // passes then adjust
+        sim.velocities
+            .par_iter_mut()
+            .zip(sim.predicted_positions.par_iter())
+            .zip(sim.positions.par_iter())
+            .for_each(|((velocity_ref, predicted_position_ref), position_ref)| {
+                *velocity_ref = (*predicted_position_ref - *position_ref) / dt;
+            });
+
+        if sim.surface_tension > 0.0 {
+            sim.update_surface_normals();
+            sim.update_surface_tension_forces(dt);
+        }
+        sim.update_viscous_forces(dt);
+        if sim.vorticity_confinement > 0.0 {
+            sim.update_vorticities();
+            sim.update_vorticity_confinement_forces(dt);
+        }
+        if sim.thermal_diffusivity > 0.0 || !sim.heat_sources.is_empty() {
+            sim.update_temperatures(dt);
+        }
+        if sim.dye_diffusivity > 0.0 {
+            sim.update_dyes(dt);
+        }
+        sim.update_positions(dt);
+        sim.update_rigid_bodies(dt);
+    }
+}
+
+impl PositionBasedSolver {
+    // Scales the constraint gradient so one correction would bring each
+    // compressed particle back to its rest density
+    fn update_lambdas(&mut self, sim: &Sim2D) {
+        self.lambdas
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(sample_index, lambda_ref)| {
+                let parameters = sim.phase_parameters[sim.particle_phases[sample_index] as usize];
+                let constraint = sim.densities[sample_index].0 / parameters.target_density - 1.0;
+
+                // Only push apart, so the free surface doesn't clump
+                if constraint <= 0.0 {
+                    *lambda_ref = 0.0;
+                    return;
+                }
+
+                let mut sample_gradient = Vector2D::new(0.0, 0.0);
+                let mut squared_gradients = 0.0;
+                for_each_neighbour(sim, sample_index, |neighbour_index, direction, distance| {
+                    let mass =
+                        sim.phase_parameters[sim.particle_phases[neighbour_index] as usize].mass;
+                    let gradient = direction
+                        * sim
+                            .density_kernel
+                            .derivative(distance, sim.smoothing_radius)
+                        * mass
+                        / parameters.target_density;
+
+                    sample_gradient += gradient;
+                    squared_gradients += gradient.dot(gradient);
+                });
+                squared_gradients += sample_gradient.dot(sample_gradient);
+
+                *lambda_ref = -constraint / (squared_gradients + CONSTRAINT_RELAXATION);
+            });
+    }
+
+    fn update_corrections(&mut self, sim: &Sim2D) {
+        self.corrections
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(sample_index, correction_ref)| {
+                let parameters = sim.phase_parameters[sim.particle_phases[sample_index] as usize];
+                let lambda = self.lambdas[sample_index];
+
+                *correction_ref = Vector2D::new(0.0, 0.0);
+                for_each_neighbour(sim, sample_index, |neighbour_index, direction, distance| {
+                    let mass =
+                        sim.phase_parameters[sim.particle_phases[neighbour_index] as usize].mass;
+
+                    // Kernel gradient with respect to the sample points away
+                    // from the neighbour
+                    *correction_ref -= direction
+                        * sim
+                            .density_kernel
+                            .derivative(distance, sim.smoothing_radius)
+                        * (lambda + self.lambdas[neighbour_index])
+                        * mass
+                        / parameters.target_density;
+                });
+            });
+    }
+
+    fn apply_corrections(&self, sim: &mut Sim2D, body_states: &[BodyState]) {
+        let mut predicted_positions = std::mem::take(&mut sim.predicted_positions);
+        predicted_positions
+            .par_iter_mut()
+            .zip(self.corrections.par_iter())
+            .for_each(|(predicted_position_ref, correction_ref)| {
+                *predicted_position_ref += *correction_ref;
+
+                // Whatever particles collide with is part of the constraint,
+                // or they pile into it and spring back out. Only the
+                // position matters here; the velocity comes from it later.
+                let mut velocity = Vector2D::new(0.0, 0.0);
+                sim.collide(body_states, predicted_position_ref, &mut velocity);
+            });
+        sim.predicted_positions = predicted_positions;
+    }
+}
+
+// Calls `f` with the index, direction and distance of every other particle
+// within the smoothing radius of `sample_index`
+fn for_each_neighbour(sim: &Sim2D, sample_index: usize, mut f: impl FnMut(usize, Vector2D, f32)) {
+    for neighbour in sim.neighbours.of(sample_index).iter() {
+        if neighbour.index == sample_index {
+            continue;
+        }
+
+        // Particles stacked against a wall or obstacle still need pulling apart, in
+        // opposite directions
+        let direction = if neighbour.distance > 0.0 {
+            neighbour.offset / neighbour.distance
+        } else if neighbour.index > sample_index {
+            Vector2D::new(1.0, 1.0).normalise()
+        } else {
+            Vector2D::new(-1.0, -1.0).normalise()
+        };
+        f(neighbour.index, direction, neighbour.distance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::SimConfig,
+        obstacles::{Obstacle, Shape},
+    };
+
+    fn resting_sim(solver: SolverKind) -> Sim2D {
+        Sim2D::new(
+            SimConfig::builder()
+                .particle_count(400)
+                .world_dimensions(4.0, 3.0)
+                .solver(solver)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn position_based_solver_settles_at_large_steps() {
+        let mut sim = resting_sim(SolverKind::PositionBased { iterations: 10 });
+        for _ in 0..60 {
+            sim.step(1.0 / 20.0);
+        }
+
+        let max_speed = sim
+            .velocities
+            .iter()
+            .map(|v| v.magnitude())
+            .fold(0.0, f32::max);
+        let max_density = sim.densities.iter().map(|d| d.0).fold(0.0, f32::max);
+        assert!(max_speed < 1.0, "{max_speed}");
+        assert!(max_density < 1.25 * sim.target_density, "{max_density}");
+    }
+
+    #[test]
+    fn position_based_constraint_keeps_clear_of_obstacles() {
+        let obstacle = Obstacle {
+            id: 0,
+            shape: Shape::Circle {
+                centre: Vector2D::new(0.0, -0.7),
+                radius: 0.5,
+            },
+            restitution: 0.0,
+            friction: 0.0,
+        };
+        let mut sim = Sim2D::new(
+            SimConfig::builder()
+                .particle_count(400)
+                .world_dimensions(4.0, 3.0)
+                .solver(SolverKind::PositionBased { iterations: 10 })
+                .obstacle(obstacle.clone())
+                .build()
+                .unwrap(),
+        );
+
+        // The corrected positions, before the usual passes move the particles
+        for _ in 0..60 {
+            sim.step(1.0 / 20.0);
+            for position in &sim.predicted_positions {
+                let (distance, _) = obstacle.shape.signed_distance(*position);
+                assert!(distance > 0.0, "{position:?} inside the obstacle");
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_substeps_follow_speed_and_force() {
+        let substepping = Substepping::Adaptive {
+            velocity_cfl: 0.5,
+            force_cfl: 0.5,
+            max_substeps: 8,
+        };
+
+        assert_eq!(substepping.substeps(0.0, 10.0, 10.0, 0.35), 1);
+        assert_eq!(substepping.substeps(1.0 / 60.0, 0.0, 0.0, 0.35), 1);
+        // 0.5 * 0.35 / 30 per substep
+        assert_eq!(substepping.substeps(1.0 / 60.0, 30.0, 0.0, 0.35), 3);
+        // 0.5 * sqrt(0.35 / 3500) = 0.005 per substep
+        assert_eq!(substepping.substeps(1.0 / 60.0, 0.0, 3500.0, 0.35), 4);
+        assert_eq!(substepping.substeps(1.0 / 60.0, 1000.0, 0.0, 0.35), 8);
+    }
+
+    #[test]
+    fn adaptive_substepping_keeps_heavy_fluid_contained() {
+        let mut sim = Sim2D::new(
+            SimConfig::builder()
+                .particle_count(400)
+                .world_dimensions(4.0, 3.0)
+                .gravity(-400.0)
+                .substepping(Substepping::Adaptive {
+                    velocity_cfl: 0.4,
+                    force_cfl: 0.25,
+                    max_substeps: 16,
+                })
+                .build()
+                .unwrap(),
+        );
+
+        let mut most_substeps = 0;
+        for _ in 0..30 {
+            sim.step(1.0 / 60.0);
+            most_substeps = most_substeps.max(sim.substeps());
+        }
+
+        assert!(most_substeps > 2, "{most_substeps}");
+        assert!(
+            sim.positions
+                .iter()
+                .all(|p| p.x.abs() <= 2.0 && p.y.abs() <= 1.5)
+        );
+    }
+
+    #[test]
+    fn solver_switches_at_runtime() {
+        let mut sim = resting_sim(SolverKind::Explicit);
+        sim.step(1.0 / 60.0);
+        let count = sim.particle_count();
+
+        let solver = SolverKind::PositionBased { iterations: 3 };
+        sim.set_solver(solver);
+        sim.step(1.0 / 60.0);
+
+        assert_eq!(sim.solver(), solver);
+        assert_eq!(sim.config().solver, solver);
+        assert_eq!(sim.particle_count(), count);
+        assert!(
+            sim.positions
+                .iter()
+                .all(|p| p.x.is_finite() && p.y.is_finite())
+        );
+    }
+}
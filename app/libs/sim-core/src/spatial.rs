@@ -0,0 +1,470 @@
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ConfigError,
+    hashing::{get_cell, hash_cell, key_from_hash},
+    vector_2d::Vector2D,
+};
+
+/// Most cells a [`SpatialIndexKind::FlatGrid`] may span, which bounds its
+/// memory at around 32 MB.
+pub const MAX_GRID_CELLS: usize = 1 << 22;
+
+/// Which [`SpatialIndex`] finds neighbouring particles, as stored in a
+/// [`crate::config::SimConfig`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SpatialIndexKind {
+    /// Cells hashed into a table the size of the particle count, then sorted
+    /// by key. Unbounded, but cells that share a key are searched together.
+    HashGrid,
+    /// Dense grid over the world box, filled by a counting sort. Particles
+    /// more than a cell outside the box are kept in the border cells.
+    #[default]
+    FlatGrid,
+}
+
+impl SpatialIndexKind {
+    pub fn build(self) -> Box<dyn SpatialIndex> {
+        match self {
+            SpatialIndexKind::HashGrid => Box::new(HashGrid::default()),
+            SpatialIndexKind::FlatGrid => Box::new(FlatGrid::default()),
+        }
+    }
+
+    /// Checks the index can bucket a world of `world_dimensions` into cells
+    /// of side `cell_size`.
+    pub fn validate(self, cell_size: f32, world_dimensions: Vector2D) -> Result<(), ConfigError> {
+        if self == SpatialIndexKind::FlatGrid
+            && FlatGrid::cell_count(cell_size, world_dimensions) > MAX_GRID_CELLS
+        {
+            return Err(ConfigError::InvalidSpatialIndex(
+                "smoothing radius is too small for a flat grid over the world",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Buckets particles by grid cell so neighbour searches only visit the cells
+/// around a particle.
+///
+/// Each bucket keeps a copy of its particles' positions next to their
+/// indices, so the neighbour loops read memory in order. [`crate::Sim2D`]
+/// then sorts its particle buffers into bucket order and renumbers the
+/// index, so the rest of each particle's data is read in order too.
+pub trait SpatialIndex: Send + Sync {
+    fn kind(&self) -> SpatialIndexKind;
+
+    /// Buckets `positions` into cells of side `cell_size`. A `stable`
+    /// rebuild keeps the particles in each cell in index order.
+    fn rebuild(
+        &mut self,
+        positions: &[Vector2D],
+        cell_size: f32,
+        world_dimensions: Vector2D,
+        stable: bool,
+    );
+
+    /// Copies moved `positions` in without rebucketing, for passes that nudge
+    /// particles between rebuilds.
+    fn refresh(&mut self, positions: &[Vector2D]);
+
+    /// Particle indices in bucket order, as of the last rebuild.
+    fn order(&self) -> &[usize];
+
+    /// Numbers the bucketed particles in bucket order, once the particle
+    /// buffers have been permuted into [`SpatialIndex::order`].
+    fn renumber(&mut self);
+
+    /// Indices and positions of the particles bucketed in `cell`, in matching
+    /// order. May include particles from other cells, so callers still check
+    /// the distance.
+    fn cell(&self, cell: (isize, isize)) -> (&[usize], &[Vector2D]);
+}
+
+/// See [`SpatialIndexKind::HashGrid`].
+#[derive(Default)]
+pub struct HashGrid {
+    cell_keys: Vec<(usize, usize)>,
+    // Start and end of each key's run in the sorted buffers
+    cell_ranges: HashMap<usize, (usize, usize)>,
+    indices: Vec<usize>,
+    positions: Vec<Vector2D>,
+}
+
+impl SpatialIndex for HashGrid {
+    fn kind(&self) -> SpatialIndexKind {
+        SpatialIndexKind::HashGrid
+    }
+
+    fn rebuild(
+        &mut self,
+        positions: &[Vector2D],
+        cell_size: f32,
+        _world_dimensions: Vector2D,
+        stable: bool,
+    ) {
+        let table_size = positions.len();
+        self.cell_keys.clear();
+        self.cell_keys
+            .par_extend(positions.par_iter().enumerate().map(|(i, position)| {
+                let hash = hash_cell(get_cell(*position, cell_size));
+                (key_from_hash(hash, table_size), i)
+            }));
+
+        // Keys are written in particle order, so a stable sort leaves each
+        // cell's neighbours, and so every sum over them, in a fixed order
+        if stable {
+            self.cell_keys.sort_by_key(|p| p.0);
+        } else {
+            self.cell_keys.sort_unstable_by_key(|p| p.0);
+        }
+
+        self.cell_ranges.clear();
+        for (i, &(key, _)) in self.cell_keys.iter().enumerate() {
+            self.cell_ranges.entry(key).or_insert((i, i)).1 = i + 1;
+        }
+
+        self.indices.clear();
+        self.indices
+            .extend(self.cell_keys.iter().map(|&(_, index)| index));
+        self.refresh(positions);
+    }
+
+    fn refresh(&mut self, positions: &[Vector2D]) {
+        self.positions.clear();
+        self.positions
+            .extend(self.indices.iter().map(|&index| positions[index]));
+    }
+
+    fn order(&self) -> &[usize] {
+        &self.indices
+    }
+
+    fn renumber(&mut self) {
+        for (i, index_ref) in self.indices.iter_mut().enumerate() {
+            *index_ref = i;
+        }
+    }
+
+    fn cell(&self, cell: (isize, isize)) -> (&[usize], &[Vector2D]) {
+        let key = key_from_hash(hash_cell(cell), self.indices.len());
+        match self.cell_ranges.get(&key) {
+            Some(&(start, end)) => (&self.indices[start..end], &self.positions[start..end]),
+            None => (&[], &[]),
+        }
+    }
+}
+
+/// See [`SpatialIndexKind::FlatGrid`].
+#[derive(Default)]
+pub struct FlatGrid {
+    // Cell coordinates of the bottom left cell, and the grid size in cells
+    origin: (isize, isize),
+    size: (usize, usize),
+    // Cell `i` holds entries `cell_starts[i]..cell_starts[i + 1]`
+    cell_starts: Vec<usize>,
+    // Scratch for the counting sort
+    particle_cells: Vec<usize>,
+    cell_fill: Vec<usize>,
+    indices: Vec<usize>,
+    positions: Vec<Vector2D>,
+    // Takes over when the world would need more than `MAX_GRID_CELLS` cells
+    fallback: Option<HashGrid>,
+}
+
+impl FlatGrid {
+    /// Cells the grid needs to cover `world_dimensions`.
+    pub fn cell_count(cell_size: f32, world_dimensions: Vector2D) -> usize {
+        let (_, size) = Self::bounds(cell_size, world_dimensions);
+        size.0.saturating_mul(size.1)
+    }
+
+    // One cell of padding catches particles predicted just past the walls.
+    // Saturates rather than overflowing for vanishing cells.
+    fn bounds(cell_size: f32, world_dimensions: Vector2D) -> ((isize, isize), (usize, usize)) {
+        let half = world_dimensions * 0.5;
+        let min = get_cell(half * -1.0, cell_size);
+        let max = get_cell(half, cell_size);
+        let span =
+            |min: isize, max: isize| max.saturating_sub(min).saturating_add(3).max(1) as usize;
+
+        (
+            (min.0.saturating_sub(1), min.1.saturating_sub(1)),
+            (span(min.0, max.0), span(min.1, max.1)),
+        )
+    }
+}
+
+impl SpatialIndex for FlatGrid {
+    fn kind(&self) -> SpatialIndexKind {
+        SpatialIndexKind::FlatGrid
+    }
+
+    // Always stable, as the scatter runs in particle order
+    fn rebuild(
+        &mut self,
+        positions: &[Vector2D],
+        cell_size: f32,
+        world_dimensions: Vector2D,
+        stable: bool,
+    ) {
+        // Configs are validated against the limit, but a grid too big to
+        // allocate is hashed instead of aborting
+        if Self::cell_count(cell_size, world_dimensions) > MAX_GRID_CELLS {
+            self.fallback.get_or_insert_with(HashGrid::default).rebuild(
+                positions,
+                cell_size,
+                world_dimensions,
+                stable,
+            );
+            return;
+        }
+        self.fallback = None;
+
+        (self.origin, self.size) = Self::bounds(cell_size, world_dimensions);
+        let (origin, size) = (self.origin, self.size);
+        let cell_count = size.0 * size.1;
+
+        self.particle_cells.clear();
+        self.particle_cells
+            .par_extend(positions.par_iter().map(|position| {
+                let cell = get_cell(*position, cell_size);
+                let x = cell
+                    .0
+                    .saturating_sub(origin.0)
+                    .clamp(0, size.0 as isize - 1);
+                let y = cell
+                    .1
+                    .saturating_sub(origin.1)
+                    .clamp(0, size.1 as isize - 1);
+                y as usize * size.0 + x as usize
+            }));
+
+        // Count each cell, then prefix sum the counts into starts
+        self.cell_starts.clear();
+        self.cell_starts.resize(cell_count + 1, 0);
+        for &cell in &self.particle_cells {
+            self.cell_starts[cell + 1] += 1;
+        }
+        for i in 1..=cell_count {
+            self.cell_starts[i] += self.cell_starts[i - 1];
+        }
+
+        self.cell_fill.clear();
+        self.cell_fill
+            .extend_from_slice(&self.cell_starts[..cell_count]);
+        self.indices.resize(positions.len(), 0);
+        for (index, &cell) in self.particle_cells.iter().enumerate() {
+            self.indices[self.cell_fill[cell]] = index;
+            self.cell_fill[cell] += 1;
+        }
+
+        self.refresh(positions);
+    }
+
+    fn refresh(&mut self, positions: &[Vector2D]) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.refresh(positions);
+            return;
+        }
+
+        self.positions.clear();
+        self.positions
+            .extend(self.indices.iter().map(|&index| positions[index]));
+    }
+
+    fn order(&self) -> &[usize] {
+        match &self.fallback {
+            Some(fallback) => fallback.order(),
+            None => &self.indices,
+        }
+    }
+
+    fn renumber(&mut self) {
+        if let Some(fallback) = &mut self.fallback {
+            fallback.renumber();
+            return;
+        }
+
+        for (i, index_ref) in self.indices.iter_mut().enumerate() {
+            *index_ref = i;
+        }
+    }
+
+    fn cell(&self, cell: (isize, isize)) -> (&[usize], &[Vector2D]) {
+        if let Some(fallback) = &self.fallback {
+            return fallback.cell(cell);
+        }
+
+        let x = cell.0.saturating_sub(self.origin.0);
+        let y = cell.1.saturating_sub(self.origin.1);
+        if x < 0 || y < 0 || x as usize >= self.size.0 || y as usize >= self.size.1 {
+            return (&[], &[]);
+        }
+
+        let i = y as usize * self.size.0 + x as usize;
+        let range = self.cell_starts[i]..self.cell_starts[i + 1];
+        (&self.indices[range.clone()], &self.positions[range])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hashing::GRID_OFFSETS, layout::InitialLayout};
+
+    // Sorted indices within `radius` of each particle, searched through the
+    // index
+    fn neighbours(
+        index: &dyn SpatialIndex,
+        positions: &[Vector2D],
+        radius: f32,
+    ) -> Vec<Vec<usize>> {
+        positions
+            .iter()
+            .map(|position| {
+                let origin_cell = get_cell(*position, radius);
+                let mut found = Vec::new();
+                for (x, y) in GRID_OFFSETS {
+                    let (indices, cell_positions) =
+                        index.cell((origin_cell.0 + x, origin_cell.1 + y));
+                    for (&neighbour_index, &neighbour_position) in
+                        indices.iter().zip(cell_positions)
+                    {
+                        let offset = neighbour_position - *position;
+                        if offset.dot(offset) <= radius * radius {
+                            found.push(neighbour_index);
+                        }
+                    }
+                }
+                found.sort_unstable();
+                found
+            })
+            .collect()
+    }
+
+    #[test]
+    fn grids_find_every_neighbour() {
+        let world_dimensions = Vector2D::new(4.0, 3.0);
+        let radius = 0.35;
+        let positions = InitialLayout::PoissonDisk { seed: 7 }
+            .generate(300, world_dimensions, 0.025)
+            .unwrap();
+
+        let brute_force: Vec<Vec<usize>> = positions
+            .iter()
+            .map(|position| {
+                (0..positions.len())
+                    .filter(|&i| {
+                        let offset = positions[i] - *position;
+                        offset.dot(offset) <= radius * radius
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for kind in [SpatialIndexKind::HashGrid, SpatialIndexKind::FlatGrid] {
+            let mut index = kind.build();
+            index.rebuild(&positions, radius, world_dimensions, true);
+
+            let mut found = neighbours(index.as_ref(), &positions, radius);
+            // Cells that share a hash key are visited once per key
+            for list in &mut found {
+                list.dedup();
+            }
+            assert_eq!(found, brute_force, "{kind:?}");
+        }
+
+        // The flat grid visits each particle once
+        let mut index = FlatGrid::default();
+        index.rebuild(&positions, radius, world_dimensions, false);
+        assert_eq!(neighbours(&index, &positions, radius), brute_force);
+    }
+
+    #[test]
+    fn flat_grid_keeps_cells_in_particle_order_and_strays_in_border() {
+        let positions = vec![
+            Vector2D::new(0.1, 0.1),
+            Vector2D::new(-0.9, 0.4),
+            Vector2D::new(0.2, 0.3),
+            Vector2D::new(50.0, 0.1),
+            Vector2D::new(0.3, 0.2),
+        ];
+        let mut index = FlatGrid::default();
+        index.rebuild(&positions, 0.5, Vector2D::new(2.0, 2.0), false);
+
+        let (indices, cell_positions) = index.cell((0, 0));
+        assert_eq!(indices, &[0, 2, 4]);
+        assert_eq!(cell_positions[1], positions[2]);
+
+        // Far past the right wall, so clamped into the padding column
+        assert_eq!(index.cell((3, 0)).0, &[3]);
+        assert!(index.cell((100, 0)).0.is_empty());
+    }
+
+    #[test]
+    fn renumbered_grids_match_positions_permuted_into_bucket_order() {
+        let world_dimensions = Vector2D::new(4.0, 3.0);
+        let radius = 0.35;
+        let positions = InitialLayout::PoissonDisk { seed: 3 }
+            .generate(200, world_dimensions, 0.025)
+            .unwrap();
+
+        for kind in [SpatialIndexKind::HashGrid, SpatialIndexKind::FlatGrid] {
+            let mut index = kind.build();
+            index.rebuild(&positions, radius, world_dimensions, true);
+            let order = index.order().to_vec();
+            let permuted: Vec<Vector2D> = order.iter().map(|&i| positions[i]).collect();
+            index.renumber();
+
+            assert_eq!(index.order(), (0..positions.len()).collect::<Vec<_>>());
+            // The same neighbours, under their new indices
+            let expected: Vec<Vec<usize>> = neighbours(index.as_ref(), &permuted, radius);
+            let mut rebuilt = kind.build();
+            rebuilt.rebuild(&permuted, radius, world_dimensions, true);
+            assert_eq!(
+                neighbours(rebuilt.as_ref(), &permuted, radius),
+                expected,
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn flat_grid_hashes_worlds_too_big_to_allocate() {
+        let world_dimensions = Vector2D::new(4.0, 3.0);
+        let radius = 1e-4;
+        assert!(FlatGrid::cell_count(radius, world_dimensions) > MAX_GRID_CELLS);
+        assert!(
+            SpatialIndexKind::FlatGrid
+                .validate(radius, world_dimensions)
+                .is_err()
+        );
+        assert_eq!(FlatGrid::cell_count(0.0, world_dimensions), usize::MAX);
+
+        let positions = vec![
+            Vector2D::new(0.1, 0.1),
+            Vector2D::new(0.10005, 0.1),
+            Vector2D::new(-1.0, 1.0),
+        ];
+        let mut flat = FlatGrid::default();
+        flat.rebuild(&positions, radius, world_dimensions, true);
+        let mut hashed = HashGrid::default();
+        hashed.rebuild(&positions, radius, world_dimensions, true);
+        assert_eq!(
+            neighbours(&flat, &positions, radius),
+            neighbours(&hashed, &positions, radius)
+        );
+
+        // Back to a dense grid once the cells are big enough
+        flat.rebuild(&positions, 0.5, world_dimensions, true);
+        assert!(flat.fallback.is_none());
+        assert_eq!(flat.cell((0, 0)).0, &[0, 1]);
+    }
+}
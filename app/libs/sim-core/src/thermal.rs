@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ConfigError, obstacles::Shape, vector_2d::Vector2D};
+
+/// Region held at a fixed temperature, such as a hotplate under the fluid or
+/// a chilled lid above it. Particles within a smoothing radius of it are
+/// heated or cooled towards `temperature`. It doesn't collide, so place it
+/// against the world box or pair it with an obstacle.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeatSource {
+    /// Assigned by [`crate::Sim2D::add_heat_source`]; must be unique in a
+    /// config.
+    #[serde(default)]
+    pub id: u32,
+    pub shape: Shape,
+    pub temperature: f32,
+    /// Rate at which touching particles approach `temperature`, per second.
+    pub conductance: f32,
+}
+
+impl HeatSource {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.temperature.is_finite() || !self.conductance.is_finite() {
+            return Err(ConfigError::InvalidHeatSource("values must be finite"));
+        }
+        if self.conductance < 0.0 {
+            return Err(ConfigError::InvalidHeatSource(
+                "conductance must not be negative",
+            ));
+        }
+
+        self.shape.validate()
+    }
+
+    /// Temperature change over `dt` of a particle at `position`, which feels
+    /// the source within `reach` of its surface. Relaxes exponentially, so it
+    /// never overshoots however long the step. The exponential goes through
+    /// libm, so deterministic runs match between native and wasm builds.
+    pub(crate) fn heat(&self, position: Vector2D, temperature: f32, reach: f32, dt: f32) -> f32 {
+        if self.shape.signed_distance(position).0 >= reach {
+            return 0.0;
+        }
+
+        (self.temperature - temperature) * (1.0 - libm::expf(-self.conductance * dt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Sim2D, config::SimConfig};
+
+    // Still, weightless box of fluid, with the left and right halves at -1
+    // and 1
+    fn split_box(thermal_diffusivity: f32) -> Sim2D {
+        let mut sim = Sim2D::new(
+            SimConfig::builder()
+                .particle_count(600)
+                .world_dimensions(4.0, 2.0)
+                .gravity(0.0)
+                .thermal_diffusivity(thermal_diffusivity)
+                .build()
+                .unwrap(),
+        );
+        for (temperature, position) in sim.temperatures.iter_mut().zip(&sim.positions) {
+            *temperature = position.x.signum();
+        }
+        sim
+    }
+
+    #[test]
+    fn heat_diffuses_from_hot_to_cold() {
+        let mut sim = split_box(0.05);
+        let total = |sim: &Sim2D| sim.temperatures.iter().sum::<f32>();
+        let before = total(&sim);
+
+        for _ in 0..60 {
+            sim.step(1.0 / 60.0);
+        }
+
+        // The step between the halves softens while the heat is only moved
+        // around
+        let near_middle: Vec<f32> = sim
+            .temperatures
+            .iter()
+            .zip(&sim.positions)
+            .filter(|(_, position)| position.x.abs() < 0.2)
+            .map(|(temperature, _)| temperature.abs())
+            .collect();
+        let step = near_middle.iter().sum::<f32>() / near_middle.len() as f32;
+        assert!(step < 0.8, "{step}");
+        assert!(
+            (total(&sim) - before).abs() < 0.01,
+            "{before} -> {}",
+            total(&sim)
+        );
+
+        let insulated = split_box(0.0);
+        assert!(insulated.temperatures.iter().all(|t| t.abs() == 1.0));
+    }
+
+    #[test]
+    fn hotplate_warms_fluid_until_it_rises() {
+        // Mean height of the fluid the plate has warmed
+        let convect = |thermal_expansion: f32| {
+            let hotplate = HeatSource {
+                id: 0,
+                shape: Shape::OrientedBox {
+                    centre: Vector2D::new(0.0, -1.5),
+                    half_extents: Vector2D::new(0.5, 0.1),
+                    rotation: 0.0,
+                },
+                temperature: 10.0,
+                conductance: 5.0,
+            };
+            let mut sim = Sim2D::new(
+                SimConfig::builder()
+                    .particle_count(600)
+                    .world_dimensions(3.0, 3.0)
+                    .thermal_diffusivity(0.01)
+                    .thermal_expansion(thermal_expansion)
+                    .heat_source(hotplate)
+                    .build()
+                    .unwrap(),
+            );
+
+            for _ in 0..300 {
+                sim.step(1.0 / 60.0);
+            }
+
+            let warm: Vec<f32> = sim
+                .temperatures
+                .iter()
+                .zip(&sim.positions)
+                .filter(|(temperature, _)| **temperature > 1.0)
+                .map(|(_, position)| position.y)
+                .collect();
+            warm.iter().sum::<f32>() / warm.len() as f32
+        };
+
+        // Conduction alone keeps the warmth near the floor
+        let conducted = convect(0.0);
+        let convected = convect(0.15);
+        assert!(conducted < -0.9, "{conducted}");
+        assert!(convected > conducted + 0.5, "{convected} vs {conducted}");
+    }
+
+    #[test]
+    fn heat_source_rejects_negative_conductance() {
+        let source = HeatSource {
+            id: 0,
+            shape: Shape::Circle {
+                centre: Vector2D::new(0.0, 0.0),
+                radius: 1.0,
+            },
+            temperature: 1.0,
+            conductance: -1.0,
+        };
+        assert!(matches!(
+            source.validate(),
+            Err(ConfigError::InvalidHeatSource(_))
+        ));
+    }
+}
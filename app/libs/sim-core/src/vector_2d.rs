@@ -33,6 +33,27 @@ impl Vector2D {
     pub fn normalise(self) -> Self {
         self / self.magnitude()
     }
+
+    pub fn cross(self, rhs: Self) -> f32 {
+        self.x * rhs.y - self.y * rhs.x
+    }
+
+    // Rotated 90 degrees anticlockwise
+    pub fn perpendicular(self) -> Self {
+        Self {
+            x: -self.y,
+            y: self.x,
+        }
+    }
+
+    pub fn rotate(self, angle: f32) -> Self {
+        // libm rather than the platform's, so native and wasm builds agree
+        let (sin, cos) = libm::sincosf(angle);
+        Self {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
+        }
+    }
 }
 
 impl Add for Vector2D {
@@ -139,6 +160,30 @@ mod tests {
         assert_eq!(a.normalise(), Vector2D::new(3.0 / 5.0, -4.0 / 5.0));
     }
 
+    #[test]
+    fn vector_cross() {
+        let a = Vector2D::new(3.0, -4.0);
+        let b = Vector2D::new(-6.0, 1.0);
+
+        assert_eq!(a.cross(b), 3.0 * 1.0 - -4.0 * -6.0);
+    }
+
+    #[test]
+    fn vector_perpendicular() {
+        let a = Vector2D::new(3.0, -4.0);
+
+        assert_eq!(a.perpendicular(), Vector2D::new(4.0, 3.0));
+        assert_eq!(a.perpendicular().dot(a), 0.0);
+    }
+
+    #[test]
+    fn vector_rotate() {
+        let a = Vector2D::new(1.0, 0.0).rotate(std::f32::consts::FRAC_PI_2);
+
+        assert!((a.x - 0.0).abs() < 1e-6);
+        assert!((a.y - 1.0).abs() < 1e-6);
+    }
+
     #[test]
     fn vector_add() {
         let a = Vector2D::new(1.0, -3.0);
@@ -0,0 +1,384 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfigError;
+
+/// Shear rate below which the non-Newtonian models treat the fluid as at
+/// rest, so their viscosity stays finite.
+const MIN_STRAIN_RATE: f32 = 1e-4;
+
+/// How the viscous pass couples neighbouring velocities, as stored in a
+/// [`crate::config::SimConfig`]. Every model weights neighbours with the
+/// config's `viscosity_kernel`.
+///
+/// The Laplacian and non-Newtonian models share one force, with viscosities
+/// in world units squared per second. Explicit updates go unstable above
+/// about `h^2 / (8 dt)` for a substep of `dt`, so viscosities are capped
+/// there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ViscosityModel {
+    /// Pulls each velocity towards its neighbours' at a rate set by
+    /// `viscosity_strength` and the viscosity of each phase. Cheap, but the
+    /// strength has no physical unit.
+    #[default]
+    Diffusion,
+    /// XSPH smoothing (Monaghan 1989), which blends each velocity with the
+    /// kernel-weighted mean of its neighbours' by `epsilon` every substep.
+    /// Tidies up noisy, very thin fluids without slowing bulk flow.
+    Xsph { epsilon: f32 },
+    /// Monaghan (1992) artificial viscosity, which only acts between
+    /// approaching particles. `alpha` scales the linear term with the speed
+    /// of sound and `beta` the quadratic term that stops particles passing
+    /// through each other in shocks.
+    Artificial { alpha: f32, beta: f32 },
+    /// Laplacian of the velocity (Morris et al. 1997), for a Newtonian fluid
+    /// with a real kinematic viscosity.
+    Laplacian { kinematic_viscosity: f32 },
+    /// Power-law fluid with viscosity `consistency * rate^(flow_index - 1)`
+    /// at shear rate `rate`. A `flow_index` below 1 thins under shear like
+    /// ketchup, and above 1 thickens like oobleck.
+    PowerLaw { consistency: f32, flow_index: f32 },
+    /// Cross fluid, which thins from `zero_shear_viscosity` at rest towards
+    /// `infinite_shear_viscosity` once the shear rate passes about
+    /// `1 / time_constant`, more sharply for larger `rate_index`.
+    Cross {
+        zero_shear_viscosity: f32,
+        infinite_shear_viscosity: f32,
+        time_constant: f32,
+        rate_index: f32,
+    },
+    /// Bingham plastic, which barely moves until the stress passes
+    /// `yield_stress`, then flows with `plastic_viscosity`. The yield stress
+    /// is per unit density, and the unyielded fluid takes the capped
+    /// viscosity.
+    Bingham {
+        plastic_viscosity: f32,
+        yield_stress: f32,
+    },
+}
+
+impl ViscosityModel {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match *self {
+            ViscosityModel::Diffusion => Ok(()),
+            ViscosityModel::Xsph { epsilon } => {
+                if (0.0..=1.0).contains(&epsilon) {
+                    Ok(())
+                } else {
+                    Err(ConfigError::InvalidViscosityModel(
+                        "epsilon must be between 0 and 1",
+                    ))
+                }
+            }
+            ViscosityModel::Artificial { alpha, beta } => {
+                if !alpha.is_finite() || !beta.is_finite() {
+                    return Err(ConfigError::InvalidViscosityModel(
+                        "alpha and beta must be finite",
+                    ));
+                }
+                if alpha < 0.0 || beta < 0.0 {
+                    return Err(ConfigError::InvalidViscosityModel(
+                        "alpha and beta must not be negative",
+                    ));
+                }
+
+                Ok(())
+            }
+            ViscosityModel::Laplacian {
+                kinematic_viscosity,
+            } => {
+                if kinematic_viscosity.is_finite() && kinematic_viscosity >= 0.0 {
+                    Ok(())
+                } else {
+                    Err(ConfigError::InvalidViscosityModel(
+                        "kinematic viscosity must be finite and not negative",
+                    ))
+                }
+            }
+            ViscosityModel::PowerLaw {
+                consistency,
+                flow_index,
+            } => {
+                if !consistency.is_finite() || consistency < 0.0 {
+                    return Err(ConfigError::InvalidViscosityModel(
+                        "consistency must be finite and not negative",
+                    ));
+                }
+                if !flow_index.is_finite() || flow_index <= 0.0 {
+                    return Err(ConfigError::InvalidViscosityModel(
+                        "flow index must be finite and greater than 0",
+                    ));
+                }
+
+                Ok(())
+            }
+            ViscosityModel::Cross {
+                zero_shear_viscosity,
+                infinite_shear_viscosity,
+                time_constant,
+                rate_index,
+            } => {
+                let values = [
+                    zero_shear_viscosity,
+                    infinite_shear_viscosity,
+                    time_constant,
+                    rate_index,
+                ];
+                if values
+                    .iter()
+                    .any(|value| !value.is_finite() || *value < 0.0)
+                {
+                    return Err(ConfigError::InvalidViscosityModel(
+                        "Cross parameters must be finite and not negative",
+                    ));
+                }
+
+                Ok(())
+            }
+            ViscosityModel::Bingham {
+                plastic_viscosity,
+                yield_stress,
+            } => {
+                let values = [plastic_viscosity, yield_stress];
+                if values
+                    .iter()
+                    .any(|value| !value.is_finite() || *value < 0.0)
+                {
+                    return Err(ConfigError::InvalidViscosityModel(
+                        "plastic viscosity and yield stress must be finite and not negative",
+                    ));
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the viscous pass needs the strain rate of each particle.
+    pub fn needs_strain_rate(&self) -> bool {
+        matches!(
+            self,
+            ViscosityModel::PowerLaw { .. }
+                | ViscosityModel::Cross { .. }
+                | ViscosityModel::Bingham { .. }
+        )
+    }
+
+    /// Kinematic viscosity of the Laplacian and non-Newtonian models at
+    /// `strain_rate`, capped at `max_viscosity`. 0 for the other models.
+    pub fn kinematic_viscosity(&self, strain_rate: f32, max_viscosity: f32) -> f32 {
+        let strain_rate = strain_rate.max(MIN_STRAIN_RATE);
+        // Powers through libm, so deterministic runs match between native
+        // and wasm builds
+        let viscosity = match *self {
+            ViscosityModel::Diffusion
+            | ViscosityModel::Xsph { .. }
+            | ViscosityModel::Artificial { .. } => 0.0,
+            ViscosityModel::Laplacian {
+                kinematic_viscosity,
+            } => kinematic_viscosity,
+            ViscosityModel::PowerLaw {
+                consistency,
+                flow_index,
+            } => consistency * libm::powf(strain_rate, flow_index - 1.0),
+            ViscosityModel::Cross {
+                zero_shear_viscosity,
+                infinite_shear_viscosity,
+                time_constant,
+                rate_index,
+            } => {
+                infinite_shear_viscosity
+                    + (zero_shear_viscosity - infinite_shear_viscosity)
+                        / (1.0 + libm::powf(time_constant * strain_rate, rate_index))
+            }
+            ViscosityModel::Bingham {
+                plastic_viscosity,
+                yield_stress,
+            } => plastic_viscosity + yield_stress / strain_rate,
+        };
+
+        viscosity.min(max_viscosity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Sim2D, config::SimConfig, layout::InitialLayout, vector_2d::Vector2D};
+
+    // Weightless block of fluid sheared left to right, away from the walls
+    fn sheared_block(viscosity_model: ViscosityModel) -> Sim2D {
+        let mut sim = Sim2D::new(
+            SimConfig::builder()
+                .particle_count(400)
+                .world_dimensions(6.0, 6.0)
+                .gravity(0.0)
+                .viscosity_strength(0.0)
+                .viscosity_model(viscosity_model)
+                .layout(InitialLayout::Mask {
+                    width: 3,
+                    height: 3,
+                    data: vec![0, 0, 0, 0, 255, 0, 0, 0, 0],
+                })
+                .build()
+                .unwrap(),
+        );
+        let centre_y = sim.positions().iter().map(|p| p.y).sum::<f32>() / 400.0;
+        for i in 0..sim.particle_count() {
+            sim.velocities[i] = Vector2D::new(sim.positions[i].y - centre_y, 0.0);
+        }
+        sim
+    }
+
+    // Spread of the horizontal velocities about their mean
+    fn shear(sim: &Sim2D) -> f32 {
+        let mean = sim.velocities().iter().map(|v| v.x).sum::<f32>() / 400.0;
+        sim.velocities()
+            .iter()
+            .map(|v| (v.x - mean) * (v.x - mean))
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    #[test]
+    fn every_model_damps_shear_and_keeps_momentum() {
+        let run = |viscosity_model: ViscosityModel| {
+            let mut sim = sheared_block(viscosity_model);
+            for _ in 0..30 {
+                sim.step(1.0 / 60.0);
+            }
+            let momentum = sim
+                .velocities()
+                .iter()
+                .fold(Vector2D::new(0.0, 0.0), |sum, v| sum + *v);
+            (shear(&sim), momentum)
+        };
+
+        let (inviscid, _) = run(ViscosityModel::Diffusion);
+        for model in [
+            ViscosityModel::Xsph { epsilon: 0.5 },
+            ViscosityModel::Artificial {
+                alpha: 0.5,
+                beta: 0.0,
+            },
+            ViscosityModel::Laplacian {
+                kinematic_viscosity: 0.5,
+            },
+            ViscosityModel::PowerLaw {
+                consistency: 0.5,
+                flow_index: 0.5,
+            },
+            ViscosityModel::Cross {
+                zero_shear_viscosity: 1.0,
+                infinite_shear_viscosity: 0.1,
+                time_constant: 1.0,
+                rate_index: 1.0,
+            },
+            ViscosityModel::Bingham {
+                plastic_viscosity: 0.1,
+                yield_stress: 0.5,
+            },
+        ] {
+            let (sheared, momentum) = run(model);
+            assert!(
+                sheared < 0.8 * inviscid,
+                "{model:?}: {sheared} >= {inviscid}"
+            );
+            assert!(momentum.magnitude() < 0.05, "{model:?}: {momentum:?}");
+        }
+    }
+
+    #[test]
+    fn strain_rate_matches_simple_shear() {
+        let mut sim = sheared_block(ViscosityModel::Diffusion);
+        sim.update_strain_rates();
+
+        // Away from the surface, where the neighbourhood is full
+        let centre = sim
+            .positions()
+            .iter()
+            .fold(Vector2D::new(0.0, 0.0), |sum, p| sum + *p)
+            / 400.0;
+        let interior: Vec<f32> = sim
+            .positions()
+            .iter()
+            .zip(&sim.strain_rates)
+            .filter(|(p, _)| (**p - centre).magnitude() < 0.5)
+            .map(|(_, rate)| *rate)
+            .collect();
+        let mean = interior.iter().sum::<f32>() / interior.len() as f32;
+        assert!((mean - 1.0).abs() < 0.2, "mean strain rate {mean}");
+    }
+
+    #[test]
+    fn non_newtonian_viscosity_follows_shear_rate() {
+        let thinning = ViscosityModel::PowerLaw {
+            consistency: 1.0,
+            flow_index: 0.5,
+        };
+        let thickening = ViscosityModel::PowerLaw {
+            consistency: 1.0,
+            flow_index: 1.5,
+        };
+        let cross = ViscosityModel::Cross {
+            zero_shear_viscosity: 2.0,
+            infinite_shear_viscosity: 0.5,
+            time_constant: 1.0,
+            rate_index: 2.0,
+        };
+        let bingham = ViscosityModel::Bingham {
+            plastic_viscosity: 0.5,
+            yield_stress: 1.0,
+        };
+        let viscosity = |model: ViscosityModel, rate: f32| model.kinematic_viscosity(rate, 100.0);
+
+        assert!(viscosity(thinning, 4.0) < viscosity(thinning, 1.0));
+        assert!(viscosity(thickening, 4.0) > viscosity(thickening, 1.0));
+        assert!((viscosity(cross, 0.0) - 2.0).abs() < 1e-3);
+        assert!((viscosity(cross, 1000.0) - 0.5).abs() < 1e-3);
+        // Unyielded fluid at rest is held at the cap
+        assert_eq!(viscosity(bingham, 0.0), 100.0);
+        assert!((viscosity(bingham, 1000.0) - 0.5).abs() < 1e-2);
+    }
+
+    #[test]
+    fn models_reject_invalid_parameters() {
+        assert!(ViscosityModel::Xsph { epsilon: 1.5 }.validate().is_err());
+        assert!(
+            ViscosityModel::Artificial {
+                alpha: -1.0,
+                beta: 0.0,
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            ViscosityModel::Laplacian {
+                kinematic_viscosity: f32::NAN,
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            ViscosityModel::PowerLaw {
+                consistency: 1.0,
+                flow_index: 0.0,
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            ViscosityModel::Bingham {
+                plastic_viscosity: 0.1,
+                yield_stress: -1.0,
+            }
+            .validate()
+            .is_err()
+        );
+        assert_eq!(ViscosityModel::Diffusion.validate(), Ok(()));
+    }
+}
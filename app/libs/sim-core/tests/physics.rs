@@ -0,0 +1,264 @@
+//! Checks the solver against physical invariants, running whole scenes
+//! headless through the public `Sim2D` API.
+
+use sim_core::{
+    Sim2D,
+    config::SimConfig,
+    layout::InitialLayout,
+    obstacles::{Obstacle, Shape},
+    solver::{SolverKind, Substepping},
+    vector_2d::Vector2D,
+};
+
+const DT: f32 = 1.0 / 60.0;
+
+// Least squares line through `points`, as (slope, intercept, r squared)
+fn fit_line(points: &[(f32, f32)]) -> (f32, f32, f32) {
+    let n = points.len() as f32;
+    let mean_x = points.iter().map(|p| p.0).sum::<f32>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f32>() / n;
+
+    let mut sxx = 0.0;
+    let mut sxy = 0.0;
+    let mut syy = 0.0;
+    for &(x, y) in points {
+        sxx += (x - mean_x) * (x - mean_x);
+        sxy += (x - mean_x) * (y - mean_y);
+        syy += (y - mean_y) * (y - mean_y);
+    }
+
+    let slope = sxy / sxx;
+    (slope, mean_y - slope * mean_x, sxy * sxy / (sxx * syy))
+}
+
+#[test]
+fn hydrostatic_column_settles_to_linear_pressure() {
+    let config = SimConfig {
+        // About the target density over the bottom half of the box
+        particle_count: 600,
+        world_width: 4.0,
+        world_height: 4.0,
+        layout: InitialLayout::DamBreak {
+            width: 1.0,
+            height: 0.5,
+        },
+        ..SimConfig::default()
+    };
+    let floor = -config.world_height / 2.0;
+    let mut sim = Sim2D::new(config);
+    for _ in 0..600 {
+        sim.step(DT);
+    }
+
+    // Settled, apart from the jitter of particles at the surface
+    let speeds: Vec<f32> = sim.velocities().iter().map(|v| v.magnitude()).collect();
+    let rms_speed = (speeds.iter().map(|s| s * s).sum::<f32>() / speeds.len() as f32).sqrt();
+    assert!(rms_speed < 0.5, "rms speed {rms_speed}");
+
+    // Mean pressure in layers by height, skipping the layers against the
+    // floor and near the free surface, where particles lack neighbours
+    let surface = sim.positions().iter().map(|p| p.y).fold(f32::MIN, f32::max);
+    let layer = 0.1;
+    let layers = ((surface - floor) / layer) as usize;
+    let mut sums = vec![(0.0, 0); layers];
+    for (position, pressure) in sim.positions().iter().zip(sim.pressures()) {
+        let index = ((position.y - floor) / layer) as usize;
+        if let Some((sum, count)) = sums.get_mut(index) {
+            *sum += pressure;
+            *count += 1;
+        }
+    }
+    let profile: Vec<(f32, f32)> = sums
+        .iter()
+        .enumerate()
+        .take(layers - 4)
+        .skip(2)
+        .map(|(index, &(sum, count))| (index as f32 * layer, sum / count as f32))
+        .collect();
+
+    let (slope, _, r_squared) = fit_line(&profile);
+    assert!(slope < 0.0, "pressure should fall with height: {profile:?}");
+    assert!(r_squared > 0.95, "r squared {r_squared}: {profile:?}");
+}
+
+#[test]
+fn momentum_is_conserved_without_gravity_or_walls() {
+    // An overpacked droplet bursts outwards, stopping well short of the walls
+    let config = SimConfig {
+        particle_count: 1500,
+        world_width: 20.0,
+        world_height: 20.0,
+        gravity: 0.0,
+        layout: InitialLayout::Droplet {
+            x: 0.0,
+            y: 0.0,
+            radius: 0.1,
+            pool_depth: 0.0,
+        },
+        ..SimConfig::default()
+    };
+    let mut sim = Sim2D::new(config);
+
+    for _ in 0..45 {
+        sim.step(DT);
+
+        // Particles have unit mass
+        let momentum = sim
+            .velocities()
+            .iter()
+            .fold(Vector2D::new(0.0, 0.0), |sum, v| sum + *v);
+        let total_speed: f32 = sim.velocities().iter().map(|v| v.magnitude()).sum();
+        assert!(
+            momentum.magnitude() <= 0.01 * total_speed,
+            "momentum {momentum:?} against total speed {total_speed}"
+        );
+    }
+
+    let extent = sim
+        .positions()
+        .iter()
+        .map(|p| p.x.abs().max(p.y.abs()))
+        .fold(0.0, f32::max);
+    assert!(extent < 9.0, "droplet reached the walls");
+}
+
+#[test]
+fn particles_never_leave_the_world_box() {
+    // In the path of the dam break
+    let obstacle = Obstacle {
+        id: 0,
+        shape: Shape::Circle {
+            centre: Vector2D::new(0.5, -1.0),
+            radius: 0.5,
+        },
+        restitution: 0.2,
+        friction: 0.1,
+    };
+
+    for solver in [
+        SolverKind::Explicit,
+        SolverKind::PositionBased { iterations: 3 },
+    ] {
+        let config = SimConfig {
+            particle_count: 800,
+            world_width: 6.0,
+            world_height: 4.0,
+            gravity: -40.0,
+            solver,
+            substepping: Substepping::Adaptive {
+                velocity_cfl: 0.4,
+                force_cfl: 0.25,
+                max_substeps: 8,
+            },
+            layout: InitialLayout::DamBreak {
+                width: 0.5,
+                height: 1.0,
+            },
+            obstacles: vec![obstacle.clone()],
+            ..SimConfig::default()
+        };
+        let mut world_dimensions = Vector2D::new(config.world_width, config.world_height);
+        let mut sim = Sim2D::new(config);
+
+        for frame in 0..240 {
+            // Shake the box with the pointer and gravity, then squeeze it
+            match frame {
+                60 => sim.enable_push_interaction(-2.0, -1.5),
+                90 => sim.enable_pull_interaction(2.0, 1.0),
+                120 => {
+                    sim.disable_interaction();
+                    sim.set_gravity(40.0);
+                }
+                180 => {
+                    world_dimensions = Vector2D::new(5.0, 3.5);
+                    sim.set_world_dimensions(world_dimensions.x, world_dimensions.y)
+                        .unwrap();
+                }
+                _ => {}
+            }
+            sim.step(DT);
+
+            let half = world_dimensions * 0.5;
+            for position in sim.positions() {
+                assert!(
+                    position.x.abs() <= half.x && position.y.abs() <= half.y,
+                    "{solver:?}: {position:?} outside the box at frame {frame}"
+                );
+                let (distance, _) = obstacle.shape.signed_distance(*position);
+                assert!(
+                    distance > 0.0,
+                    "{solver:?}: {position:?} inside the obstacle at frame {frame}"
+                );
+            }
+        }
+    }
+}
+
+// Leading edge of a collapsing square column, from Martin and Moyce (1952),
+// as (t * sqrt(2g / a), front / a) for a column of width a
+const DAM_BREAK_FRONT: [(f32, f32); 8] = [
+    (0.41, 1.11),
+    (0.84, 1.22),
+    (1.19, 1.44),
+    (1.43, 1.67),
+    (1.63, 1.89),
+    (1.83, 2.11),
+    (1.98, 2.33),
+    (2.20, 2.56),
+];
+
+fn reference_front(t: f32) -> f32 {
+    let upper = DAM_BREAK_FRONT
+        .iter()
+        .position(|&(time, _)| time >= t)
+        .unwrap_or(DAM_BREAK_FRONT.len() - 1)
+        .max(1);
+    let (t0, z0) = DAM_BREAK_FRONT[upper - 1];
+    let (t1, z1) = DAM_BREAK_FRONT[upper];
+    z0 + (z1 - z0) * (t - t0) / (t1 - t0)
+}
+
+#[test]
+fn dam_break_front_follows_reference() {
+    // A 2 by 2 column at about the target density
+    let config = SimConfig {
+        particle_count: 300,
+        world_width: 8.0,
+        world_height: 4.0,
+        layout: InitialLayout::DamBreak {
+            width: 0.25,
+            height: 0.5,
+        },
+        ..SimConfig::default()
+    };
+    let left_wall = -config.world_width / 2.0;
+    let gravity = config.gravity.abs();
+    let mut sim = Sim2D::new(config);
+
+    let front = |sim: &Sim2D| {
+        sim.positions()
+            .iter()
+            .map(|p| p.x - left_wall)
+            .fold(0.0, f32::max)
+    };
+    let width = front(&sim);
+    let time_scale = (2.0 * gravity / width).sqrt();
+
+    let mut checked = 0;
+    while sim.time() * time_scale < DAM_BREAK_FRONT[DAM_BREAK_FRONT.len() - 1].0 {
+        sim.step(DT / 2.0);
+
+        let t = sim.time() * time_scale;
+        if t < DAM_BREAK_FRONT[0].0 {
+            continue;
+        }
+        let expected = reference_front(t);
+        let actual = front(&sim) / width;
+        assert!(
+            (actual - expected).abs() <= 0.2 * expected,
+            "front at {actual} instead of {expected} when T = {t}"
+        );
+        checked += 1;
+    }
+    assert!(checked > 10);
+}
@@ -1,4 +1,18 @@
-use sim_core::Sim2D;
+use sim_core::{
+    Sim2D,
+    config::SimConfig,
+    dye::DYE_CHANNELS,
+    flow::{Emitter, Sink},
+    kinematics::KinematicBody,
+    layout::InitialLayout,
+    obstacles::Obstacle,
+    rigid_bodies::RigidBody,
+    sdf::Boundary,
+    solver::{SolverKind, Substepping},
+    spatial::SpatialIndexKind,
+    thermal::HeatSource,
+    viscosity::ViscosityModel,
+};
 
 pub use wasm_bindgen_rayon::init_thread_pool;
 
@@ -15,44 +29,49 @@ pub struct WasmSim2D(Sim2D);
 
 #[wasm_bindgen]
 impl WasmSim2D {
+    /// Takes a `SimConfig` object (camelCase keys, missing keys use the
+    /// defaults) and throws if any parameter is invalid.
     #[wasm_bindgen(constructor)]
-    pub fn new(
-        particle_count: usize,
-        particle_radius: f32,
-        world_width: f32,
-        world_height: f32,
-        gravity: f32,
-        target_density: f32,
-        pressure_multiplier: f32,
-        viscosity_strength: f32,
-        smoothing_radius: f32,
-        interaction_strength: f32,
-        interaction_radius: f32,
-    ) -> Self {
-        WasmSim2D(Sim2D::new(
-            particle_count,
-            particle_radius,
-            world_width,
-            world_height,
-            gravity,
-            target_density,
-            pressure_multiplier,
-            viscosity_strength,
-            smoothing_radius,
-            interaction_strength,
-            interaction_radius,
-        ))
+    pub fn new(config: JsValue) -> Result<WasmSim2D, JsError> {
+        let config: SimConfig = serde_wasm_bindgen::from_value(config)?;
+        config.validate()?;
+
+        Ok(WasmSim2D(Sim2D::new(config)))
+    }
+
+    pub fn get_config(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.config())?)
+    }
+
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<WasmSim2D, JsError> {
+        Ok(WasmSim2D(Sim2D::from_snapshot(snapshot)?))
     }
 
     pub fn reset_sim(&mut self) {
         self.0.reset_sim();
     }
 
+    /* Snapshots */
+    pub fn snapshot(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.0.snapshot()?)
+    }
+
+    /// Buffer pointers change after a restore and must be fetched again.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), JsError> {
+        Ok(self.0.restore(snapshot)?)
+    }
+
     pub fn step(&mut self, dt: f32) {
         self.0.step(dt);
     }
 
     /* Pointers */
+    /// One `u32` per particle, which follows it as the particles are
+    /// reordered between steps.
+    pub fn get_particle_ids_ptr(&self) -> *const u8 {
+        self.0.get_particle_ids_ptr()
+    }
+
     pub fn get_positions_ptr(&self) -> *const u8 {
         self.0.get_positions_ptr()
     }
@@ -61,9 +80,127 @@ impl WasmSim2D {
         self.0.get_velocity_magnitudes_ptr()
     }
 
+    /// One `f32` per particle, positive for anticlockwise swirls.
+    pub fn get_vorticities_ptr(&self) -> *const u8 {
+        self.0.get_vorticities_ptr()
+    }
+
+    /// One `f32` per particle.
+    pub fn get_temperatures_ptr(&self) -> *const u8 {
+        self.0.get_temperatures_ptr()
+    }
+
+    /// `get_dye_channels` `f32` concentrations per particle, interleaved.
+    pub fn get_dyes_ptr(&self) -> *const u8 {
+        self.0.get_dyes_ptr()
+    }
+
+    pub fn get_dye_channels(&self) -> usize {
+        DYE_CHANNELS
+    }
+
+    /// One `u8` per particle, indexing the base fluid (0) then `get_phases`.
+    pub fn get_particle_phases_ptr(&self) -> *const u8 {
+        self.0.get_particle_phases_ptr()
+    }
+
+    pub fn get_phases(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(self.0.phases())?)
+    }
+
+    /// Number of live particles behind the particle pointers. The pointers
+    /// stay put as this changes, up to `get_max_particle_count`.
+    pub fn get_particle_count(&self) -> usize {
+        self.0.particle_count()
+    }
+
+    pub fn get_max_particle_count(&self) -> usize {
+        self.0.max_particle_count()
+    }
+
+    /// Six `f32`s per body: `x, y, rotation, vx, vy, angularVelocity`.
+    pub fn get_rigid_body_states_ptr(&self) -> *const u8 {
+        self.0.get_rigid_body_states_ptr()
+    }
+
+    pub fn get_rigid_body_count(&self) -> usize {
+        self.0.rigid_bodies().len()
+    }
+
+    /* Solver */
+    pub fn get_solver(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.solver())?)
+    }
+
+    /// Takes a `SolverKind` object, used from the next step on.
+    pub fn set_solver(&mut self, solver: JsValue) -> Result<(), JsError> {
+        let solver: SolverKind = serde_wasm_bindgen::from_value(solver)?;
+        solver.validate()?;
+
+        self.0.set_solver(solver);
+        Ok(())
+    }
+
+    pub fn get_substepping(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.substepping())?)
+    }
+
+    /// Takes a `Substepping` object, used from the next step on.
+    pub fn set_substepping(&mut self, substepping: JsValue) -> Result<(), JsError> {
+        let substepping: Substepping = serde_wasm_bindgen::from_value(substepping)?;
+        substepping.validate()?;
+
+        self.0.set_substepping(substepping);
+        Ok(())
+    }
+
+    /// Substeps the last `step` was split into.
+    pub fn get_substeps(&self) -> u32 {
+        self.0.substeps()
+    }
+
+    pub fn get_spatial_index(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.spatial_index())?)
+    }
+
+    /// Takes a `SpatialIndexKind` object, used from the next step on. Throws
+    /// if a flat grid over the world would be too big.
+    pub fn set_spatial_index(&mut self, spatial_index: JsValue) -> Result<(), JsError> {
+        let spatial_index: SpatialIndexKind = serde_wasm_bindgen::from_value(spatial_index)?;
+        Ok(self.0.set_spatial_index(spatial_index)?)
+    }
+
+    pub fn get_deterministic(&self) -> bool {
+        self.0.deterministic()
+    }
+
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.0.set_deterministic(deterministic);
+    }
+
+    /// Particle state checksum as 16 hex digits, matching the server's step
+    /// acknowledgements.
+    pub fn get_checksum(&self) -> String {
+        format!("{:016x}", self.0.checksum())
+    }
+
     /* Parameters */
-    pub fn set_world_dimensions(&mut self, world_width: f32, world_height: f32) {
-        self.0.set_world_dimensions(world_width, world_height);
+    /// Takes an `InitialLayout` object, applied on the next `reset_sim`.
+    /// Throws for a layout a config would reject.
+    pub fn set_layout(&mut self, layout: JsValue) -> Result<(), JsError> {
+        let layout: InitialLayout = serde_wasm_bindgen::from_value(layout)?;
+
+        Ok(self.0.set_layout(layout)?)
+    }
+
+    /// Throws, keeping the current world, for dimensions a config would
+    /// reject.
+    pub fn set_world_dimensions(
+        &mut self,
+        world_width: f32,
+        world_height: f32,
+    ) -> Result<(), JsError> {
+        Ok(self.0.set_world_dimensions(world_width, world_height)?)
     }
 
     pub fn set_gravity(&mut self, gravity: f32) {
@@ -82,8 +219,63 @@ impl WasmSim2D {
         self.0.set_viscosity_strength(viscosity_strength);
     }
 
-    pub fn set_smoothing_radius(&mut self, smoothing_radius: f32) {
-        self.0.set_smoothing_radius(smoothing_radius);
+    pub fn get_viscosity_model(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.viscosity_model())?)
+    }
+
+    /// Takes a `ViscosityModel` object, used from the next step on.
+    pub fn set_viscosity_model(&mut self, viscosity_model: JsValue) -> Result<(), JsError> {
+        let viscosity_model: ViscosityModel = serde_wasm_bindgen::from_value(viscosity_model)?;
+        viscosity_model.validate()?;
+
+        self.0.set_viscosity_model(viscosity_model);
+        Ok(())
+    }
+
+    /// Throws, keeping the current strength, for a strength a config would
+    /// reject.
+    pub fn set_surface_tension(&mut self, surface_tension: f32) -> Result<(), JsError> {
+        Ok(self.0.set_surface_tension(surface_tension)?)
+    }
+
+    /// Throws, keeping the current strength, for a strength a config would
+    /// reject.
+    pub fn set_vorticity_confinement(&mut self, vorticity_confinement: f32) -> Result<(), JsError> {
+        Ok(self.0.set_vorticity_confinement(vorticity_confinement)?)
+    }
+
+    /// Throws, keeping the current diffusivity, for a diffusivity a config would
+    /// reject.
+    pub fn set_thermal_diffusivity(&mut self, thermal_diffusivity: f32) -> Result<(), JsError> {
+        Ok(self.0.set_thermal_diffusivity(thermal_diffusivity)?)
+    }
+
+    /// Throws, keeping the current expansion, for an expansion a config
+    /// would reject.
+    pub fn set_thermal_expansion(&mut self, thermal_expansion: f32) -> Result<(), JsError> {
+        Ok(self.0.set_thermal_expansion(thermal_expansion)?)
+    }
+
+    /// Throws, keeping the current tension, for a tension a config would
+    /// reject.
+    pub fn set_interface_tension(&mut self, interface_tension: f32) -> Result<(), JsError> {
+        Ok(self.0.set_interface_tension(interface_tension)?)
+    }
+
+    /// Throws, keeping the current radius, for a radius a config would
+    /// reject.
+    pub fn set_smoothing_radius(&mut self, smoothing_radius: f32) -> Result<(), JsError> {
+        Ok(self.0.set_smoothing_radius(smoothing_radius)?)
+    }
+
+    /// Throws, keeping the current diffusivity, for a diffusivity a config would
+    /// reject.
+    pub fn set_dye_diffusivity(&mut self, dye_diffusivity: f32) -> Result<(), JsError> {
+        Ok(self.0.set_dye_diffusivity(dye_diffusivity)?)
+    }
+
+    pub fn clear_dyes(&mut self) {
+        self.0.clear_dyes();
     }
 
     pub fn set_interaction_strength(&mut self, interaction_strength: f32) {
@@ -94,6 +286,157 @@ impl WasmSim2D {
         self.0.set_interaction_radius(interaction_radius);
     }
 
+    /* Obstacles */
+    pub fn get_obstacles(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(self.0.obstacles())?)
+    }
+
+    /// Takes an `Obstacle` object and returns the id assigned to it.
+    pub fn add_obstacle(&mut self, obstacle: JsValue) -> Result<u32, JsError> {
+        let obstacle: Obstacle = serde_wasm_bindgen::from_value(obstacle)?;
+        obstacle.validate()?;
+
+        Ok(self.0.add_obstacle(obstacle))
+    }
+
+    pub fn remove_obstacle(&mut self, id: u32) -> bool {
+        self.0.remove_obstacle(id)
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.0.clear_obstacles();
+    }
+
+    /* Kinematic bodies */
+    pub fn get_time(&self) -> f32 {
+        self.0.time()
+    }
+
+    pub fn get_kinematic_bodies(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(self.0.kinematic_bodies())?)
+    }
+
+    /// Current `{ position, rotation, velocity, angularVelocity }` of each
+    /// kinematic body, in the same order as `get_kinematic_bodies`.
+    pub fn get_kinematic_body_states(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(
+            &self.0.kinematic_body_states(),
+        )?)
+    }
+
+    /// Takes a `KinematicBody` object and returns the id assigned to it.
+    pub fn add_kinematic_body(&mut self, body: JsValue) -> Result<u32, JsError> {
+        let body: KinematicBody = serde_wasm_bindgen::from_value(body)?;
+        body.validate()?;
+
+        Ok(self.0.add_kinematic_body(body))
+    }
+
+    pub fn remove_kinematic_body(&mut self, id: u32) -> bool {
+        self.0.remove_kinematic_body(id)
+    }
+
+    pub fn clear_kinematic_bodies(&mut self) {
+        self.0.clear_kinematic_bodies();
+    }
+
+    /* Rigid bodies */
+    pub fn get_rigid_bodies(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(self.0.rigid_bodies())?)
+    }
+
+    /// Takes a `RigidBody` object and returns the id assigned to it. The
+    /// rigid body state pointer must be fetched again afterwards.
+    pub fn add_rigid_body(&mut self, body: JsValue) -> Result<u32, JsError> {
+        let body: RigidBody = serde_wasm_bindgen::from_value(body)?;
+        body.validate()?;
+
+        Ok(self.0.add_rigid_body(body))
+    }
+
+    pub fn remove_rigid_body(&mut self, id: u32) -> bool {
+        self.0.remove_rigid_body(id)
+    }
+
+    pub fn clear_rigid_bodies(&mut self) {
+        self.0.clear_rigid_bodies();
+    }
+
+    /* Open flow */
+    pub fn get_emitters(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(self.0.emitters())?)
+    }
+
+    /// Takes an `Emitter` object and returns the id assigned to it. Throws
+    /// for an emitter a config would reject.
+    pub fn add_emitter(&mut self, emitter: JsValue) -> Result<u32, JsError> {
+        let emitter: Emitter = serde_wasm_bindgen::from_value(emitter)?;
+
+        Ok(self.0.add_emitter(emitter)?)
+    }
+
+    pub fn remove_emitter(&mut self, id: u32) -> bool {
+        self.0.remove_emitter(id)
+    }
+
+    pub fn clear_emitters(&mut self) {
+        self.0.clear_emitters();
+    }
+
+    pub fn get_sinks(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(self.0.sinks())?)
+    }
+
+    /// Takes a `Sink` object and returns the id assigned to it.
+    pub fn add_sink(&mut self, sink: JsValue) -> Result<u32, JsError> {
+        let sink: Sink = serde_wasm_bindgen::from_value(sink)?;
+        sink.validate()?;
+
+        Ok(self.0.add_sink(sink))
+    }
+
+    pub fn remove_sink(&mut self, id: u32) -> bool {
+        self.0.remove_sink(id)
+    }
+
+    pub fn clear_sinks(&mut self) {
+        self.0.clear_sinks();
+    }
+
+    /* Heat sources */
+    pub fn get_heat_sources(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(self.0.heat_sources())?)
+    }
+
+    /// Takes a `HeatSource` object and returns the id assigned to it.
+    pub fn add_heat_source(&mut self, source: JsValue) -> Result<u32, JsError> {
+        let source: HeatSource = serde_wasm_bindgen::from_value(source)?;
+        source.validate()?;
+
+        Ok(self.0.add_heat_source(source))
+    }
+
+    pub fn remove_heat_source(&mut self, id: u32) -> bool {
+        self.0.remove_heat_source(id)
+    }
+
+    pub fn clear_heat_sources(&mut self) {
+        self.0.clear_heat_sources();
+    }
+
+    /* Boundary */
+    pub fn get_boundary(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.boundary())?)
+    }
+
+    /// Takes a `Boundary` object, or `null` to go back to the plain box.
+    /// Throws for a boundary a config would reject.
+    pub fn set_boundary(&mut self, boundary: JsValue) -> Result<(), JsError> {
+        let boundary: Option<Boundary> = serde_wasm_bindgen::from_value(boundary)?;
+
+        Ok(self.0.set_boundary(boundary)?)
+    }
+
     /* Interaction */
     pub fn enable_pull_interaction(&mut self, x: f32, y: f32) {
         self.0.enable_pull_interaction(x, y);
@@ -106,4 +449,17 @@ impl WasmSim2D {
     pub fn disable_interaction(&mut self) {
         self.0.disable_interaction();
     }
+
+    pub fn enable_dye_interaction(
+        &mut self,
+        x: f32,
+        y: f32,
+        channel: usize,
+    ) -> Result<(), JsError> {
+        Ok(self.0.enable_dye_interaction(x, y, channel)?)
+    }
+
+    pub fn disable_dye_interaction(&mut self) {
+        self.0.disable_dye_interaction();
+    }
 }
@@ -1,24 +1,43 @@
 import json
+import math
 import os
 import sys
 
 import bmesh
 import bpy
 
+# Colour of each dye channel, mixed by concentration over a dark base
+DYE_COLOURS = [(0.9, 0.15, 0.1), (0.1, 0.75, 0.2), (0.1, 0.3, 0.95)]
+DYE_BASE = (0.05, 0.05, 0.05)
+
 
 def read_frame_json(json_path):
     with open(json_path, 'r') as f:
         data = json.load(f)
 
     positions = data['positions']
+    # Older frames name particles by index
+    ids = data.get('ids', list(range(len(positions))))
     velocity_magnitudes = data['velocity_magnitudes']
+    vorticities = data.get('vorticities', [])
+    temperatures = data.get('temperatures', [])
+    # Older frames have a single fluid
+    phases = data.get('phases', [0] * len(positions))
+    phase_colours = data.get('phase_colours', [])
+    phase_materials = data.get('phase_materials', [])
+    dyes = data.get('dyes', [])
+    obstacles = data.get('obstacles', [])
+    kinematic_bodies = data.get('kinematic_bodies', [])
+    rigid_bodies = data.get('rigid_bodies', [])
 
     # Flatten positions
     position_flat = []
     for pos in positions:
         position_flat.extend([pos['x'], pos['y']])
 
-    return tuple(position_flat), velocity_magnitudes
+    return (ids, tuple(position_flat), velocity_magnitudes, vorticities,
+            temperatures, phases, phase_colours, phase_materials, dyes,
+            obstacles, kinematic_bodies, rigid_bodies)
 
 
 def turbo_colormap(x):
@@ -117,10 +136,79 @@ def create_velocity_materials(num_materials=20):
     return materials
 
 
+def create_phase_materials(phase_colours, phase_materials):
+    # Phase 0 is the base fluid, which keeps the velocity colouring
+    materials = [None]
+    for i, colour in enumerate(phase_colours):
+        material = bpy.data.materials.new(name=f"PhaseMat_{i + 1:02d}")
+        material.use_nodes = True
+        material.node_tree.nodes.clear()
+
+        # Older frames only have fluids
+        kind = phase_materials[i]['type'] if i < len(phase_materials) else 'fluid'
+        if kind == 'granular':
+            # Matte, so sand and snow don't glow like the fluids
+            shader_node = material.node_tree.nodes.new(
+                type='ShaderNodeBsdfDiffuse')
+            shader_node.inputs[0].default_value = (*colour, 1.0)
+            shader_node.inputs[1].default_value = 1.0
+        else:
+            shader_node = material.node_tree.nodes.new(
+                type='ShaderNodeEmission')
+            shader_node.inputs[0].default_value = (*colour, 1.0)
+            shader_node.inputs[1].default_value = 1.5
+
+        output_node = material.node_tree.nodes.new(
+            type='ShaderNodeOutputMaterial')
+        material.node_tree.links.new(
+            shader_node.outputs[0], output_node.inputs[0])
+
+        materials.append(material)
+
+    return materials
+
+
+def create_dye_materials(levels=4):
+    # One material per mix of quantised channel concentrations, indexed by
+    # get_dye_material_index
+    materials = []
+    for i in range(levels ** len(DYE_COLOURS)):
+        concentrations = [(i // levels ** k) % levels / (levels - 1)
+                          for k in range(len(DYE_COLOURS))]
+        color = [min(1, base + sum(c * colour[j] for c, colour in
+                                    zip(concentrations, DYE_COLOURS)))
+                 for j, base in enumerate(DYE_BASE)]
+
+        material = bpy.data.materials.new(name=f"DyeMat_{i:02d}")
+        material.use_nodes = True
+        material.node_tree.nodes.clear()
+
+        emission_node = material.node_tree.nodes.new(type='ShaderNodeEmission')
+        emission_node.inputs[0].default_value = (*color, 1.0)
+        emission_node.inputs[1].default_value = 1.5
+
+        output_node = material.node_tree.nodes.new(
+            type='ShaderNodeOutputMaterial')
+        material.node_tree.links.new(
+            emission_node.outputs[0], output_node.inputs[0])
+
+        materials.append(material)
+
+    return materials
+
+
 def get_material_index(velocity_normalized, num_materials):
     return min(int(velocity_normalized * num_materials), num_materials - 1)
 
 
+def get_dye_material_index(dye, levels=4):
+    index = 0
+    for k, concentration in enumerate(dye[:len(DYE_COLOURS)]):
+        level = round(min(1, max(0, concentration)) * (levels - 1))
+        index += level * levels ** k
+    return index
+
+
 def create_particle_mesh(particle_radius):
     bm = bmesh.new()
     bmesh.ops.create_uvsphere(
@@ -138,6 +226,74 @@ def create_particle_mesh(particle_radius):
     return particle_template
 
 
+def obstacle_outline(shape, segments=32):
+    kind = shape['type']
+
+    if kind == 'circle':
+        cx, cy = shape['centre']['x'], shape['centre']['y']
+        r = shape['radius']
+        return [(cx + r * math.cos(2 * math.pi * i / segments),
+                 cy + r * math.sin(2 * math.pi * i / segments))
+                for i in range(segments)]
+
+    if kind == 'orientedBox':
+        cx, cy = shape['centre']['x'], shape['centre']['y']
+        hx, hy = shape['halfExtents']['x'], shape['halfExtents']['y']
+        c, s = math.cos(shape['rotation']), math.sin(shape['rotation'])
+        return [(cx + x * c - y * s, cy + x * s + y * c)
+                for x, y in ((-hx, -hy), (hx, -hy), (hx, hy), (-hx, hy))]
+
+    if kind == 'segment':
+        # Capsule around the segment
+        ax, ay = shape['start']['x'], shape['start']['y']
+        bx, by = shape['end']['x'], shape['end']['y']
+        r = max(shape['thickness'], 0.01)
+        angle = math.atan2(by - ay, bx - ax)
+        half = segments // 2
+        outline = []
+        for i in range(half + 1):
+            t = angle - math.pi / 2 + math.pi * i / half
+            outline.append((bx + r * math.cos(t), by + r * math.sin(t)))
+        for i in range(half + 1):
+            t = angle + math.pi / 2 + math.pi * i / half
+            outline.append((ax + r * math.cos(t), ay + r * math.sin(t)))
+        return outline
+
+    if kind == 'polygon':
+        return [(v['x'], v['y']) for v in shape['vertices']]
+
+    return []
+
+
+def create_obstacles(obstacles, name="Obstacle"):
+    material = bpy.data.materials.new(name=f"{name}Mat")
+    material.use_nodes = True
+    material.node_tree.nodes.clear()
+    emission_node = material.node_tree.nodes.new(type='ShaderNodeEmission')
+    emission_node.inputs[0].default_value = (0.6, 0.6, 0.6, 1.0)
+    emission_node.inputs[1].default_value = 1.0
+    output_node = material.node_tree.nodes.new(type='ShaderNodeOutputMaterial')
+    material.node_tree.links.new(
+        emission_node.outputs[0], output_node.inputs[0])
+
+    for obstacle in obstacles:
+        outline = obstacle_outline(obstacle['shape'])
+        if len(outline) < 3:
+            continue
+
+        bm = bmesh.new()
+        verts = [bm.verts.new((x, y, 0.0)) for x, y in outline]
+        bm.faces.new(verts)
+
+        mesh = bpy.data.meshes.new(f"{name}_{obstacle['id']}")
+        bm.to_mesh(mesh)
+        bm.free()
+        mesh.materials.append(material)
+
+        obj = bpy.data.objects.new(f"{name}_{obstacle['id']}", mesh)
+        bpy.context.collection.objects.link(obj)
+
+
 def setup_render_settings(output_directory, world_width, world_height):
     scene = bpy.context.scene
     scene.render.engine = 'BLENDER_EEVEE_NEXT'
@@ -152,16 +308,22 @@ def setup_render_settings(output_directory, world_width, world_height):
     scene.eevee.use_volumetric_shadows = False
 
 
-def render_single_frame(json_path, particle_count, world_width, world_height, particle_radius, output_directory):
+def render_single_frame(json_path, particle_count, world_width, world_height, particle_radius, output_directory, colour_by):
+    # Read single JSON frame
+    (ids, positions, velocities, vorticities, temperatures, phases,
+     phase_colours, material_types, dyes, obstacles, kinematic_bodies,
+     rigid_bodies) = read_frame_json(json_path)
+
     materials = create_velocity_materials(num_materials=20)
     particle_template = create_particle_mesh(particle_radius)
 
     particles = []
     for particle_index in range(particle_count):
-        # Duplicate template for each particle
+        # Duplicate template for each particle, named by its id so it can be
+        # followed from frame to frame
         new_particle = particle_template.copy()
         new_particle.data = particle_template.data.copy()
-        new_particle.name = f"Particle_{particle_index:04d}"
+        new_particle.name = f"Particle_{ids[particle_index]:04d}"
         bpy.context.collection.objects.link(new_particle)
 
         # Assign initial material
@@ -176,8 +338,21 @@ def render_single_frame(json_path, particle_count, world_width, world_height, pa
     min_velocity = 0.3
     velocity_range = 4.0
 
-    # Read single JSON frame
-    positions, velocities = read_frame_json(json_path)
+    phase_materials = create_phase_materials(phase_colours, material_types)
+    # Older frames carry no dye, vorticity or temperature, so keep the
+    # velocity colouring
+    dye_materials = create_dye_materials() if colour_by == 'dye' and dyes else None
+    by_vorticity = colour_by == 'vorticity' and vorticities
+    by_temperature = colour_by == 'temperature' and temperatures
+    # Vorticity is signed, so it maps symmetrically about the middle
+    max_vorticity = max([1.0] + [abs(v) for v in vorticities])
+    # Temperature maps from the coldest to the hottest particle
+    min_temperature = min(temperatures, default=0.0)
+    temperature_range = max(
+        1.0, max(temperatures, default=0.0) - min_temperature)
+    create_obstacles(obstacles)
+    create_obstacles(kinematic_bodies, name="KinematicBody")
+    create_obstacles(rigid_bodies, name="RigidBody")
 
     for particle_index in range(particle_count):
         particle = particles[particle_index]
@@ -186,15 +361,32 @@ def render_single_frame(json_path, particle_count, world_width, world_height, pa
         pos_y = positions[2 * particle_index + 1]
         particle.location = (pos_x, pos_y, 0.0)
 
-        velocity = velocities[particle_index]
-        velocity_normalized = (velocity - min_velocity) / velocity_range
-        material_index = get_material_index(
-            velocity_normalized, len(materials))
+        phase = phases[particle_index]
+        if 0 < phase < len(phase_materials):
+            material = phase_materials[phase]
+        elif dye_materials:
+            material = dye_materials[get_dye_material_index(
+                dyes[particle_index])]
+        elif by_vorticity:
+            vorticity_normalized = 0.5 + \
+                0.5 * vorticities[particle_index] / max_vorticity
+            material = materials[get_material_index(
+                vorticity_normalized, len(materials))]
+        elif by_temperature:
+            temperature_normalized = (
+                temperatures[particle_index] - min_temperature) / temperature_range
+            material = materials[get_material_index(
+                temperature_normalized, len(materials))]
+        else:
+            velocity = velocities[particle_index]
+            velocity_normalized = (velocity - min_velocity) / velocity_range
+            material = materials[get_material_index(
+                velocity_normalized, len(materials))]
 
         if particle.data.materials:
-            particle.data.materials[0] = materials[material_index]
+            particle.data.materials[0] = material
         else:
-            particle.data.materials.append(materials[material_index])
+            particle.data.materials.append(material)
 
     # Extract frame number from JSON
     frame_number = int(os.path.splitext(os.path.basename(json_path))[0])
@@ -215,6 +407,8 @@ def main():
     world_width = float(sys.argv[-3])
     world_height = float(sys.argv[-2])
     particle_radius = float(sys.argv[-1])
+    # 'velocity', 'vorticity', 'temperature' or 'dye', for the base fluid
+    colour_by = os.environ.get('RENDER_COLOUR_BY', 'velocity')
 
     os.makedirs(output_directory, exist_ok=True)
 
@@ -226,7 +420,7 @@ def main():
     setup_scene(world_width, world_height)
 
     render_single_frame(
-        json_path, particle_count, world_width, world_height, particle_radius, output_directory)
+        json_path, particle_count, world_width, world_height, particle_radius, output_directory, colour_by)
 
 
 if __name__ == "__main__":
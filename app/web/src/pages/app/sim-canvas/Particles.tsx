@@ -8,26 +8,68 @@ import {
 } from "../../../workers/simWorkerScript.ts";
 import { useSim } from "../../../contexts/Sim.ts";
 
+type ColourMode = "speed" | "vorticity" | "temperature" | "dye";
+
+// Colour of each dye channel, mixed by concentration over a dark base
+const DYE_COLOURS: [number, number, number][] = [
+  [0.9, 0.15, 0.1],
+  [0.1, 0.75, 0.2],
+  [0.1, 0.3, 0.95],
+];
+const DYE_BASE = 0.05;
+
 export default function Particles() {
   const { simWorker, simPaused, toggleSim, stepSim } = useSim();
   const refs = useRef<Map<number, THREE.Mesh>>(new Map());
+  const phaseColours = useRef<[number, number, number][]>([]);
+  const colourMode = useRef<ColourMode>("speed");
 
   const apply = useCallback(
     (
       memoryBuffer: ArrayBuffer,
       positionsPtr: number,
       velocityMagnitudesPtr: number,
+      vorticitiesPtr: number,
+      temperaturesPtr: number,
+      dyesPtr: number,
+      dyeChannels: number,
+      particleCount: number,
+      particlePhasesPtr: number,
     ) => {
       const positions = new Float32Array(
         memoryBuffer,
         positionsPtr,
-        PARTICLE_COUNT * 2,
+        particleCount * 2,
       );
 
       const velocityMagnitudes = new Float32Array(
         memoryBuffer,
         velocityMagnitudesPtr,
-        PARTICLE_COUNT,
+        particleCount,
+      );
+
+      const vorticities = new Float32Array(
+        memoryBuffer,
+        vorticitiesPtr,
+        particleCount,
+      );
+
+      const temperatures = new Float32Array(
+        memoryBuffer,
+        temperaturesPtr,
+        particleCount,
+      );
+
+      const dyes = new Float32Array(
+        memoryBuffer,
+        dyesPtr,
+        particleCount * dyeChannels,
+      );
+
+      const particlePhases = new Uint8Array(
+        memoryBuffer,
+        particlePhasesPtr,
+        particleCount,
       );
 
       // Calculate velocity range for color mapping
@@ -38,22 +80,67 @@ export default function Particles() {
       const minV = Math.min(0.1, minVel);
       const span = Math.max(1.0, maxVel - minV);
 
-      // Update meshes
+      // Vorticity is signed, so it maps symmetrically about the middle
+      let maxVorticity = 1.0;
+      if (colourMode.current === "vorticity") {
+        for (const vorticity of vorticities) {
+          maxVorticity = Math.max(maxVorticity, Math.abs(vorticity));
+        }
+      }
+
+      // Temperature maps from the coldest to the hottest particle
+      let minTemperature = 0.0;
+      let temperatureSpan = 1.0;
+      if (colourMode.current === "temperature") {
+        const maxTemperature = Math.max(...temperatures);
+        minTemperature = Math.min(...temperatures);
+        temperatureSpan = Math.max(1.0, maxTemperature - minTemperature);
+      }
+
+      // Update meshes, hiding those past the live particle count
       for (let i = 0; i < PARTICLE_COUNT; i++) {
         const mesh = refs.current.get(i);
         if (!mesh) continue;
 
+        mesh.visible = i < particleCount;
+        if (!mesh.visible) continue;
+
         // Position
         const x = positions[i * 2];
         const y = positions[i * 2 + 1];
         mesh.position.set(x, y, 0);
 
-        // Color
-        const p = velocityMagnitudes[i];
-        const t = (p - minV) / span;
-        const { r, g, b } = turboRGB(t);
+        // Color, by speed, vorticity, temperature or dye for the base fluid
+        // and by phase otherwise
         const mat = mesh.material as THREE.MeshStandardMaterial;
-        mat.color.setRGB(r, g, b);
+        const phase = particlePhases[i];
+        if (phase > 0 && phase <= phaseColours.current.length) {
+          const [r, g, b] = phaseColours.current[phase - 1];
+          mat.color.setRGB(r, g, b);
+        } else if (colourMode.current === "vorticity") {
+          const t = 0.5 + (0.5 * vorticities[i]) / maxVorticity;
+          const { r, g, b } = turboRGB(t);
+          mat.color.setRGB(r, g, b);
+        } else if (colourMode.current === "temperature") {
+          const t = (temperatures[i] - minTemperature) / temperatureSpan;
+          const { r, g, b } = turboRGB(t);
+          mat.color.setRGB(r, g, b);
+        } else if (colourMode.current === "dye") {
+          let [r, g, b] = [DYE_BASE, DYE_BASE, DYE_BASE];
+          const channels = Math.min(dyeChannels, DYE_COLOURS.length);
+          for (let c = 0; c < channels; c++) {
+            const concentration = dyes[i * dyeChannels + c];
+            r += concentration * DYE_COLOURS[c][0];
+            g += concentration * DYE_COLOURS[c][1];
+            b += concentration * DYE_COLOURS[c][2];
+          }
+          mat.color.setRGB(Math.min(r, 1), Math.min(g, 1), Math.min(b, 1));
+        } else {
+          const p = velocityMagnitudes[i];
+          const t = (p - minV) / span;
+          const { r, g, b } = turboRGB(t);
+          mat.color.setRGB(r, g, b);
+        }
       }
     },
     [],
@@ -69,6 +156,13 @@ export default function Particles() {
           memoryBuffer: ArrayBuffer;
           positionsPtr: number;
           velocityMagnitudesPtr: number;
+          vorticitiesPtr: number;
+          temperaturesPtr: number;
+          dyesPtr: number;
+          dyeChannels: number;
+          particleCount: number;
+          particlePhasesPtr: number;
+          colours: [number, number, number][];
         };
       }>,
     ) => {
@@ -79,7 +173,15 @@ export default function Particles() {
           memoryBuffer,
           event.data.payload.positionsPtr,
           event.data.payload.velocityMagnitudesPtr,
+          event.data.payload.vorticitiesPtr,
+          event.data.payload.temperaturesPtr,
+          event.data.payload.dyesPtr,
+          event.data.payload.dyeChannels,
+          event.data.payload.particleCount,
+          event.data.payload.particlePhasesPtr,
         );
+      } else if (type === "UPDATE_PHASES") {
+        phaseColours.current = event.data.payload.colours;
       } else if (type === "READY") {
         simWorker.postMessage({
           type: "INIT_SIM",
@@ -108,7 +210,9 @@ export default function Particles() {
     };
   }, [apply, simWorker]);
 
-  // Keyboard controls: Space = toggle pause, ArrowRight = single step (when paused)
+  // Keyboard controls: Space = toggle pause, ArrowRight = single step (when
+  // paused), V = colour by vorticity, T = colour by temperature and D = colour
+  // by dye instead of speed
   useEffect(() => {
     const onKeyDown = (e: KeyboardEvent) => {
       // Ignore shortcuts while typing
@@ -126,6 +230,14 @@ export default function Particles() {
       } else if (e.code === "ArrowRight") {
         e.preventDefault();
         stepSim();
+      } else if (e.code === "KeyV") {
+        colourMode.current =
+          colourMode.current === "vorticity" ? "speed" : "vorticity";
+      } else if (e.code === "KeyT") {
+        colourMode.current =
+          colourMode.current === "temperature" ? "speed" : "temperature";
+      } else if (e.code === "KeyD") {
+        colourMode.current = colourMode.current === "dye" ? "speed" : "dye";
       }
     };
 
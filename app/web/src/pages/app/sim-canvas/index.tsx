@@ -26,6 +26,9 @@ export default function SimCanvas() {
     isDown: false,
     button: null,
   });
+  // Shift + left drag paints dye in the selected channel instead of pulling
+  const painting = useRef(false);
+  const dyeChannel = useRef(0);
 
   const [isRecording, setIsRecording] = useState(false);
 
@@ -80,6 +83,42 @@ export default function SimCanvas() {
     });
   }, [simMetadata, simWorker]);
 
+  // Keyboard controls: 1-3 = select the dye channel to paint, C = clear dye
+  useEffect(() => {
+    const onKeyDown = (e: KeyboardEvent) => {
+      // Ignore shortcuts while typing
+      const active = document.activeElement as HTMLElement | null;
+      const typing =
+        active &&
+        (active.isContentEditable ||
+          /^(INPUT|TEXTAREA|SELECT)$/i.test(active.tagName));
+
+      if (typing) return;
+
+      if (/^Digit[1-3]$/.test(e.code)) {
+        dyeChannel.current = Number(e.code.slice(5)) - 1;
+      } else if (e.code === "KeyC") {
+        simWorker?.postMessage({ type: "CLEAR_DYES" });
+      }
+    };
+
+    window.addEventListener("keydown", onKeyDown);
+    return () => {
+      window.removeEventListener("keydown", onKeyDown);
+    };
+  }, [simWorker]);
+
+  const paint = () => {
+    simWorker?.postMessage({
+      type: "ENABLE_DYE_INTERACTION",
+      payload: {
+        x: pointer.current.x,
+        y: pointer.current.y,
+        channel: dyeChannel.current,
+      },
+    });
+  };
+
   const onPointerDown = (e: React.PointerEvent<HTMLDivElement>) => {
     if (!canvasRef.current || !simWorker) return;
 
@@ -89,6 +128,12 @@ export default function SimCanvas() {
 
     if (e.button !== 0 && e.button !== 2) return;
 
+    painting.current = e.button === 0 && e.shiftKey;
+    if (painting.current) {
+      paint();
+      return;
+    }
+
     // const click = e.button === 2 ? "Push" : "Pull";
     // console.log(`${click} force:`, pointer.current.x, pointer.current.y);
 
@@ -108,6 +153,11 @@ export default function SimCanvas() {
   const onPointerMove = () => {
     if (!pointer.current.isDown || !canvasRef.current || !simWorker) return;
 
+    if (painting.current) {
+      paint();
+      return;
+    }
+
     // const click = pointerState.current.button === 2 ? "Push" : "Pull";
     // console.log(`${click} force:`, pointer.current.x, pointer.current.y);
 
@@ -133,8 +183,11 @@ export default function SimCanvas() {
     // console.log("Stop force");
 
     simWorker.postMessage({
-      type: "DISABLE_INTERACTION",
+      type: painting.current
+        ? "DISABLE_DYE_INTERACTION"
+        : "DISABLE_INTERACTION",
     });
+    painting.current = false;
   };
 
   const startRecording = () => {
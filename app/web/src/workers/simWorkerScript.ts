@@ -1,4 +1,10 @@
 import init, { WasmSim2D as Sim2D } from "sim-wasm";
+import type {
+  Phase,
+  SolverKind,
+  Substepping,
+  ViscosityModel,
+} from "../ws/protocol.ts";
 // import { getSimSocket } from "../ws/SimSocket.ts";
 
 export const PARTICLE_COUNT = 5000;
@@ -50,6 +56,29 @@ export type WorkerMessageEvent = MessageEvent<
       type: "UPDATE_PARAMETER";
       payload: { parameter: "viscosityStrength"; viscosityStrength: number };
     }
+  | {
+      type: "UPDATE_PARAMETER";
+      payload: { parameter: "surfaceTension"; surfaceTension: number };
+    }
+  | {
+      type: "UPDATE_PARAMETER";
+      payload: {
+        parameter: "vorticityConfinement";
+        vorticityConfinement: number;
+      };
+    }
+  | {
+      type: "UPDATE_PARAMETER";
+      payload: { parameter: "thermalDiffusivity"; thermalDiffusivity: number };
+    }
+  | {
+      type: "UPDATE_PARAMETER";
+      payload: { parameter: "thermalExpansion"; thermalExpansion: number };
+    }
+  | {
+      type: "UPDATE_PARAMETER";
+      payload: { parameter: "dyeDiffusivity"; dyeDiffusivity: number };
+    }
   | {
       type: "UPDATE_PARAMETER";
       payload: {
@@ -65,9 +94,22 @@ export type WorkerMessageEvent = MessageEvent<
       type: "UPDATE_PARAMETER";
       payload: { parameter: "smoothingRadius"; smoothingRadius: number };
     }
+  | { type: "SET_SOLVER"; payload: { solver: SolverKind } }
+  | { type: "SET_SUBSTEPPING"; payload: { substepping: Substepping } }
+  | {
+      type: "SET_VISCOSITY_MODEL";
+      payload: { viscosityModel: ViscosityModel };
+    }
+  | { type: "SET_DETERMINISTIC"; payload: { deterministic: boolean } }
   | { type: "ENABLE_PUSH_INTERACTION"; payload: { x: number; y: number } }
   | { type: "ENABLE_PULL_INTERACTION"; payload: { x: number; y: number } }
   | { type: "DISABLE_INTERACTION" }
+  | {
+      type: "ENABLE_DYE_INTERACTION";
+      payload: { x: number; y: number; channel: number };
+    }
+  | { type: "DISABLE_DYE_INTERACTION" }
+  | { type: "CLEAR_DYES" }
 >;
 
 // WebSocket
@@ -126,9 +168,9 @@ onmessage = async (event: WorkerMessageEvent) => {
       const interactionStrength = event.data.payload.interactionStrength;
       const interactionRadius = event.data.payload.interactionRadius;
 
-      simWasm = new Sim2D(
-        PARTICLE_COUNT,
-        PARTICLE_RADIUS,
+      simWasm = new Sim2D({
+        particleCount: PARTICLE_COUNT,
+        particleRadius: PARTICLE_RADIUS,
         worldWidth,
         worldHeight,
         gravity,
@@ -138,7 +180,7 @@ onmessage = async (event: WorkerMessageEvent) => {
         smoothingRadius,
         interactionStrength,
         interactionRadius,
-      );
+      });
 
       // parameters = {
       //   worldWidth,
@@ -158,6 +200,22 @@ onmessage = async (event: WorkerMessageEvent) => {
           memoryBuffer: simMemory.buffer,
           positionsPtr: simWasm.get_positions_ptr(),
           velocityMagnitudesPtr: simWasm.get_velocity_magnitudes_ptr(),
+          vorticitiesPtr: simWasm.get_vorticities_ptr(),
+          temperaturesPtr: simWasm.get_temperatures_ptr(),
+          dyesPtr: simWasm.get_dyes_ptr(),
+          dyeChannels: simWasm.get_dye_channels(),
+          particleCount: simWasm.get_particle_count(),
+          particlePhasesPtr: simWasm.get_particle_phases_ptr(),
+        },
+      });
+
+      // Phase colours only change with the config, so they are sent once
+      self.postMessage({
+        type: "UPDATE_PHASES",
+        payload: {
+          colours: (simWasm.get_phases() as Phase[]).map(
+            (phase) => phase.colour,
+          ),
         },
       });
 
@@ -187,17 +245,7 @@ onmessage = async (event: WorkerMessageEvent) => {
 
       //     await simSocket.startRecording(
       //       event.data.payload.simulationId,
-      //       PARTICLE_COUNT,
-      //       PARTICLE_RADIUS,
-      //       parameters.worldWidth,
-      //       parameters.worldHeight,
-      //       parameters.gravity,
-      //       parameters.targetDensity,
-      //       parameters.pressureMultiplier,
-      //       parameters.viscosityStrength,
-      //       parameters.smoothingRadius,
-      //       parameters.interactionStrength,
-      //       parameters.interactionRadius,
+      //       simWasm.get_config(),
       //     );
       //   }
       // }
@@ -224,6 +272,13 @@ onmessage = async (event: WorkerMessageEvent) => {
               memoryBuffer: simMemory.buffer,
               positionsPtr: simWasm.get_positions_ptr(),
               velocityMagnitudesPtr: simWasm.get_velocity_magnitudes_ptr(),
+              vorticitiesPtr: simWasm.get_vorticities_ptr(),
+              temperaturesPtr: simWasm.get_temperatures_ptr(),
+              dyesPtr: simWasm.get_dyes_ptr(),
+              dyeChannels: simWasm.get_dye_channels(),
+              particleCount: simWasm.get_particle_count(),
+              particlePhasesPtr: simWasm.get_particle_phases_ptr(),
+              substeps: simWasm.get_substeps(),
             },
           });
         }
@@ -254,6 +309,13 @@ onmessage = async (event: WorkerMessageEvent) => {
             memoryBuffer: simMemory.buffer,
             positionsPtr: simWasm.get_positions_ptr(),
             velocityMagnitudesPtr: simWasm.get_velocity_magnitudes_ptr(),
+            vorticitiesPtr: simWasm.get_vorticities_ptr(),
+            temperaturesPtr: simWasm.get_temperatures_ptr(),
+            dyesPtr: simWasm.get_dyes_ptr(),
+            dyeChannels: simWasm.get_dye_channels(),
+            particleCount: simWasm.get_particle_count(),
+            particlePhasesPtr: simWasm.get_particle_phases_ptr(),
+            substeps: simWasm.get_substeps(),
           },
         });
 
@@ -309,6 +371,55 @@ onmessage = async (event: WorkerMessageEvent) => {
           //   );
           break;
         }
+        case "surfaceTension": {
+          simWasm?.set_surface_tension(event.data.payload.surfaceTension);
+          // if (recording)
+          //   await simSocket.setSurfaceTension(
+          //     frame,
+          //     event.data.payload.surfaceTension,
+          //   );
+          break;
+        }
+        case "vorticityConfinement": {
+          simWasm?.set_vorticity_confinement(
+            event.data.payload.vorticityConfinement,
+          );
+          // if (recording)
+          //   await simSocket.setVorticityConfinement(
+          //     frame,
+          //     event.data.payload.vorticityConfinement,
+          //   );
+          break;
+        }
+        case "thermalDiffusivity": {
+          simWasm?.set_thermal_diffusivity(
+            event.data.payload.thermalDiffusivity,
+          );
+          // if (recording)
+          //   await simSocket.setThermalDiffusivity(
+          //     frame,
+          //     event.data.payload.thermalDiffusivity,
+          //   );
+          break;
+        }
+        case "thermalExpansion": {
+          simWasm?.set_thermal_expansion(event.data.payload.thermalExpansion);
+          // if (recording)
+          //   await simSocket.setThermalExpansion(
+          //     frame,
+          //     event.data.payload.thermalExpansion,
+          //   );
+          break;
+        }
+        case "dyeDiffusivity": {
+          simWasm?.set_dye_diffusivity(event.data.payload.dyeDiffusivity);
+          // if (recording)
+          //   await simSocket.setDyeDiffusivity(
+          //     frame,
+          //     event.data.payload.dyeDiffusivity,
+          //   );
+          break;
+        }
         case "smoothingRadius": {
           simWasm?.set_smoothing_radius(event.data.payload.smoothingRadius);
           // if (recording)
@@ -341,6 +452,39 @@ onmessage = async (event: WorkerMessageEvent) => {
       }
       break;
     }
+    case "SET_SOLVER": {
+      simWasm?.set_solver(event.data.payload.solver);
+      // if (recording)
+      //   await simSocket.setSolver(frame, event.data.payload.solver);
+      break;
+    }
+    case "SET_SUBSTEPPING": {
+      simWasm?.set_substepping(event.data.payload.substepping);
+      // if (recording)
+      //   await simSocket.setSubstepping(
+      //     frame,
+      //     event.data.payload.substepping,
+      //   );
+      break;
+    }
+    case "SET_VISCOSITY_MODEL": {
+      simWasm?.set_viscosity_model(event.data.payload.viscosityModel);
+      // if (recording)
+      //   await simSocket.setViscosityModel(
+      //     frame,
+      //     event.data.payload.viscosityModel,
+      //   );
+      break;
+    }
+    case "SET_DETERMINISTIC": {
+      simWasm?.set_deterministic(event.data.payload.deterministic);
+      // if (recording)
+      //   await simSocket.setDeterministic(
+      //     frame,
+      //     event.data.payload.deterministic,
+      //   );
+      break;
+    }
     case "ENABLE_PUSH_INTERACTION": {
       simWasm?.enable_push_interaction(
         event.data.payload.x,
@@ -372,5 +516,30 @@ onmessage = async (event: WorkerMessageEvent) => {
       // if (recording) await simSocket.disableInteraction(frame);
       break;
     }
+    case "ENABLE_DYE_INTERACTION": {
+      simWasm?.enable_dye_interaction(
+        event.data.payload.x,
+        event.data.payload.y,
+        event.data.payload.channel,
+      );
+      // if (recording)
+      //   await simSocket.enableDyeInteraction(
+      //     frame,
+      //     event.data.payload.x,
+      //     event.data.payload.y,
+      //     event.data.payload.channel,
+      //   );
+      break;
+    }
+    case "DISABLE_DYE_INTERACTION": {
+      simWasm?.disable_dye_interaction();
+      // if (recording) await simSocket.disableDyeInteraction(frame);
+      break;
+    }
+    case "CLEAR_DYES": {
+      simWasm?.clear_dyes();
+      // if (recording) await simSocket.clearDyes(frame);
+      break;
+    }
   }
 };
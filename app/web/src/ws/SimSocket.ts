@@ -1,4 +1,17 @@
-import type { ServerMessage, ClientMessage } from "./protocol";
+import type {
+  ServerMessage,
+  ClientMessage,
+  Emitter,
+  HeatSource,
+  KinematicBody,
+  Obstacle,
+  RigidBody,
+  SimConfig,
+  Sink,
+  SolverKind,
+  Substepping,
+  ViscosityModel,
+} from "./protocol";
 type Listener = (msg: ServerMessage) => void;
 
 export class SimSocket {
@@ -52,35 +65,12 @@ export class SimSocket {
     this.ws.send(JSON.stringify(message));
   }
 
-  startRecording(
-    simulationId: string,
-    particleCount: number,
-    particleRadius: number,
-    worldWidth: number,
-    worldHeight: number,
-    gravity: number,
-    targetDensity: number,
-    pressureMultiplier: number,
-    viscosityStrength: number,
-    smoothingRadius: number,
-    interactionStrength: number,
-    interactionRadius: number,
-  ) {
-    return this.send({
-      type: "startRecording",
-      simulationId,
-      particleCount,
-      particleRadius,
-      worldWidth,
-      worldHeight,
-      gravity,
-      targetDensity,
-      pressureMultiplier,
-      viscosityStrength,
-      smoothingRadius,
-      interactionStrength,
-      interactionRadius,
-    });
+  startRecording(simulationId: string, config: SimConfig) {
+    return this.send({ type: "startRecording", simulationId, config });
+  }
+
+  resumeRecording(simulationId: string) {
+    return this.send({ type: "resumeRecording", simulationId });
   }
 
   stopRecording(frame: number, simulationId: string) {
@@ -111,6 +101,33 @@ export class SimSocket {
       viscosityStrength,
     });
   }
+  setSurfaceTension(frame: number, surfaceTension: number) {
+    return this.send({
+      type: "setSurfaceTension",
+      frame,
+      surfaceTension,
+    });
+  }
+  setVorticityConfinement(frame: number, vorticityConfinement: number) {
+    return this.send({
+      type: "setVorticityConfinement",
+      frame,
+      vorticityConfinement,
+    });
+  }
+  setThermalDiffusivity(frame: number, thermalDiffusivity: number) {
+    return this.send({
+      type: "setThermalDiffusivity",
+      frame,
+      thermalDiffusivity,
+    });
+  }
+  setThermalExpansion(frame: number, thermalExpansion: number) {
+    return this.send({ type: "setThermalExpansion", frame, thermalExpansion });
+  }
+  setDyeDiffusivity(frame: number, dyeDiffusivity: number) {
+    return this.send({ type: "setDyeDiffusivity", frame, dyeDiffusivity });
+  }
   setSmoothingRadius(frame: number, smoothingRadius: number) {
     return this.send({ type: "setSmoothingRadius", frame, smoothingRadius });
   }
@@ -138,6 +155,70 @@ export class SimSocket {
   disableInteraction(frame: number) {
     return this.send({ type: "disableInteraction", frame });
   }
+  enableDyeInteraction(frame: number, x: number, y: number, channel: number) {
+    return this.send({ type: "enableDyeInteraction", frame, x, y, channel });
+  }
+  disableDyeInteraction(frame: number) {
+    return this.send({ type: "disableDyeInteraction", frame });
+  }
+  clearDyes(frame: number) {
+    return this.send({ type: "clearDyes", frame });
+  }
+
+  setSolver(frame: number, solver: SolverKind) {
+    return this.send({ type: "setSolver", frame, solver });
+  }
+  setSubstepping(frame: number, substepping: Substepping) {
+    return this.send({ type: "setSubstepping", frame, substepping });
+  }
+  setViscosityModel(frame: number, viscosityModel: ViscosityModel) {
+    return this.send({ type: "setViscosityModel", frame, viscosityModel });
+  }
+  setDeterministic(frame: number, deterministic: boolean) {
+    return this.send({ type: "setDeterministic", frame, deterministic });
+  }
+
+  addObstacle(frame: number, obstacle: Obstacle) {
+    return this.send({ type: "addObstacle", frame, obstacle });
+  }
+  removeObstacle(frame: number, id: number) {
+    return this.send({ type: "removeObstacle", frame, id });
+  }
+
+  addKinematicBody(frame: number, body: KinematicBody) {
+    return this.send({ type: "addKinematicBody", frame, body });
+  }
+  removeKinematicBody(frame: number, id: number) {
+    return this.send({ type: "removeKinematicBody", frame, id });
+  }
+
+  addRigidBody(frame: number, body: RigidBody) {
+    return this.send({ type: "addRigidBody", frame, body });
+  }
+  removeRigidBody(frame: number, id: number) {
+    return this.send({ type: "removeRigidBody", frame, id });
+  }
+
+  addEmitter(frame: number, emitter: Emitter) {
+    return this.send({ type: "addEmitter", frame, emitter });
+  }
+  removeEmitter(frame: number, id: number) {
+    return this.send({ type: "removeEmitter", frame, id });
+  }
+
+  addSink(frame: number, sink: Sink) {
+    return this.send({ type: "addSink", frame, sink });
+  }
+  removeSink(frame: number, id: number) {
+    return this.send({ type: "removeSink", frame, id });
+  }
+
+  addHeatSource(frame: number, heatSource: HeatSource) {
+    return this.send({ type: "addHeatSource", frame, heatSource });
+  }
+  removeHeatSource(frame: number, id: number) {
+    return this.send({ type: "removeHeatSource", frame, id });
+  }
 
   close() {
     this.ws.close();
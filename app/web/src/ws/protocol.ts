@@ -1,6 +1,7 @@
 export type ServerMessage =
   | { type: "ready" }
   | { type: "recordingStarted"; recordingId: string }
+  | { type: "resumedRecording"; recordingId: string; frame: number }
   | { type: "recordingStopped"; recordingId: string }
   | { type: "error"; message: string }
   | {
@@ -8,30 +9,227 @@ export type ServerMessage =
       recordingId: string;
       frame: number;
       parameterChanged: boolean;
+      substeps?: number;
+      checksum?: string;
     };
 
-export type ClientMessage =
+export type InitialLayout =
+  | { type: "grid" }
+  | { type: "damBreak"; width: number; height: number }
   | {
-      type: "startRecording";
-      simulationId: string;
-      particleCount: number;
-      particleRadius: number;
-      worldWidth: number;
-      worldHeight: number;
-      gravity: number;
-      targetDensity: number;
-      pressureMultiplier: number;
-      viscosityStrength: number;
-      smoothingRadius: number;
-      interactionStrength: number;
-      interactionRadius: number;
+      type: "droplet";
+      x: number;
+      y: number;
+      radius: number;
+      poolDepth: number;
+    }
+  | { type: "twoColumns"; width: number; height: number }
+  | { type: "jitter"; amplitude: number; seed: number }
+  | { type: "poissonDisk"; seed: number }
+  | { type: "mask"; width: number; height: number; data: number[] };
+
+export interface Vector2D {
+  x: number;
+  y: number;
+}
+
+export type Shape =
+  | { type: "circle"; centre: Vector2D; radius: number }
+  | {
+      type: "orientedBox";
+      centre: Vector2D;
+      halfExtents: Vector2D;
+      rotation: number;
     }
+  | { type: "segment"; start: Vector2D; end: Vector2D; thickness: number }
+  | { type: "polygon"; vertices: Vector2D[] };
+
+export interface Obstacle {
+  id?: number;
+  shape: Shape;
+  restitution: number;
+  friction: number;
+}
+
+export interface Keyframe {
+  time: number;
+  position: Vector2D;
+  rotation: number;
+}
+
+export type Motion =
+  | { type: "keyframes"; keyframes: Keyframe[]; looped: boolean }
+  | {
+      type: "oscillator";
+      centre: Vector2D;
+      rotation: number;
+      amplitude: Vector2D;
+      angularAmplitude: number;
+      frequency: number;
+      phase: number;
+      spin: number;
+    };
+
+export interface KinematicBody {
+  id?: number;
+  shape: Shape;
+  motion: Motion;
+  restitution: number;
+  friction: number;
+}
+
+export interface RigidBody {
+  id?: number;
+  shape: Shape;
+  mass: number;
+  inertia?: number | null;
+  position: Vector2D;
+  rotation?: number;
+  velocity?: Vector2D;
+  angularVelocity?: number;
+  restitution: number;
+  friction: number;
+}
+
+export interface Emitter {
+  id?: number;
+  position: Vector2D;
+  velocity: Vector2D;
+  rate: number;
+  width?: number;
+  phase?: number;
+}
+
+export interface Sink {
+  id?: number;
+  shape: Shape;
+}
+
+export interface HeatSource {
+  id?: number;
+  shape: Shape;
+  temperature: number;
+  conductance: number;
+}
+
+export type Material =
+  | { type: "fluid" }
+  | { type: "granular"; frictionAngle: number; cohesion: number };
+
+export interface Phase {
+  targetDensity: number;
+  pressureMultiplier: number;
+  viscosityStrength: number;
+  colour: [number, number, number];
+  material?: Material;
+  region?: Shape | null;
+}
+
+export type BoundarySource =
+  | { type: "polylines"; lines: Vector2D[][]; thickness: number }
+  | { type: "image"; width: number; height: number; data: number[] };
+
+export interface Boundary {
+  source: BoundarySource;
+  cellSize: number;
+  restitution: number;
+  friction: number;
+}
+
+export type SolverKind =
+  | { type: "explicit" }
+  | { type: "positionBased"; iterations: number };
+
+export type SpatialIndexKind = { type: "hashGrid" } | { type: "flatGrid" };
+
+export type KernelKind =
+  | { type: "spiky" }
+  | { type: "poly6" }
+  | { type: "cubicSpline" }
+  | { type: "wendlandC2" }
+  | { type: "wendlandC4" };
+
+export type ViscosityModel =
+  | { type: "diffusion" }
+  | { type: "xsph"; epsilon: number }
+  | { type: "artificial"; alpha: number; beta: number }
+  | { type: "laplacian"; kinematicViscosity: number }
+  | { type: "powerLaw"; consistency: number; flowIndex: number }
+  | {
+      type: "cross";
+      zeroShearViscosity: number;
+      infiniteShearViscosity: number;
+      timeConstant: number;
+      rateIndex: number;
+    }
+  | { type: "bingham"; plasticViscosity: number; yieldStress: number };
+
+export type Substepping =
+  | { type: "fixed"; substeps: number }
+  | {
+      type: "adaptive";
+      velocityCfl: number;
+      forceCfl: number;
+      maxSubsteps: number;
+    };
+
+export interface SimConfig {
+  particleCount: number;
+  maxParticleCount?: number | null;
+  particleRadius: number;
+  worldWidth: number;
+  worldHeight: number;
+  gravity: number;
+  targetDensity: number;
+  pressureMultiplier: number;
+  viscosityStrength: number;
+  viscosityModel?: ViscosityModel;
+  smoothingRadius: number;
+  interactionStrength: number;
+  interactionRadius: number;
+  collisionDamping?: number;
+  interfaceTension?: number;
+  surfaceTension?: number;
+  vorticityConfinement?: number;
+  ambientTemperature?: number;
+  thermalDiffusivity?: number;
+  thermalExpansion?: number;
+  dyeDiffusivity?: number;
+  solver?: SolverKind;
+  substepping?: Substepping;
+  deterministic?: boolean;
+  spatialIndex?: SpatialIndexKind;
+  densityKernel?: KernelKind;
+  viscosityKernel?: KernelKind;
+  layout?: InitialLayout;
+  obstacles?: Obstacle[];
+  boundary?: Boundary | null;
+  kinematicBodies?: KinematicBody[];
+  rigidBodies?: RigidBody[];
+  emitters?: Emitter[];
+  sinks?: Sink[];
+  heatSources?: HeatSource[];
+  phases?: Phase[];
+}
+
+export type ClientMessage =
+  | { type: "startRecording"; simulationId: string; config: SimConfig }
+  | { type: "resumeRecording"; simulationId: string }
   | { type: "stopRecording"; frame: number; simulationId: string }
   | { type: "step"; frame: number; dt: number }
   | { type: "setGravity"; frame: number; gravity: number }
   | { type: "setTargetDensity"; frame: number; targetDensity: number }
   | { type: "setPressureMultiplier"; frame: number; pressureMultiplier: number }
   | { type: "setViscosityStrength"; frame: number; viscosityStrength: number }
+  | { type: "setSurfaceTension"; frame: number; surfaceTension: number }
+  | {
+      type: "setVorticityConfinement";
+      frame: number;
+      vorticityConfinement: number;
+    }
+  | { type: "setThermalDiffusivity"; frame: number; thermalDiffusivity: number }
+  | { type: "setThermalExpansion"; frame: number; thermalExpansion: number }
+  | { type: "setDyeDiffusivity"; frame: number; dyeDiffusivity: number }
   | { type: "setSmoothingRadius"; frame: number; smoothingRadius: number }
   | {
       type: "setInteractionStrength";
@@ -41,4 +239,33 @@ export type ClientMessage =
   | { type: "setInteractionRadius"; frame: number; interactionRadius: number }
   | { type: "enablePushInteraction"; frame: number; x: number; y: number }
   | { type: "enablePullInteraction"; frame: number; x: number; y: number }
-  | { type: "disableInteraction"; frame: number };
+  | { type: "disableInteraction"; frame: number }
+  | {
+      type: "enableDyeInteraction";
+      frame: number;
+      x: number;
+      y: number;
+      channel: number;
+    }
+  | { type: "disableDyeInteraction"; frame: number }
+  | { type: "clearDyes"; frame: number }
+  | { type: "setSolver"; frame: number; solver: SolverKind }
+  | { type: "setSubstepping"; frame: number; substepping: Substepping }
+  | {
+      type: "setViscosityModel";
+      frame: number;
+      viscosityModel: ViscosityModel;
+    }
+  | { type: "setDeterministic"; frame: number; deterministic: boolean }
+  | { type: "addObstacle"; frame: number; obstacle: Obstacle }
+  | { type: "removeObstacle"; frame: number; id: number }
+  | { type: "addKinematicBody"; frame: number; body: KinematicBody }
+  | { type: "removeKinematicBody"; frame: number; id: number }
+  | { type: "addRigidBody"; frame: number; body: RigidBody }
+  | { type: "removeRigidBody"; frame: number; id: number }
+  | { type: "addEmitter"; frame: number; emitter: Emitter }
+  | { type: "removeEmitter"; frame: number; id: number }
+  | { type: "addSink"; frame: number; sink: Sink }
+  | { type: "removeSink"; frame: number; id: number }
+  | { type: "addHeatSource"; frame: number; heatSource: HeatSource }
+  | { type: "removeHeatSource"; frame: number; id: number };
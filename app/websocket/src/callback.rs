@@ -9,6 +9,7 @@ use sim_core::Sim2D;
 
 use crate::{
     protocol::{ClientMessage, ServerMessage},
+    resume::{save_recording, take_recording},
     sqs::send_to_sqs,
 };
 
@@ -29,31 +30,20 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
             Message::Text(text) => match serde_json::from_str::<ClientMessage>(&text) {
                 Ok(ClientMessage::StartRecording {
                     simulation_id,
-                    particle_count,
-                    particle_radius,
-                    world_width,
-                    world_height,
-                    gravity,
-                    target_density,
-                    pressure_multiplier,
-                    viscosity_strength,
-                    smoothing_radius,
-                    interaction_strength,
-                    interaction_radius,
+                    config,
                 }) => {
-                    sim = Some(Sim2D::new(
-                        particle_count,
-                        particle_radius,
-                        world_width,
-                        world_height,
-                        gravity,
-                        target_density,
-                        pressure_multiplier,
-                        viscosity_strength,
-                        smoothing_radius,
-                        interaction_strength,
-                        interaction_radius,
-                    ));
+                    if let Err(error) = config.validate() {
+                        send(
+                            &mut socket,
+                            ServerMessage::Error {
+                                message: format!("Invalid config: {error}"),
+                            },
+                        )
+                        .await;
+                        continue;
+                    }
+
+                    sim = Some(Sim2D::new(*config));
 
                     timestamp = Utc::now();
                     simulation_id_global = simulation_id.clone();
@@ -66,6 +56,34 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                     )
                     .await
                 }
+                Ok(ClientMessage::ResumeRecording { simulation_id }) => {
+                    match take_recording(&session_id, &simulation_id).await {
+                        Some(recording) => {
+                            sim = Some(recording.sim);
+                            current_frame = recording.frame;
+                            timestamp = recording.timestamp;
+                            simulation_id_global = simulation_id;
+
+                            send(
+                                &mut socket,
+                                ServerMessage::ResumedRecording {
+                                    recording_id: 0.to_string(),
+                                    frame: current_frame,
+                                },
+                            )
+                            .await
+                        }
+                        None => {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: "No recording to resume".to_string(),
+                                },
+                            )
+                            .await
+                        }
+                    }
+                }
                 Ok(ClientMessage::StopRecording) => {
                     send(
                         &mut socket,
@@ -88,8 +106,19 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 session_id.clone(),
                                 simulation_id_global.clone(),
                                 frame,
+                                s.get_particle_ids(),
                                 s.get_positions(),
                                 s.get_velocity_magnitudes(),
+                                s.get_vorticities(),
+                                s.get_temperatures(),
+                                s.get_particle_phases(),
+                                s.get_dyes(),
+                                s.phases(),
+                                s.obstacles(),
+                                s.kinematic_bodies(),
+                                s.time(),
+                                s.rigid_bodies(),
+                                s.rigid_body_states(),
                                 s.get_world_width(),
                                 s.get_world_height(),
                                 s.get_particle_radius(),
@@ -104,6 +133,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                         recording_id: 0.to_string(),
                                         frame,
                                         parameter_changed: false,
+                                        substeps: Some(s.substeps()),
+                                        checksum: Some(format!("{:016x}", s.checksum())),
                                     },
                                 )
                                 .await;
@@ -125,6 +156,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -145,6 +178,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -165,6 +200,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -185,6 +222,182 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::SetSurfaceTension {
+                    frame,
+                    surface_tension,
+                }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut()
+                            && let Err(error) = s.set_surface_tension(surface_tension)
+                        {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid surface tension: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::SetVorticityConfinement {
+                    frame,
+                    vorticity_confinement,
+                }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut()
+                            && let Err(error) = s.set_vorticity_confinement(vorticity_confinement)
+                        {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid vorticity confinement: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::SetThermalDiffusivity {
+                    frame,
+                    thermal_diffusivity,
+                }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut()
+                            && let Err(error) = s.set_thermal_diffusivity(thermal_diffusivity)
+                        {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid thermal diffusivity: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::SetThermalExpansion {
+                    frame,
+                    thermal_expansion,
+                }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut()
+                            && let Err(error) = s.set_thermal_expansion(thermal_expansion)
+                        {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid thermal expansion: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::SetDyeDiffusivity {
+                    frame,
+                    dye_diffusivity,
+                }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut()
+                            && let Err(error) = s.set_dye_diffusivity(dye_diffusivity)
+                        {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid dye diffusivity: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::ClearDyes { frame }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.clear_dyes();
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -195,8 +408,17 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                     smoothing_radius,
                 }) => {
                     if current_frame == frame {
-                        if let Some(s) = sim.as_mut() {
-                            s.set_smoothing_radius(smoothing_radius);
+                        if let Some(s) = sim.as_mut()
+                            && let Err(error) = s.set_smoothing_radius(smoothing_radius)
+                        {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid smoothing radius: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
                         }
 
                         send(
@@ -205,6 +427,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -225,6 +449,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -245,6 +471,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -263,6 +491,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -280,6 +510,8 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -297,6 +529,468 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                                 recording_id: 0.to_string(),
                                 frame,
                                 parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::EnableDyeInteraction {
+                    frame,
+                    x,
+                    y,
+                    channel,
+                }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut()
+                            && let Err(error) = s.enable_dye_interaction(x, y, channel)
+                        {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid dye channel: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::DisableDyeInteraction { frame }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.disable_dye_interaction();
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+
+                Ok(ClientMessage::SetSolver { frame, solver }) => {
+                    if current_frame == frame {
+                        if let Err(error) = solver.validate() {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid solver: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        if let Some(s) = sim.as_mut() {
+                            s.set_solver(solver);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::SetSubstepping { frame, substepping }) => {
+                    if current_frame == frame {
+                        if let Err(error) = substepping.validate() {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid substepping: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        if let Some(s) = sim.as_mut() {
+                            s.set_substepping(substepping);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::SetViscosityModel {
+                    frame,
+                    viscosity_model,
+                }) => {
+                    if current_frame == frame {
+                        if let Err(error) = viscosity_model.validate() {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid viscosity model: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        if let Some(s) = sim.as_mut() {
+                            s.set_viscosity_model(viscosity_model);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::SetDeterministic {
+                    frame,
+                    deterministic,
+                }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.set_deterministic(deterministic);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::AddObstacle { frame, obstacle }) => {
+                    if current_frame == frame {
+                        if let Err(error) = obstacle.validate() {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid obstacle: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        if let Some(s) = sim.as_mut() {
+                            s.add_obstacle(obstacle);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::RemoveObstacle { frame, id }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.remove_obstacle(id);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::AddKinematicBody { frame, body }) => {
+                    if current_frame == frame {
+                        if let Err(error) = body.validate() {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid kinematic body: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        if let Some(s) = sim.as_mut() {
+                            s.add_kinematic_body(body);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::RemoveKinematicBody { frame, id }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.remove_kinematic_body(id);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::AddRigidBody { frame, body }) => {
+                    if current_frame == frame {
+                        if let Err(error) = body.validate() {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid rigid body: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        if let Some(s) = sim.as_mut() {
+                            s.add_rigid_body(body);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::RemoveRigidBody { frame, id }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.remove_rigid_body(id);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::AddEmitter { frame, emitter }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut()
+                            && let Err(error) = s.add_emitter(emitter)
+                        {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid emitter: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::RemoveEmitter { frame, id }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.remove_emitter(id);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::AddSink { frame, sink }) => {
+                    if current_frame == frame {
+                        if let Err(error) = sink.validate() {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid sink: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        if let Some(s) = sim.as_mut() {
+                            s.add_sink(sink);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::RemoveSink { frame, id }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.remove_sink(id);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::AddHeatSource { frame, heat_source }) => {
+                    if current_frame == frame {
+                        if let Err(error) = heat_source.validate() {
+                            send(
+                                &mut socket,
+                                ServerMessage::Error {
+                                    message: format!("Invalid heat source: {error}"),
+                                },
+                            )
+                            .await;
+                            continue;
+                        }
+
+                        if let Some(s) = sim.as_mut() {
+                            s.add_heat_source(heat_source);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok(ClientMessage::RemoveHeatSource { frame, id }) => {
+                    if current_frame == frame {
+                        if let Some(s) = sim.as_mut() {
+                            s.remove_heat_source(id);
+                        }
+
+                        send(
+                            &mut socket,
+                            ServerMessage::Acknowledge {
+                                recording_id: 0.to_string(),
+                                frame,
+                                parameter_changed: true,
+                                substeps: None,
+                                checksum: None,
                             },
                         )
                         .await;
@@ -318,7 +1012,7 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
                 let _ = socket.send(Message::Pong(Bytes::from("PONG"))).await;
             }
             Message::Close(_) => {
-                sim = None;
+                break;
             }
             x => {
                 println!("Message type not supported: {x:?}")
@@ -326,6 +1020,20 @@ pub async fn sim_callback(mut socket: WebSocket, peer: SocketAddr, session_id: S
         }
     }
 
+    // Keep an unfinished recording around so the client can resume it
+    if let Some(s) = sim
+        && let Err(error) = save_recording(
+            &session_id,
+            &simulation_id_global,
+            &s,
+            current_frame,
+            timestamp,
+        )
+        .await
+    {
+        println!("Failed to save recording: {error:?}");
+    }
+
     println!("Connection with {peer} closed");
 }
 
@@ -1,5 +1,6 @@
 mod callback;
 mod protocol;
+mod resume;
 mod sqs;
 
 use axum::{
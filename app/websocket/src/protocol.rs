@@ -1,4 +1,14 @@
 use serde::{Deserialize, Serialize};
+use sim_core::{
+    config::SimConfig,
+    flow::{Emitter, Sink},
+    kinematics::KinematicBody,
+    obstacles::Obstacle,
+    rigid_bodies::RigidBody,
+    solver::{SolverKind, Substepping},
+    thermal::HeatSource,
+    viscosity::ViscosityModel,
+};
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
@@ -6,17 +16,11 @@ pub enum ClientMessage {
     #[serde(rename_all = "camelCase")]
     StartRecording {
         simulation_id: String,
-        particle_count: usize,
-        particle_radius: f32,
-        world_width: f32,
-        world_height: f32,
-        gravity: f32,
-        target_density: f32,
-        pressure_multiplier: f32,
-        viscosity_strength: f32,
-        smoothing_radius: f32,
-        interaction_strength: f32,
-        interaction_radius: f32,
+        config: Box<SimConfig>,
+    },
+    #[serde(rename_all = "camelCase")]
+    ResumeRecording {
+        simulation_id: String,
     },
     #[serde(rename_all = "camelCase")]
     StopRecording,
@@ -44,6 +48,34 @@ pub enum ClientMessage {
         viscosity_strength: f32,
     },
     #[serde(rename_all = "camelCase")]
+    SetSurfaceTension {
+        frame: usize,
+        surface_tension: f32,
+    },
+    #[serde(rename_all = "camelCase")]
+    SetVorticityConfinement {
+        frame: usize,
+        vorticity_confinement: f32,
+    },
+    #[serde(rename_all = "camelCase")]
+    SetThermalDiffusivity {
+        frame: usize,
+        thermal_diffusivity: f32,
+    },
+    #[serde(rename_all = "camelCase")]
+    SetThermalExpansion {
+        frame: usize,
+        thermal_expansion: f32,
+    },
+    #[serde(rename_all = "camelCase")]
+    SetDyeDiffusivity {
+        frame: usize,
+        dye_diffusivity: f32,
+    },
+    ClearDyes {
+        frame: usize,
+    },
+    #[serde(rename_all = "camelCase")]
     SetSmoothingRadius {
         frame: usize,
         smoothing_radius: f32,
@@ -71,6 +103,81 @@ pub enum ClientMessage {
     DisableInteraction {
         frame: usize,
     },
+    EnableDyeInteraction {
+        frame: usize,
+        x: f32,
+        y: f32,
+        channel: usize,
+    },
+    DisableDyeInteraction {
+        frame: usize,
+    },
+    SetSolver {
+        frame: usize,
+        solver: SolverKind,
+    },
+    SetSubstepping {
+        frame: usize,
+        substepping: Substepping,
+    },
+    #[serde(rename_all = "camelCase")]
+    SetViscosityModel {
+        frame: usize,
+        viscosity_model: ViscosityModel,
+    },
+    SetDeterministic {
+        frame: usize,
+        deterministic: bool,
+    },
+    AddObstacle {
+        frame: usize,
+        obstacle: Obstacle,
+    },
+    RemoveObstacle {
+        frame: usize,
+        id: u32,
+    },
+    AddKinematicBody {
+        frame: usize,
+        body: KinematicBody,
+    },
+    RemoveKinematicBody {
+        frame: usize,
+        id: u32,
+    },
+    AddRigidBody {
+        frame: usize,
+        body: RigidBody,
+    },
+    RemoveRigidBody {
+        frame: usize,
+        id: u32,
+    },
+    AddEmitter {
+        frame: usize,
+        emitter: Emitter,
+    },
+    RemoveEmitter {
+        frame: usize,
+        id: u32,
+    },
+    AddSink {
+        frame: usize,
+        sink: Sink,
+    },
+    RemoveSink {
+        frame: usize,
+        id: u32,
+    },
+    #[serde(rename_all = "camelCase")]
+    AddHeatSource {
+        frame: usize,
+        heat_source: HeatSource,
+    },
+    RemoveHeatSource {
+        frame: usize,
+        id: u32,
+    },
 }
 
 #[derive(Debug, Serialize)]
@@ -82,6 +189,11 @@ pub enum ServerMessage {
         recording_id: String,
     },
     #[serde(rename_all = "camelCase")]
+    ResumedRecording {
+        recording_id: String,
+        frame: usize,
+    },
+    #[serde(rename_all = "camelCase")]
     StoppedRecording {
         recording_id: String,
     },
@@ -90,6 +202,12 @@ pub enum ServerMessage {
         recording_id: String,
         frame: usize,
         parameter_changed: bool,
+        /// Substeps the acknowledged frame was split into, for steps only.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        substeps: Option<u32>,
+        /// Particle state checksum after a step, as 16 hex digits.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        checksum: Option<String>,
     },
     Error {
         message: String,
@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sim_core::Sim2D;
+use tokio::fs;
+
+const RECORDINGS_DIR: &str = "sim-bins";
+
+#[derive(Serialize, Deserialize)]
+struct RecordingState {
+    frame: usize,
+    timestamp: String,
+}
+
+pub struct ResumedRecording {
+    pub sim: Sim2D,
+    pub frame: usize,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Session and simulation ids come from the client, so keep them out of the path
+fn recording_path(session_id: &str, simulation_id: &str, extension: &str) -> PathBuf {
+    let sanitise = |id: &str| -> String {
+        id.chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect()
+    };
+
+    PathBuf::from(RECORDINGS_DIR).join(format!(
+        "{}_{}.{extension}",
+        sanitise(session_id),
+        sanitise(simulation_id)
+    ))
+}
+
+/// Persists an interrupted recording so it can be picked up again after the
+/// client reconnects. Fails without writing anything if the simulation's
+/// parameters couldn't be restored.
+pub async fn save_recording(
+    session_id: &str,
+    simulation_id: &str,
+    sim: &Sim2D,
+    frame: usize,
+    timestamp: DateTime<Utc>,
+) -> std::io::Result<()> {
+    let snapshot = sim
+        .snapshot()
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+    let state = RecordingState {
+        frame,
+        timestamp: timestamp.to_rfc3339(),
+    };
+
+    fs::write(recording_path(session_id, simulation_id, "bin"), snapshot).await?;
+    fs::write(
+        recording_path(session_id, simulation_id, "json"),
+        serde_json::to_vec(&state)?,
+    )
+    .await
+}
+
+/// Loads and removes a recording saved by [`save_recording`].
+pub async fn take_recording(session_id: &str, simulation_id: &str) -> Option<ResumedRecording> {
+    let snapshot_path = recording_path(session_id, simulation_id, "bin");
+    let state_path = recording_path(session_id, simulation_id, "json");
+
+    let snapshot = fs::read(&snapshot_path).await.ok()?;
+    let state = fs::read(&state_path).await.ok()?;
+
+    let _ = fs::remove_file(&snapshot_path).await;
+    let _ = fs::remove_file(&state_path).await;
+
+    let state: RecordingState = serde_json::from_slice(&state).ok()?;
+    let sim = Sim2D::from_snapshot(&snapshot).ok()?;
+    let timestamp = DateTime::parse_from_rfc3339(&state.timestamp)
+        .ok()?
+        .with_timezone(&Utc);
+
+    Some(ResumedRecording {
+        sim,
+        frame: state.frame,
+        timestamp,
+    })
+}
@@ -3,7 +3,14 @@ use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_sqs::Error;
 use chrono::{DateTime, Utc};
 use serde::Serialize;
-use sim_core::vector_2d::Vector2D;
+use sim_core::{
+    dye::Dye,
+    kinematics::{BodyState, KinematicBody},
+    obstacles::Obstacle,
+    phases::Phase,
+    rigid_bodies::RigidBody,
+    vector_2d::Vector2D,
+};
 use std::env;
 
 #[derive(Serialize)]
@@ -22,8 +29,19 @@ pub async fn send_to_sqs(
     session_id: String,
     simulation_id: String,
     frame: usize,
+    particle_ids: Vec<u32>,
     positions: Vec<Vector2D>,
     velocity_magnitudes: Vec<f32>,
+    vorticities: Vec<f32>,
+    temperatures: Vec<f32>,
+    particle_phases: Vec<u8>,
+    dyes: Vec<Dye>,
+    phases: &[Phase],
+    obstacles: &[Obstacle],
+    kinematic_bodies: &[KinematicBody],
+    time: f32,
+    rigid_bodies: &[RigidBody],
+    rigid_body_states: &[BodyState],
     world_width: f32,
     world_height: f32,
     particle_radius: f32,
@@ -36,10 +54,34 @@ pub async fn send_to_sqs(
 
     let timestamp_string = timestamp.format("%Y-%m-%dT%H-%M-%SZ").to_string();
 
+    // Moving bodies are posed here so the renderer can draw them like obstacles
+    let kinematic_bodies: Vec<_> = kinematic_bodies
+        .iter()
+        .map(|body| serde_json::json!({ "id": body.id, "shape": body.shape_at(time) }))
+        .collect();
+    let rigid_bodies: Vec<_> = rigid_bodies
+        .iter()
+        .zip(rigid_body_states)
+        .map(|(body, state)| {
+            let shape = body.shape.transformed(state.position, state.rotation);
+            serde_json::json!({ "id": body.id, "shape": shape })
+        })
+        .collect();
+
     // Upload to S3
     let data = serde_json::json!({
+        "ids": particle_ids,
         "positions": positions,
-        "velocity_magnitudes": velocity_magnitudes
+        "velocity_magnitudes": velocity_magnitudes,
+        "vorticities": vorticities,
+        "temperatures": temperatures,
+        "phases": particle_phases,
+        "dyes": dyes,
+        "phase_colours": phases.iter().map(|phase| phase.colour).collect::<Vec<_>>(),
+        "phase_materials": phases.iter().map(|phase| phase.material).collect::<Vec<_>>(),
+        "obstacles": obstacles,
+        "kinematic_bodies": kinematic_bodies,
+        "rigid_bodies": rigid_bodies
     })
     .to_string();
 